@@ -1,14 +0,0 @@
-use std::env;
-use std::fs;
-use std::path::PathBuf;
-
-fn main() {
-    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
-
-    // Put the linker script somewhere the linker can find it.
-    fs::write(out_dir.join("linker.ld"), include_bytes!("linker.ld")).unwrap();
-    println!("cargo:rustc-link-search={}", out_dir.display());
-    println!("cargo:rerun-if-changed=linker.ld");
-
-    println!("cargo:rerun-if-changed=build.rs");
-}
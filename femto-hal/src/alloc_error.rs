@@ -0,0 +1,22 @@
+//! Reporting that femto-rt's heap ran out, over the UAT.
+
+use crate::Uat;
+
+/// If the last allocation from the heap failed, print its size in hex, the
+/// way `std` would put it but without `core::fmt`, and return true:
+///
+/// ```text
+/// memory allocation of 0x00000100 bytes failed
+/// ```
+///
+/// Meant for a panic handler, since that's where running out ends up. See
+/// [`femto_rt::heap::failed_alloc`].
+pub fn report_alloc_error(uat: &mut Uat) -> bool {
+    let Some(layout) = femto_rt::heap::failed_alloc() else {
+        return false;
+    };
+    uat.write_bytes(b"memory allocation of 0x");
+    uat.write_hex(layout.size() as u32);
+    uat.write_bytes(b" bytes failed\n");
+    true
+}
@@ -0,0 +1,58 @@
+//! Busy-wait delays.
+//!
+//! There's no timer on the bus, so the only clock the core has is its own
+//! instructions. The delay loop is two instructions, an `addi` and a `bnez`,
+//! and `RiscvFemto` takes 4 cycles for each of them, so every trip around the
+//! loop is exactly [`CYCLES_PER_LOOP`] cycles.
+
+use core::arch::asm;
+
+use embedded_hal::delay::DelayNs;
+
+/// Clock cycles per trip around the delay loop.
+pub const CYCLES_PER_LOOP: u32 = 8;
+
+/// An `embedded-hal` delay for a core running at a known clock frequency.
+#[derive(Debug, Clone, Copy)]
+pub struct Delay {
+    ns_per_loop: u32,
+}
+
+impl Delay {
+    /// A delay for a core clocked at `clk_freq` Hz. For the testbench that's
+    /// [`CLK_FREQ`](crate::CLK_FREQ).
+    pub const fn new(clk_freq: u32) -> Self {
+        let loops_per_sec = clk_freq / CYCLES_PER_LOOP;
+        let loops_per_sec = if loops_per_sec == 0 { 1 } else { loops_per_sec };
+        // Rounding down here means rounding the number of loops up, so a
+        // delay is never shorter than asked for.
+        let ns_per_loop = 1_000_000_000 / loops_per_sec;
+        Self {
+            ns_per_loop: if ns_per_loop == 0 { 1 } else { ns_per_loop },
+        }
+    }
+
+    /// Spin for `loops` trips around the delay loop.
+    #[inline(never)]
+    pub fn spin(loops: u32) {
+        if loops == 0 {
+            return;
+        }
+        // SAFETY: only touches the register it's given.
+        unsafe {
+            asm!(
+                "1:",
+                "addi {0}, {0}, -1",
+                "bnez {0}, 1b",
+                inout(reg) loops => _,
+                options(nomem, nostack),
+            )
+        }
+    }
+}
+
+impl DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        Self::spin(ns.div_ceil(self.ns_per_loop));
+    }
+}
@@ -0,0 +1,99 @@
+//! Driver for the LED register.
+//!
+//! `RiscvFemto_tb` latches the low byte of any write to the LEDs but reads
+//! back 0, so the driver keeps its own copy of what it last wrote. That's
+//! what lets each LED be set on its own without clobbering the others.
+
+use core::cell::Cell;
+use core::convert::Infallible;
+
+use embedded_hal::digital::{ErrorType, OutputPin, StatefulOutputPin};
+
+use crate::register::WriteOnly;
+
+/// The 8 LEDs, as a whole byte or one pin at a time.
+pub struct Leds {
+    reg: WriteOnly<u8>,
+    shadow: Cell<u8>,
+}
+
+impl Leds {
+    /// Take over the LED register, turning all the LEDs off so the shadow
+    /// copy matches the hardware.
+    pub fn new(reg: WriteOnly<u8>) -> Self {
+        reg.write(0);
+        Self {
+            reg,
+            shadow: Cell::new(0),
+        }
+    }
+
+    /// Give the register back.
+    pub fn free(self) -> WriteOnly<u8> {
+        self.reg
+    }
+
+    /// The last value written.
+    pub fn get(&self) -> u8 {
+        self.shadow.get()
+    }
+
+    /// Set all 8 LEDs at once.
+    pub fn set(&self, value: u8) {
+        self.shadow.set(value);
+        self.reg.write(value);
+    }
+
+    /// LED number `n`, from 0 to 7.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is 8 or more.
+    pub fn pin(&self, n: u8) -> Led<'_> {
+        assert!(n < 8, "there are only 8 LEDs");
+        Led {
+            leds: self,
+            mask: 1 << n,
+        }
+    }
+
+    /// All 8 LEDs as separate pins, LED 0 first.
+    pub fn split(&self) -> [Led<'_>; 8] {
+        core::array::from_fn(|n| Led {
+            leds: self,
+            mask: 1 << n,
+        })
+    }
+}
+
+/// One LED, as an `embedded-hal` output pin.
+pub struct Led<'a> {
+    leds: &'a Leds,
+    mask: u8,
+}
+
+impl ErrorType for Led<'_> {
+    type Error = Infallible;
+}
+
+impl OutputPin for Led<'_> {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.leds.set(self.leds.get() & !self.mask);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.leds.set(self.leds.get() | self.mask);
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for Led<'_> {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.leds.get() & self.mask != 0)
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(self.leds.get() & self.mask == 0)
+    }
+}
@@ -0,0 +1,128 @@
+//! Peripheral access for the `RiscvFemto_tb` memory map.
+//!
+//! Anything with address bit 22 set goes to the testbench's peripherals
+//! instead of RAM. The testbench only looks at one address bit for each of
+//! them:
+//!
+//! | Address    | Register     | Access | Contents                          |
+//! |------------|--------------|--------|-----------------------------------|
+//! | `0x40_0004`| LEDs         | write  | the 8 LEDs, from the low byte     |
+//! | `0x40_0008`| UAT data     | write  | the byte to transmit              |
+//! | `0x40_0010`| UAT status   | read   | bit 9 is set when the UAT is ready|
+//!
+//! Get hold of them through [`Peripherals::take`]:
+//!
+//! ```ignore
+//! let p = femto_hal::Peripherals::take().unwrap();
+//! while !p.uat.status.is_set(femto_hal::UatRegisters::READY) {}
+//! p.uat.data.write(b'!');
+//! ```
+//!
+//! The drivers ([`Leds`], [`Uat`] and [`Delay`]) implement the
+//! `embedded-hal`, `embedded-hal-nb` and `embedded-io` traits, so drivers
+//! written against those work here unmodified.
+//!
+//! # Features
+//!
+//! - `panic-uat`: a panic handler that prints the panic's location and
+//!   message over the UAT. The message goes through `core::fmt`, which won't
+//!   fit in the 1 KiB `BRAM`, so it needs a bigger memory layout in release
+//!   builds, like `FEMTO_MEMORY=BRAM:0:8K`.
+//! - `panic-uat-code`: a panic handler that prints only where the panic
+//!   happened, as hex numbers. See [`Uat::write_hex`].
+//! - `heap-bump` and `heap-free-list`: turn on femto-rt's heap of the same
+//!   name, and add [`report_alloc_error`] to say when it ran out. Running
+//!   out panics, and `panic-uat` prints the message, or `panic-uat-code`
+//!   what [`report_alloc_error`] does.
+//! - `paint-stack`: turn on femto-rt's stack painting, and add
+//!   [`print_stack_high_water`] to report how much stack has been used.
+
+#![no_std]
+
+#[cfg(any(feature = "heap-bump", feature = "heap-free-list"))]
+mod alloc_error;
+pub mod delay;
+pub mod leds;
+#[cfg(any(feature = "panic-uat", feature = "panic-uat-code"))]
+mod panic;
+pub mod register;
+#[cfg(feature = "paint-stack")]
+mod stack;
+pub mod uat;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use register::{Field, ReadOnly, WriteOnly};
+
+#[cfg(any(feature = "heap-bump", feature = "heap-free-list"))]
+pub use alloc_error::report_alloc_error;
+pub use delay::Delay;
+pub use leds::{Led, Leds};
+#[cfg(feature = "paint-stack")]
+pub use stack::print_stack_high_water;
+pub use uat::Uat;
+
+/// Clock frequency of the core in `RiscvFemto_tb`, as given to `RiscvUAT`.
+pub const CLK_FREQ: u32 = 500_000_000;
+
+/// Address of the LED register.
+pub const LEDS_ADDR: usize = 0x40_0004;
+/// Address of the UAT data register.
+pub const UAT_DATA_ADDR: usize = 0x40_0008;
+/// Address of the UAT status register.
+pub const UAT_STATUS_ADDR: usize = 0x40_0010;
+
+/// The two registers of the `RiscvUAT` transmitter.
+pub struct UatRegisters {
+    /// Writing here sends the low byte, if [`READY`](Self::READY) is set.
+    /// Otherwise the byte is dropped.
+    pub data: WriteOnly<u8>,
+    /// Read the whole word: the ready flag is above the low byte.
+    pub status: ReadOnly<u32>,
+}
+
+impl UatRegisters {
+    /// Set in [`status`](Self::status) while the transmitter is idle.
+    pub const READY: Field = Field::bit(9);
+}
+
+/// Every peripheral on the bus.
+pub struct Peripherals {
+    /// The LEDs latch the low byte of any write.
+    pub leds: WriteOnly<u8>,
+    pub uat: UatRegisters,
+}
+
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+impl Peripherals {
+    /// Get the peripherals, the first time this is called. Every call after
+    /// that returns `None`.
+    #[inline]
+    pub fn take() -> Option<Self> {
+        // The core has no interrupts, so nothing can get between the load and
+        // the store.
+        if TAKEN.load(Ordering::Relaxed) {
+            return None;
+        }
+        TAKEN.store(true, Ordering::Relaxed);
+        // SAFETY: this is the first and only time `take` gets this far.
+        Some(unsafe { Self::steal() })
+    }
+
+    /// Get the peripherals whether or not they've been taken already.
+    ///
+    /// # Safety
+    ///
+    /// Whatever else holds them must not be using them at the same time.
+    #[inline]
+    pub unsafe fn steal() -> Self {
+        Self {
+            leds: WriteOnly::new(LEDS_ADDR),
+            uat: UatRegisters {
+                data: WriteOnly::new(UAT_DATA_ADDR),
+                status: ReadOnly::new(UAT_STATUS_ADDR),
+            },
+        }
+    }
+}
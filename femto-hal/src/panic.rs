@@ -0,0 +1,71 @@
+//! Panic handlers that report over the UAT, then stop the core with
+//! [`femto_rt::PANIC_EXIT_CODE`].
+//!
+//! With `panic-uat`, a panic prints the same thing `std` would:
+//!
+//! ```text
+//! panicked at src/main.rs:23:5:
+//! explicit panic
+//! ```
+//!
+//! With `panic-uat-code`, it prints the address of the file name in
+//! `.rodata` and the line number, both in hex:
+//!
+//! ```text
+//! panicked at 0x000001c4:0x00000017
+//! ```
+//!
+//! The file name can be read back out of the ELF at that address, e.g. with
+//! `llvm-objdump -s -j .rodata`. That keeps `core::fmt` and the message
+//! strings out of the image entirely. If the heap is on and has just run
+//! out, [`report_alloc_error`](crate::report_alloc_error) goes first.
+
+use core::panic::PanicInfo;
+
+use crate::{Peripherals, Uat};
+
+#[cfg(all(feature = "panic-uat", feature = "panic-uat-code"))]
+compile_error!("only one of the `panic-uat` and `panic-uat-code` features can be enabled");
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    // SAFETY: nothing else is going to run after this, so whoever owns the
+    // UAT won't be using it.
+    let mut uat = Uat::new(unsafe { Peripherals::steal() }.uat);
+    report(&mut uat, info);
+    femto_rt::exit(femto_rt::PANIC_EXIT_CODE)
+}
+
+#[cfg(feature = "panic-uat")]
+fn report(uat: &mut Uat, info: &PanicInfo) {
+    use core::fmt::Write;
+
+    match info.location() {
+        Some(loc) => {
+            let _ = writeln!(
+                uat,
+                "panicked at {}:{}:{}:",
+                loc.file(),
+                loc.line(),
+                loc.column()
+            );
+        }
+        None => uat.write_bytes(b"panicked:\n"),
+    }
+    let _ = writeln!(uat, "{}", info.message());
+}
+
+#[cfg(all(feature = "panic-uat-code", not(feature = "panic-uat")))]
+fn report(uat: &mut Uat, info: &PanicInfo) {
+    #[cfg(any(feature = "heap-bump", feature = "heap-free-list"))]
+    crate::report_alloc_error(uat);
+    uat.write_bytes(b"panicked at 0x");
+    let (file, line) = match info.location() {
+        Some(loc) => (loc.file().as_ptr() as u32, loc.line()),
+        None => (0, 0),
+    };
+    uat.write_hex(file);
+    uat.write_bytes(b":0x");
+    uat.write_hex(line);
+    uat.write_byte(b'\n');
+}
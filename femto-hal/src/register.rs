@@ -0,0 +1,225 @@
+//! Volatile access to memory-mapped registers.
+//!
+//! Each register is a handle holding its address. The handles are only ever
+//! built by [`Peripherals`](crate::Peripherals), so with LTO the address is a
+//! constant and every access compiles down to a single `lb`/`lw`/`sb`/`sw`.
+//!
+//! Writes only need `&self`, so a driver can share a register between several
+//! handles, like the pins of [`Leds`](crate::Leds). The core has no
+//! interrupts, so a [`ReadWrite::modify`] can't be interrupted halfway.
+//!
+//! Which of the three types a register gets depends on what the hardware
+//! actually does with it, not on the width of the access: `RiscvFemto_tb`
+//! latches the LEDs and the UAT data on writes but returns 0 when they're
+//! read, so those are [`WriteOnly`], and the UAT status ignores writes, so
+//! it's [`ReadOnly`].
+
+use core::marker::PhantomData;
+use core::ops::{BitAnd, BitOr, Not, Shl, Shr};
+
+/// An integer type a register can hold.
+pub trait RegisterValue:
+    Copy
+    + Eq
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + Not<Output = Self>
+    + Shl<u32, Output = Self>
+    + Shr<u32, Output = Self>
+    + sealed::Sealed
+{
+    const ZERO: Self;
+    const ONES: Self;
+    const BITS: u32;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! register_value {
+    ($($t:ty),*) => {$(
+        impl sealed::Sealed for $t {}
+        impl RegisterValue for $t {
+            const ZERO: Self = 0;
+            const ONES: Self = !0;
+            const BITS: u32 = <$t>::BITS;
+        }
+    )*};
+}
+
+register_value!(u8, u16, u32);
+
+/// A run of bits within a register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub offset: u32,
+    pub width: u32,
+}
+
+impl Field {
+    pub const fn new(offset: u32, width: u32) -> Self {
+        Self { offset, width }
+    }
+
+    /// A single bit.
+    pub const fn bit(offset: u32) -> Self {
+        Self::new(offset, 1)
+    }
+
+    /// The field's bits, in place.
+    #[inline(always)]
+    pub fn mask<T: RegisterValue>(self) -> T {
+        debug_assert!(self.width > 0 && self.offset + self.width <= T::BITS);
+        (T::ONES >> (T::BITS - self.width)) << self.offset
+    }
+
+    /// Pull the field out of a register value, shifted down to bit 0.
+    #[inline(always)]
+    pub fn get<T: RegisterValue>(self, value: T) -> T {
+        (value & self.mask()) >> self.offset
+    }
+
+    /// Replace the field in a register value. Bits of `field` that don't fit
+    /// are dropped.
+    #[inline(always)]
+    pub fn set<T: RegisterValue>(self, value: T, field: T) -> T {
+        let mask: T = self.mask();
+        (value & !mask) | ((field << self.offset) & mask)
+    }
+}
+
+/// A register that can only be read.
+pub struct ReadOnly<T> {
+    ptr: *const T,
+    _marker: PhantomData<T>,
+}
+
+impl<T: RegisterValue> ReadOnly<T> {
+    /// # Safety
+    ///
+    /// `addr` must be a readable register of type `T`, and nothing else may
+    /// be using it.
+    pub const unsafe fn new(addr: usize) -> Self {
+        Self {
+            ptr: addr as *const T,
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn read(&self) -> T {
+        // SAFETY: `new`'s contract says this is a readable register.
+        unsafe { self.ptr.read_volatile() }
+    }
+
+    #[inline(always)]
+    pub fn read_field(&self, field: Field) -> T {
+        field.get(self.read())
+    }
+
+    /// True if any bit of `field` is set.
+    #[inline(always)]
+    pub fn is_set(&self, field: Field) -> bool {
+        self.read() & field.mask() != T::ZERO
+    }
+}
+
+/// A register that can only be written.
+pub struct WriteOnly<T> {
+    ptr: *mut T,
+    _marker: PhantomData<T>,
+}
+
+impl<T: RegisterValue> WriteOnly<T> {
+    /// # Safety
+    ///
+    /// `addr` must be a writable register of type `T`, and nothing else may
+    /// be using it.
+    pub const unsafe fn new(addr: usize) -> Self {
+        Self {
+            ptr: addr as *mut T,
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn write(&self, value: T) {
+        // SAFETY: `new`'s contract says this is a writable register.
+        unsafe { self.ptr.write_volatile(value) }
+    }
+
+    /// Write `value` into `field`, with every other bit zero. There's no way
+    /// to keep the other bits, since the register can't be read back.
+    #[inline(always)]
+    pub fn write_field(&self, field: Field, value: T) {
+        self.write(field.set(T::ZERO, value));
+    }
+}
+
+/// A register that can be read and written.
+pub struct ReadWrite<T> {
+    ptr: *mut T,
+    _marker: PhantomData<T>,
+}
+
+impl<T: RegisterValue> ReadWrite<T> {
+    /// # Safety
+    ///
+    /// `addr` must be a readable and writable register of type `T`, and
+    /// nothing else may be using it.
+    pub const unsafe fn new(addr: usize) -> Self {
+        Self {
+            ptr: addr as *mut T,
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn read(&self) -> T {
+        // SAFETY: `new`'s contract says this is a readable register.
+        unsafe { self.ptr.read_volatile() }
+    }
+
+    #[inline(always)]
+    pub fn write(&self, value: T) {
+        // SAFETY: `new`'s contract says this is a writable register.
+        unsafe { self.ptr.write_volatile(value) }
+    }
+
+    /// Read the register, pass it through `f`, and write back the result.
+    #[inline(always)]
+    pub fn modify(&self, f: impl FnOnce(T) -> T) {
+        let value = self.read();
+        self.write(f(value));
+    }
+
+    #[inline(always)]
+    pub fn read_field(&self, field: Field) -> T {
+        field.get(self.read())
+    }
+
+    /// True if any bit of `field` is set.
+    #[inline(always)]
+    pub fn is_set(&self, field: Field) -> bool {
+        self.read() & field.mask() != T::ZERO
+    }
+
+    /// Change just `field`, leaving the other bits as they were.
+    #[inline(always)]
+    pub fn write_field(&self, field: Field, value: T) {
+        self.modify(|v| field.set(v, value));
+    }
+
+    /// Set every bit of `field`.
+    #[inline(always)]
+    pub fn set(&self, field: Field) {
+        self.modify(|v| v | field.mask::<T>());
+    }
+
+    /// Clear every bit of `field`.
+    #[inline(always)]
+    pub fn clear(&self, field: Field) {
+        self.modify(|v| v & !field.mask::<T>());
+    }
+}
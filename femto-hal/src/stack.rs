@@ -0,0 +1,21 @@
+//! Reporting the stack high-water mark over the UAT.
+
+use crate::Uat;
+
+/// Print how deep the stack has been so far, against the size reserved for
+/// it, in hex:
+///
+/// ```text
+/// stack: 0x00000030 of 0x00000040 bytes used
+/// ```
+///
+/// More used than reserved means the stack has run into the heap or `.bss`.
+/// See [`femto_rt::stack_high_water`] for how it's measured.
+pub fn print_stack_high_water(uat: &mut Uat) {
+    let used = femto_rt::stack_high_water();
+    uat.write_bytes(b"stack: 0x");
+    uat.write_hex(used as u32);
+    uat.write_bytes(b" of 0x");
+    uat.write_hex(femto_rt::stack_size() as u32);
+    uat.write_bytes(b" bytes used\n");
+}
@@ -0,0 +1,144 @@
+//! Driver for the `RiscvUAT` transmitter.
+//!
+//! The UAT drops any byte written while it's busy, so every write has to wait
+//! for [`UatRegisters::READY`] first. At the testbench's baud rate that's
+//! about 10 cycles of the UAT clock per byte.
+
+use core::convert::Infallible;
+use core::fmt;
+
+use crate::UatRegisters;
+
+/// Blocking transmit-only serial port.
+pub struct Uat {
+    regs: UatRegisters,
+}
+
+impl Uat {
+    pub fn new(regs: UatRegisters) -> Self {
+        Self { regs }
+    }
+
+    /// Give the registers back.
+    pub fn free(self) -> UatRegisters {
+        self.regs
+    }
+
+    /// True if a byte written now would be sent.
+    #[inline]
+    pub fn is_ready(&self) -> bool {
+        self.regs.status.is_set(UatRegisters::READY)
+    }
+
+    /// Wait for the transmitter to go idle, then send `byte`.
+    #[inline(never)]
+    pub fn write_byte(&mut self, byte: u8) {
+        while !self.is_ready() {}
+        self.regs.data.write(byte);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.write_byte(b);
+        }
+    }
+
+    /// Send `value` as 8 hex digits. This is much smaller than going through
+    /// `core::fmt`, and doesn't need a divide, which RV32I doesn't have.
+    pub fn write_hex(&mut self, value: u32) {
+        for shift in (0..32).step_by(4).rev() {
+            let digit = (value >> shift) as u8 & 0xf;
+            self.write_byte(if digit < 10 {
+                b'0' + digit
+            } else {
+                b'a' + digit - 10
+            });
+        }
+    }
+}
+
+impl fmt::Write for Uat {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl embedded_io::ErrorType for Uat {
+    type Error = Infallible;
+}
+
+impl embedded_io::Write for Uat {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    /// Wait until the last byte has gone out on `tx`.
+    fn flush(&mut self) -> Result<(), Infallible> {
+        while !self.is_ready() {}
+        Ok(())
+    }
+}
+
+impl embedded_hal_nb::serial::ErrorType for Uat {
+    type Error = Infallible;
+}
+
+impl embedded_hal_nb::serial::Write<u8> for Uat {
+    fn write(&mut self, word: u8) -> nb::Result<(), Infallible> {
+        if !self.is_ready() {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.regs.data.write(word);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        if !self.is_ready() {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(())
+    }
+}
+
+// Inlined so that plain strings, which `as_str` can see through at compile
+// time, skip `core::fmt` entirely. Formatting even a single integer pulls in a
+// couple of KiB of `core::fmt`, which is more than the whole BRAM.
+#[doc(hidden)]
+#[inline(always)]
+pub fn _print(args: fmt::Arguments) {
+    // SAFETY: the UAT registers hold no state of their own, and `write_byte`
+    // waits for ready, so sharing them with a `Uat` someone else owns can at
+    // worst interleave the output.
+    let mut uat = Uat::new(unsafe { crate::Peripherals::steal() }.uat);
+    match args.as_str() {
+        Some(s) => uat.write_bytes(s.as_bytes()),
+        None => {
+            fmt::write(&mut uat, args).ok();
+        }
+    }
+}
+
+/// Print to the UAT.
+///
+/// This doesn't need the [`Peripherals`](crate::Peripherals): it borrows the
+/// UAT registers for the duration of the call, whoever owns them.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::uat::_print(::core::format_args!($($arg)*))
+    };
+}
+
+/// Print to the UAT, with a newline.
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\n")
+    };
+    ($($arg:tt)*) => {{
+        $crate::print!($($arg)*);
+        $crate::print!("\n");
+    }};
+}
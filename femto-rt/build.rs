@@ -0,0 +1,517 @@
+//! Generates `linker.ld` from the memory layout the application asks for.
+//!
+//! The layout comes from `[package.metadata.femto]` in the application's
+//! `Cargo.toml` (or `[workspace.metadata.femto]`), and each key can be
+//! overridden with a `FEMTO_*` environment variable:
+//!
+//! ```toml
+//! [package.metadata.femto]
+//! stack-size = 64          # FEMTO_STACK_SIZE
+//! heap-size = 0            # FEMTO_HEAP_SIZE, the least the heap may get
+//! reset-address = 0x0000   # FEMTO_RESET_ADDRESS, default: start of `rom`
+//! rom = "BRAM"             # FEMTO_ROM, region for .text/.rodata
+//! ram = "BRAM"             # FEMTO_RAM, region for .data/.bss/stack
+//!
+//! [package.metadata.femto.memory]   # FEMTO_MEMORY="BRAM:0x0000:1K"
+//! BRAM = { origin = 0x0000, length = "1K" }
+//!
+//! [package.metadata.femto.debug]    # on top of the rest, for debug builds
+//! memory = { BRAM = { origin = 0x0000, length = "64K" } }
+//! ```
+//!
+//! Without any of that, the layout is the 1 KiB `BRAM` of `RiscvFemto_tb`.
+//!
+//! `debug` is there because unoptimized code is many times the size of the
+//! BRAM. It's used when cargo's `PROFILE` is `debug`, which covers
+//! `cargo test` as well as plain `cargo build`.
+//!
+//! Cargo doesn't tell a dependency's build script which package is being
+//! built, so the manifest to read comes from `FEMTO_MANIFEST`, which
+//! `.cargo/config.toml` sets to the workspace's `Cargo.toml`:
+//!
+//! ```toml
+//! [env]
+//! FEMTO_MANIFEST = { value = "Cargo.toml", relative = true }
+//! ```
+//!
+//! Without it, the build gets the default layout, with a warning.
+//!
+//! That makes the layout one per workspace, not one per package. Every
+//! package in a build shares the one femto-rt, built once, so there's nowhere
+//! for a package's own `[package.metadata.femto]` to come in; other packages,
+//! like `steps/step10`, get the top-level one whether they have their own or
+//! not. To build one of them with its own layout, point `FEMTO_MANIFEST` at
+//! its `Cargo.toml` by absolute path from the environment, which wins over
+//! `[env]`, or use the `FEMTO_*` variables.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use femto_linker_script::{parse_number as parse_number_str, Region};
+
+/// Variables that override a key of `[package.metadata.femto]`.
+const ENV_KEYS: [(&str, &str); 6] = [
+    ("FEMTO_MEMORY", "memory"),
+    ("FEMTO_ROM", "rom"),
+    ("FEMTO_RAM", "ram"),
+    ("FEMTO_RESET_ADDRESS", "reset-address"),
+    ("FEMTO_STACK_SIZE", "stack-size"),
+    ("FEMTO_HEAP_SIZE", "heap-size"),
+];
+
+/// The psABI wants `sp` 16-byte aligned at every call.
+const STACK_ALIGN: u32 = 16;
+
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=FEMTO_MANIFEST");
+    for (var, _) in ENV_KEYS {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+
+    let manifest = env::var_os("FEMTO_MANIFEST").map(PathBuf::from);
+    match &manifest {
+        Some(manifest) => println!("cargo:rerun-if-changed={}", manifest.display()),
+        None => println!(
+            "cargo:warning=FEMTO_MANIFEST isn't set, so femto-rt is using the default memory layout"
+        ),
+    }
+    let (layout, source) = match load_layout(manifest.as_deref()) {
+        Ok(layout) => layout,
+        Err(errors) => {
+            eprintln!("error: invalid femto-rt memory layout");
+            for e in errors {
+                eprintln!("  - {e}");
+            }
+            process::exit(1);
+        }
+    };
+
+    // Put the linker script somewhere the linker can find it.
+    let heap = env::var_os("CARGO_FEATURE_HEAP_BUMP").is_some()
+        || env::var_os("CARGO_FEATURE_HEAP_FREE_LIST").is_some();
+    fs::write(
+        out_dir.join("linker.ld"),
+        layout.linker_script(&source, heap),
+    )
+    .unwrap();
+    println!("cargo:rustc-link-search={}", out_dir.display());
+}
+
+struct Layout {
+    regions: Vec<Region>,
+    rom: String,
+    ram: String,
+    reset_address: u32,
+    stack_size: u32,
+    heap_size: u32,
+}
+
+/// A value from either the manifest or the environment, remembering which so
+/// errors can say where to look.
+enum Value {
+    Toml(toml::Value),
+    Env(String),
+}
+
+/// Read the layout, and a description of where it came from for the header
+/// of the linker script. Every problem found is returned, not just the first.
+fn load_layout(manifest: Option<&Path>) -> Result<(Layout, String), Vec<String>> {
+    let mut table = toml::Table::new();
+    let mut source = "the default layout".to_string();
+    if let Some(manifest) = manifest {
+        let text = fs::read_to_string(manifest)
+            .map_err(|e| vec![format!("couldn't read {}: {e}", manifest.display())])?;
+        let doc: toml::Table = text
+            .parse()
+            .map_err(|e| vec![format!("couldn't parse {}: {e}", manifest.display())])?;
+        for section in ["workspace", "package"] {
+            let femto = doc
+                .get(section)
+                .and_then(|s| s.get("metadata"))
+                .and_then(|m| m.get("femto"));
+            if let Some(femto) = femto {
+                table = femto
+                    .as_table()
+                    .cloned()
+                    .ok_or_else(|| vec![format!("[{section}.metadata.femto] must be a table")])?;
+                source = format!("[{section}.metadata.femto] in {}", manifest.display());
+            }
+        }
+    }
+
+    let mut errors = Vec::new();
+    let debug = match table.remove("debug") {
+        None => toml::Table::new(),
+        Some(toml::Value::Table(debug)) => debug,
+        Some(_) => {
+            errors.push(format!("`debug` in {source} must be a table"));
+            toml::Table::new()
+        }
+    };
+    for key in table.keys().chain(debug.keys()) {
+        if !ENV_KEYS.iter().any(|(_, k)| k == key) {
+            errors.push(format!("unknown key `{key}` in {source}"));
+        }
+    }
+    if env::var("PROFILE").as_deref() == Ok("debug") && !debug.is_empty() {
+        source = format!("{source}, with `debug`");
+        table.extend(debug);
+    }
+    let mut values: Vec<(&str, Value)> = Vec::new();
+    for (var, key) in ENV_KEYS {
+        if let Ok(value) = env::var(var) {
+            source = format!("{source} and {var}");
+            values.push((key, Value::Env(value)));
+        } else if let Some(value) = table.get(key) {
+            values.push((key, Value::Toml(value.clone())));
+        }
+    }
+    let get = |key: &str| values.iter().find(|(k, _)| *k == key).map(|(_, v)| v);
+    let describe = |key: &str| match get(key) {
+        Some(Value::Env(_)) => {
+            let var = ENV_KEYS.iter().find(|(_, k)| *k == key).unwrap().0;
+            var.to_string()
+        }
+        _ => format!("`{key}`"),
+    };
+    let number = |key: &str, default: u32, errors: &mut Vec<String>| match get(key) {
+        None => Some(default),
+        Some(value) => match parse_number(value) {
+            Some(n) => Some(n),
+            None => {
+                errors.push(format!("{} isn't a valid number", describe(key)));
+                None
+            }
+        },
+    };
+
+    let regions = match get("memory") {
+        None => vec![Region {
+            name: "BRAM".into(),
+            origin: 0,
+            length: 1024,
+        }],
+        Some(value) => match parse_regions(value) {
+            Ok(regions) => regions,
+            Err(e) => {
+                errors.push(format!("{}: {e}", describe("memory")));
+                Vec::new()
+            }
+        },
+    };
+    let stack_size = number("stack-size", 64, &mut errors);
+    let heap_size = number("heap-size", 0, &mut errors);
+
+    let region_name = |key: &str, fallback: &str, errors: &mut Vec<String>| -> Option<String> {
+        let name = match get(key) {
+            Some(Value::Env(s)) => s.clone(),
+            Some(Value::Toml(toml::Value::String(s))) => s.clone(),
+            Some(Value::Toml(_)) => {
+                errors.push(format!("`{key}` must be the name of a memory region"));
+                return None;
+            }
+            // With one region, everything goes in it. Otherwise look for one
+            // with the obvious name.
+            None if regions.len() == 1 => regions[0].name.clone(),
+            None => fallback.to_string(),
+        };
+        if regions.is_empty() {
+            return None;
+        }
+        if !regions.iter().any(|r| r.name == name) {
+            let names: Vec<&str> = regions.iter().map(|r| r.name.as_str()).collect();
+            errors.push(format!(
+                "{} is `{name}`, but the memory regions are {}",
+                match get(key) {
+                    Some(_) => describe(key),
+                    None => format!("`{key}` isn't set, and the default"),
+                },
+                names.join(", ")
+            ));
+            return None;
+        }
+        Some(name)
+    };
+    let rom = region_name("rom", "ROM", &mut errors);
+    let ram = region_name("ram", "RAM", &mut errors);
+
+    for (i, a) in regions.iter().enumerate() {
+        if a.length == 0 {
+            errors.push(format!("memory region {} is empty", a.name));
+        }
+        if a.end() > 1 << 32 {
+            errors.push(format!(
+                "memory region {} runs past the end of the address space",
+                a.name
+            ));
+        }
+        for b in &regions[..i] {
+            if a.name == b.name {
+                errors.push(format!("memory region {} is declared twice", a.name));
+            } else if (a.origin as u64) < b.end() && (b.origin as u64) < a.end() {
+                errors.push(format!(
+                    "memory regions {} ({:#x}..{:#x}) and {} ({:#x}..{:#x}) overlap",
+                    b.name,
+                    b.origin,
+                    b.end(),
+                    a.name,
+                    a.origin,
+                    a.end()
+                ));
+            }
+        }
+    }
+
+    let find = |name: &Option<String>| {
+        name.as_ref()
+            .and_then(|name| regions.iter().find(|r| &r.name == name))
+    };
+    let reset_address = match find(&rom) {
+        Some(rom) => {
+            let reset = number("reset-address", rom.origin, &mut errors);
+            if let Some(reset) = reset {
+                if reset % 4 != 0 {
+                    errors.push(format!(
+                        "{} ({reset:#x}) isn't 4-byte aligned",
+                        describe("reset-address")
+                    ));
+                } else if reset < rom.origin || reset as u64 >= rom.end() {
+                    errors.push(format!(
+                        "{} ({reset:#x}) isn't inside the {} region ({:#x}..{:#x}) that holds the code",
+                        describe("reset-address"),
+                        rom.name,
+                        rom.origin,
+                        rom.end()
+                    ));
+                }
+            }
+            reset
+        }
+        None => None,
+    };
+    if let (Some(ram), Some(stack_size), Some(heap_size)) = (find(&ram), stack_size, heap_size) {
+        if stack_size % STACK_ALIGN != 0 {
+            errors.push(format!(
+                "{} ({stack_size}) isn't a multiple of {STACK_ALIGN}",
+                describe("stack-size")
+            ));
+        }
+        if ram.end() % STACK_ALIGN as u64 != 0 {
+            errors.push(format!(
+                "the stack starts at the end of {} ({:#x}), which isn't {STACK_ALIGN}-byte aligned",
+                ram.name,
+                ram.end()
+            ));
+        }
+        if stack_size as u64 + heap_size as u64 > ram.length as u64 {
+            errors.push(format!(
+                "a {stack_size} byte stack and a {heap_size} byte heap don't fit in {} ({} bytes)",
+                ram.name, ram.length
+            ));
+        }
+    }
+
+    match (rom, ram, reset_address, stack_size, heap_size) {
+        (Some(rom), Some(ram), Some(reset_address), Some(stack_size), Some(heap_size))
+            if errors.is_empty() =>
+        {
+            let layout = Layout {
+                regions,
+                rom,
+                ram,
+                reset_address,
+                stack_size,
+                heap_size,
+            };
+            Ok((layout, source))
+        }
+        _ => Err(errors),
+    }
+}
+
+/// A number, written as a TOML integer or as a string in the same syntax as
+/// a linker script. See [`femto_linker_script::parse_number`].
+fn parse_number(value: &Value) -> Option<u32> {
+    match value {
+        Value::Toml(toml::Value::Integer(n)) => u32::try_from(*n).ok(),
+        Value::Toml(toml::Value::String(s)) | Value::Env(s) => parse_number_str(s),
+        Value::Toml(_) => None,
+    }
+}
+
+/// Regions are a table of `NAME = { origin = .., length = .. }` in the
+/// manifest, or `NAME:ORIGIN:LENGTH[,...]` in `FEMTO_MEMORY`.
+fn parse_regions(value: &Value) -> Result<Vec<Region>, String> {
+    let mut regions = Vec::new();
+    match value {
+        Value::Toml(toml::Value::Table(table)) => {
+            for (name, region) in table {
+                let field = |key: &str| {
+                    let value = region
+                        .get(key)
+                        .ok_or_else(|| format!("region {name} has no `{key}`"))?;
+                    parse_number(&Value::Toml(value.clone()))
+                        .ok_or_else(|| format!("region {name} has an invalid `{key}`"))
+                };
+                if let Some(table) = region.as_table() {
+                    if let Some(key) = table.keys().find(|k| *k != "origin" && *k != "length") {
+                        return Err(format!("region {name} has an unknown key `{key}`"));
+                    }
+                }
+                regions.push(Region {
+                    name: name.clone(),
+                    origin: field("origin")?,
+                    length: field("length")?,
+                });
+            }
+        }
+        Value::Toml(_) => {
+            return Err("must be a table of `NAME = { origin = .., length = .. }`".into())
+        }
+        Value::Env(s) => {
+            for region in s.split(',') {
+                let parts: Vec<&str> = region.split(':').map(str::trim).collect();
+                let [name, origin, length] = parts[..] else {
+                    return Err(format!("expected NAME:ORIGIN:LENGTH, found `{region}`"));
+                };
+                regions.push(Region {
+                    name: name.to_string(),
+                    origin: parse_number_str(origin)
+                        .ok_or_else(|| format!("region {name} has an invalid origin"))?,
+                    length: parse_number_str(length)
+                        .ok_or_else(|| format!("region {name} has an invalid length"))?,
+                });
+            }
+        }
+    }
+    if regions.is_empty() {
+        return Err("no memory regions".into());
+    }
+    for region in &regions {
+        let valid = region
+            .name
+            .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && region
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!("`{}` isn't a valid region name", region.name));
+        }
+    }
+    Ok(regions)
+}
+
+impl Layout {
+    /// The linker script for this layout. `heap` says whether one of the heap
+    /// features is on, in which case the stack can't go below `_heap_end`.
+    fn linker_script(&self, source: &str, heap: bool) -> String {
+        let mut s = String::new();
+        writeln!(s, "/* Generated by femto-rt's build.rs from {source}. */").unwrap();
+        s.push_str("\nMEMORY\n{\n");
+        for r in &self.regions {
+            let attrs = if r.name == self.rom && r.name != self.ram {
+                "RX"
+            } else {
+                "RWX"
+            };
+            writeln!(
+                s,
+                "\t{} ({attrs}) : ORIGIN = {:#010x}, LENGTH = {:#x}",
+                r.name, r.origin, r.length
+            )
+            .unwrap();
+        }
+        s.push_str("}\n");
+        write!(
+            s,
+            r#"
+REGION_ALIAS("REGION_TEXT", {rom});
+REGION_ALIAS("REGION_DATA", {ram});
+
+/* Where the code and the work RAM are, for tools reading the ELF */
+_rom_start = ORIGIN(REGION_TEXT);
+_rom_end = ORIGIN(REGION_TEXT) + LENGTH(REGION_TEXT);
+_ram_start = ORIGIN(REGION_DATA);
+_ram_end = ORIGIN(REGION_DATA) + LENGTH(REGION_DATA);
+
+PROVIDE(_stack_start = ORIGIN(REGION_DATA) + LENGTH(REGION_DATA));
+PROVIDE(_stack_size = {stack_size});
+PROVIDE(_heap_size = {heap_size});
+
+SECTIONS
+{{
+
+  /* Our code, starting at the reset address */
+  .text {reset:#010x} :
+  {{
+    _sinit = ABSOLUTE(.);
+    KEEP(*(.init));
+    _einit = ABSOLUTE(.);
+    KEEP(*(.init.rust));
+    . = ALIGN(4);
+    *(.text .text.*);
+  }}
+  > REGION_TEXT
+
+  .rodata : ALIGN(4)
+  {{
+    *(.rodata .rodata.*);
+    . = ALIGN(4);
+  }} > REGION_TEXT
+
+  .data : ALIGN(4)
+  {{
+    _sidata = LOADADDR(.data);
+    _sdata = .;
+    *(.data .data.*);
+    /* Must be called __global_pointer$ for linker relaxations to work. */
+    PROVIDE(__global_pointer$ = . + 0x800);
+    *(.sdata .sdata.*);
+    . = ALIGN(4);
+    _edata = .;
+  }} > REGION_DATA AT > REGION_TEXT
+
+  .bss (NOLOAD) :
+  {{
+    _sbss = .;
+    *(.sbss .sbss.*);
+    *(.bss .bss.*);
+    . = ALIGN(4);
+    _ebss = .;
+  }} > REGION_DATA
+
+  /* Our stack, with the heap filling the gap below it */
+  .stack (NOLOAD) :
+  {{
+    . = ALIGN(8);
+    _heap_start = .;
+    . = ABSOLUTE(_stack_start);
+  }} > REGION_DATA
+  _heap_end = _stack_start - _stack_size;
+  /* How far the stack can grow before it runs into something */
+  _stack_limit = {stack_limit};
+
+  .eh_frame (INFO) : {{ KEEP(*(.eh_frame)) }}
+  .eh_frame_hdr (INFO) : {{ *(.eh_frame_hdr) }}
+}}
+
+ASSERT(_stack_start - _heap_start >= _stack_size + _heap_size,
+  "{ram} is too small for .data, .bss, a {stack_size} byte stack and a {heap_size} byte heap.");
+"#,
+            rom = self.rom,
+            ram = self.ram,
+            reset = self.reset_address,
+            stack_size = self.stack_size,
+            heap_size = self.heap_size,
+            stack_limit = if heap { "_heap_end" } else { "_heap_start" },
+        )
+        .unwrap();
+        s
+    }
+}
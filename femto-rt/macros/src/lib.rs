@@ -0,0 +1,80 @@
+//! Attribute macros re-exported by `femto-rt`.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Error, ItemFn, ReturnType, Type};
+
+/// Marks the function `_start` jumps to once the runtime is set up.
+///
+/// The function must have the signature `fn() -> !` or `fn()`. It's placed in
+/// the `.init.rust` section right after `_start` and exported as
+/// `_start_rust`. Like `cortex-m-rt`'s `#[entry]`, it's renamed to a hidden
+/// identifier, so it can't be called from anywhere else in the program. If it
+/// returns, the program ends with `femto_rt::exit(0)`.
+#[proc_macro_attribute]
+pub fn entry(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return Error::new(Span::call_site(), "`#[entry]` takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let f = parse_macro_input!(input as ItemFn);
+
+    let valid_signature = f.sig.constness.is_none()
+        && f.sig.asyncness.is_none()
+        && f.sig.unsafety.is_none()
+        && f.sig.abi.is_none()
+        && f.sig.inputs.is_empty()
+        && f.sig.generics.params.is_empty()
+        && f.sig.generics.where_clause.is_none()
+        && f.sig.variadic.is_none()
+        && matches!(f.vis, syn::Visibility::Inherited)
+        && match &f.sig.output {
+            ReturnType::Default => true,
+            ReturnType::Type(_, ty) => matches!(**ty, Type::Never(_)),
+        };
+
+    if !valid_signature {
+        return Error::new(
+            f.sig.span(),
+            "`#[entry]` function must have signature `fn() -> !` or `fn()`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let attrs = f.attrs;
+    let block = f.block;
+    // The name the function was written with goes away, so nothing can call
+    // it.
+    let ident = syn::Ident::new("__femto_rt_start_rust", Span::call_site());
+
+    if let ReturnType::Default = f.sig.output {
+        // Keep the body in its own function so a `return` in it still means
+        // "return from main".
+        return quote!(
+            #(#attrs)*
+            #[doc(hidden)]
+            #[link_section = ".init.rust"]
+            #[export_name = "_start_rust"]
+            pub extern "C" fn #ident() -> ! {
+                #[inline(always)]
+                fn __femto_rt_main() #block
+                __femto_rt_main();
+                ::femto_rt::exit(0)
+            }
+        )
+        .into();
+    }
+
+    quote!(
+        #(#attrs)*
+        #[doc(hidden)]
+        #[link_section = ".init.rust"]
+        #[export_name = "_start_rust"]
+        pub extern "C" fn #ident() -> ! #block
+    )
+    .into()
+}
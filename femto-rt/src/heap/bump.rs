@@ -0,0 +1,89 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+use core::ptr;
+
+use super::{arena, HeapStats};
+
+/// A bump allocator: each allocation goes right after the one before it.
+///
+/// Freeing the most recent allocation gives its memory back, so a `Vec` that
+/// grows while nothing else allocates doesn't leak. Anything else that's
+/// freed stays used until the program ends.
+pub struct Bump {
+    /// Address of the first free byte, or 0 before the first allocation.
+    next: Cell<usize>,
+}
+
+// SAFETY: `RiscvFemto` has a single hart and no interrupts, so nothing can
+// get in between reading `next` and writing it back.
+unsafe impl Sync for Bump {}
+
+impl Bump {
+    pub const fn new() -> Self {
+        Self { next: Cell::new(0) }
+    }
+
+    fn next(&self) -> usize {
+        match self.next.get() {
+            0 => arena().0,
+            next => next,
+        }
+    }
+
+    /// How much of the heap is in use.
+    pub fn stats(&self) -> HeapStats {
+        let (start, end) = arena();
+        let next = self.next();
+        HeapStats {
+            used: next - start,
+            free: end - next,
+        }
+    }
+}
+
+impl Default for Bump {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for Bump {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (_, end) = arena();
+        let start = self.next().next_multiple_of(layout.align());
+        match start.checked_add(layout.size()) {
+            Some(next) if next <= end => {
+                self.next.set(next);
+                start as *mut u8
+            }
+            _ => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if ptr as usize + layout.size() == self.next() {
+            self.next.set(ptr as usize);
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // The most recent allocation can grow or shrink where it is.
+        // If it doesn't fit, the old allocation stays as it was.
+        let (_, end) = arena();
+        if ptr as usize + layout.size() == self.next() {
+            return match (ptr as usize).checked_add(new_size) {
+                Some(next) if next <= end => {
+                    self.next.set(next);
+                    ptr
+                }
+                _ => ptr::null_mut(),
+            };
+        }
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new = self.alloc(new_layout);
+        if !new.is_null() {
+            ptr::copy_nonoverlapping(ptr, new, layout.size().min(new_size));
+        }
+        new
+    }
+}
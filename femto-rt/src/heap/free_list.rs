@@ -0,0 +1,168 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+use core::mem;
+use core::ptr;
+
+use super::{arena, HeapStats};
+
+/// A free block, stored in the block itself.
+struct Hole {
+    size: usize,
+    next: *mut Hole,
+}
+
+/// Everything is handed out in multiples of this, so any free block can hold
+/// a [`Hole`].
+const BLOCK: usize = mem::size_of::<Hole>();
+
+/// A first-fit allocator over a list of free blocks, sorted by address.
+///
+/// Allocating takes the first free block big enough for the request, and
+/// freeing puts the block back, merging it with any free neighbours. The
+/// list lives inside the free memory itself, and `GlobalAlloc` is always told
+/// the size of what it's freeing, so there's no header on allocations.
+/// Sizes are rounded up to 8 bytes.
+pub struct FreeList {
+    /// Address of the first hole, or null when the heap is full.
+    head: Cell<*mut Hole>,
+    /// Bytes handed out, after rounding.
+    used: Cell<usize>,
+    /// Whether the arena has been put on the list yet.
+    ready: Cell<bool>,
+}
+
+// SAFETY: `RiscvFemto` has a single hart and no interrupts, so nothing can
+// get in between reading the list and writing it back.
+unsafe impl Sync for FreeList {}
+
+impl FreeList {
+    pub const fn new() -> Self {
+        Self {
+            head: Cell::new(ptr::null_mut()),
+            used: Cell::new(0),
+            ready: Cell::new(false),
+        }
+    }
+
+    /// The arena, trimmed to whole blocks.
+    fn bounds() -> (usize, usize) {
+        let (start, end) = arena();
+        let start = start.next_multiple_of(BLOCK);
+        (start, (end & !(BLOCK - 1)).max(start))
+    }
+
+    /// Put the whole arena on the list, the first time through.
+    fn init(&self) {
+        if self.ready.replace(true) {
+            return;
+        }
+        let (start, end) = Self::bounds();
+        if end > start {
+            let hole = start as *mut Hole;
+            // SAFETY: the arena is ours and nothing has been handed out yet.
+            unsafe {
+                hole.write(Hole {
+                    size: end - start,
+                    next: ptr::null_mut(),
+                })
+            };
+            self.head.set(hole);
+        }
+    }
+
+    /// How much of the heap is in use.
+    pub fn stats(&self) -> HeapStats {
+        let (start, end) = Self::bounds();
+        let used = self.used.get();
+        HeapStats {
+            used,
+            free: end - start - used,
+        }
+    }
+}
+
+impl Default for FreeList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `layout`'s size as the allocator hands it out.
+fn block_size(layout: &Layout) -> usize {
+    layout.size().max(1).next_multiple_of(BLOCK)
+}
+
+unsafe impl GlobalAlloc for FreeList {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.init();
+        let size = block_size(&layout);
+        let align = layout.align().max(BLOCK);
+
+        // `link` is whatever points at `hole`: the head, or the hole before.
+        let mut link: *mut *mut Hole = self.head.as_ptr();
+        while !(*link).is_null() {
+            let hole = *link;
+            let Hole {
+                size: hole_size,
+                next,
+            } = hole.read();
+            let hole_start = hole as usize;
+            let hole_end = hole_start + hole_size;
+
+            let start = hole_start.next_multiple_of(align);
+            if let Some(end) = start.checked_add(size).filter(|&end| end <= hole_end) {
+                // Whatever is left on either side goes back on the list in
+                // place of the hole. Both are whole blocks, since everything
+                // here is.
+                let mut rest = next;
+                if end < hole_end {
+                    let back = end as *mut Hole;
+                    back.write(Hole {
+                        size: hole_end - end,
+                        next: rest,
+                    });
+                    rest = back;
+                }
+                if start > hole_start {
+                    (*hole).size = start - hole_start;
+                    (*hole).next = rest;
+                    rest = hole;
+                }
+                *link = rest;
+                self.used.set(self.used.get() + size);
+                return start as *mut u8;
+            }
+            link = &raw mut (*hole).next;
+        }
+        ptr::null_mut()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let size = block_size(&layout);
+        let start = ptr as usize;
+        self.used.set(self.used.get() - size);
+
+        // Find the holes either side of the block.
+        let mut prev: *mut Hole = ptr::null_mut();
+        let mut next = self.head.get();
+        while !next.is_null() && (next as usize) < start {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = ptr as *mut Hole;
+        block.write(Hole { size, next });
+        if !next.is_null() && start + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+        if prev.is_null() {
+            self.head.set(block);
+        } else if prev as usize + (*prev).size == start {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! A `#[global_allocator]` for the RAM left over between `.bss` and the stack.
+//!
+//! The linker script puts the heap right after `.bss` and stops it where the
+//! stack reserved by `stack-size` begins, exporting the bounds as
+//! `_heap_start` and `_heap_end`. `heap-size` only sets the least the heap is
+//! allowed to be; whatever else is free in the RAM goes to it too.
+//!
+//! Two allocators are available, picked with a cargo feature:
+//!
+//! - `heap-bump`: [`Bump`] hands out memory from the bottom up and only
+//!   takes back the most recent allocation. Small and fast, for programs
+//!   that allocate once at startup.
+//! - `heap-free-list`: [`FreeList`] keeps a first-fit list of free blocks and
+//!   merges neighbours back together, so memory can be freed in any order.
+//!
+//! Running out of memory returns a null pointer, as `GlobalAlloc` requires,
+//! so `Vec::try_reserve` and friends get to see the error. Anything that
+//! can't handle it goes to `alloc::alloc::handle_alloc_error`, which panics
+//! with `memory allocation of N bytes failed`. The global allocator keeps
+//! the allocation that failed in [`failed_alloc`], so a panic handler too
+//! small for `core::fmt` can still say so.
+
+mod bump;
+mod free_list;
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+
+pub use bump::Bump;
+pub use free_list::FreeList;
+
+#[cfg(all(feature = "heap-bump", feature = "heap-free-list"))]
+compile_error!("only one of the `heap-bump` and `heap-free-list` features can be enabled");
+
+#[cfg(feature = "heap-bump")]
+#[global_allocator]
+static HEAP: Global<Bump> = Global::new(Bump::new());
+
+#[cfg(all(feature = "heap-free-list", not(feature = "heap-bump")))]
+#[global_allocator]
+static HEAP: Global<FreeList> = Global::new(FreeList::new());
+
+/// The global allocator: `heap`, remembering the last allocation it turned
+/// down.
+struct Global<A> {
+    heap: A,
+    failed: Cell<Option<Layout>>,
+}
+
+// SAFETY: as for the allocators themselves, `RiscvFemto` has a single hart
+// and no interrupts.
+unsafe impl<A: Sync> Sync for Global<A> {}
+
+impl<A> Global<A> {
+    const fn new(heap: A) -> Self {
+        Self {
+            heap,
+            failed: Cell::new(None),
+        }
+    }
+
+    /// Remember `layout` if `ptr` is null, or forget the last failure if not.
+    fn record(&self, ptr: *mut u8, layout: Layout) -> *mut u8 {
+        self.failed.set(ptr.is_null().then_some(layout));
+        ptr
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Global<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.record(self.heap.alloc(layout), layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.record(self.heap.alloc_zeroed(layout), layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.heap.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        self.record(self.heap.realloc(ptr, layout, new_size), new_layout)
+    }
+}
+
+/// How much of the heap is in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Bytes handed out and not yet freed, including rounding up to the
+    /// allocator's block size.
+    pub used: usize,
+    /// Bytes still available. With [`FreeList`] these may be split up into
+    /// blocks too small for a given allocation.
+    pub free: usize,
+}
+
+/// How much of the global allocator's heap is in use.
+pub fn heap_stats() -> HeapStats {
+    HEAP.heap.stats()
+}
+
+/// The global allocator's latest allocation, if it failed. A later one that
+/// succeeds clears it, so a panic straight after running out can tell that's
+/// why. A `try_reserve` that fails sets it too.
+pub fn failed_alloc() -> Option<Layout> {
+    HEAP.failed.get()
+}
+
+/// The bytes between `_heap_start` and `_heap_end`.
+fn arena() -> (usize, usize) {
+    extern "C" {
+        static _heap_start: u8;
+        static _heap_end: u8;
+    }
+    (
+        &raw const _heap_start as usize,
+        &raw const _heap_end as usize,
+    )
+}
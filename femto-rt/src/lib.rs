@@ -0,0 +1,195 @@
+//! Minimal runtime for the RiscvFemto core.
+//!
+//! This crate owns everything that has to happen before Rust code can run:
+//! the `_start` routine, the linker script, and the hand-off to the
+//! application's entry point. Applications depend on it and mark a single
+//! `fn() -> !` or `fn()` with [`entry`]:
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! use femto_rt::entry;
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     loop {}
+//! }
+//! ```
+//!
+//! The linker script is placed in the build output directory as `linker.ld`,
+//! so the application still needs `-Tlinker.ld` in its link arguments.
+//!
+//! When the program is done, [`exit`] stops the core with an exit code that
+//! the testbench and `femto-sim` both pick up.
+//!
+//! # Features
+//!
+//! - `heap-bump` and `heap-free-list`: a `#[global_allocator]` over the RAM
+//!   between `.bss` and the stack, so `alloc` can be used. See [`heap`].
+//! - `paint-stack`: fill the free RAM below the stack with [`STACK_PAINT`]
+//!   before `main`, so [`stack_high_water`] can tell how deep it got.
+
+#![no_std]
+
+#[cfg(any(feature = "heap-bump", feature = "heap-free-list"))]
+pub mod heap;
+
+use core::arch::{asm, global_asm};
+
+pub use femto_rt_macros::entry;
+#[cfg(any(feature = "heap-bump", feature = "heap-free-list"))]
+pub use heap::heap_stats;
+
+/// Exit code used after a panic, same as `std`.
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+/// Stop the core, leaving `code` in `a0`.
+///
+/// `RiscvFemto` stops advancing `pc` on any SYSTEM instruction, so the
+/// `ecall` here is the last thing it ever does. `RiscvFemto_tb` and
+/// `femto-sim` both watch for that and end the simulation, reporting `a0` as
+/// the exit code.
+#[inline(always)]
+pub fn exit(code: i32) -> ! {
+    // SAFETY: `ecall` doesn't touch memory, and on a core that does trap it,
+    // the loop keeps us from running off the end.
+    unsafe {
+        asm!(
+            "1: ecall",
+            "j 1b",
+            in("a0") code,
+            options(noreturn, nomem, nostack),
+        )
+    }
+}
+
+/// Stop the core with `ebreak`, leaving 0 in `a0`.
+///
+/// This is the same as `exit(0)` as far as the core is concerned, but shows up
+/// as a breakpoint rather than an exit under a debugger.
+#[inline(always)]
+pub fn halt() -> ! {
+    // SAFETY: as for `exit`.
+    unsafe {
+        asm!(
+            "1: ebreak",
+            "j 1b",
+            in("a0") 0,
+            options(noreturn, nomem, nostack),
+        )
+    }
+}
+
+/// What `paint-stack` fills the unused stack with.
+pub const STACK_PAINT: u32 = 0xdead_beef;
+
+/// The deepest the stack has been so far, in bytes below `_stack_start`.
+///
+/// `_start` paints everything from `_stack_limit` (the end of `.bss`, or of
+/// the heap if there is one) up to `_stack_start` with [`STACK_PAINT`], and
+/// this looks for the lowest word that's been overwritten since. It can only
+/// see as far as `_stack_limit`, so a stack that went past that into `.bss`
+/// shows up as using all of the room there was.
+#[cfg(feature = "paint-stack")]
+pub fn stack_high_water() -> usize {
+    extern "C" {
+        static _stack_limit: u32;
+        static _stack_start: u32;
+    }
+    let start = &raw const _stack_start as usize;
+    let mut word = &raw const _stack_limit;
+    // SAFETY: everything between `_stack_limit` and `_stack_start` is RAM that
+    // `_start` painted, and reading it can't hurt whatever's using it now.
+    while (word as usize) < start && unsafe { word.read_volatile() } == STACK_PAINT {
+        word = word.wrapping_add(1);
+    }
+    start - word as usize
+}
+
+/// The stack size the linker script reserved, `stack-size` in
+/// `[package.metadata.femto]`.
+pub fn stack_size() -> usize {
+    extern "C" {
+        static _stack_size: u8;
+    }
+    &raw const _stack_size as usize
+}
+
+// Set up the global pointer and stack, copy `.data` into place from its load
+// image in ROM, zero `.bss`, paint the stack if asked to, clear out the
+// remaining registers, and jump to the function marked with `#[entry]`.
+global_asm!(r#"
+    .section .init, "ax"
+    .global _start
+_start:
+    .option push
+    .option norelax
+    la gp, __global_pointer$
+    .option pop
+    la sp, _stack_start
+    mv fp, sp
+    la t0, _sidata
+    la t1, _sdata
+    la t2, _edata
+    // With everything in one RAM, .data is already where it belongs.
+    beq t0, t1, 101f
+    beq t1, t2, 101f
+100: // loop for data
+    lw t3, 0(t0)
+    sw t3, 0(t1)
+    addi t0, t0, 4
+    addi t1, t1, 4
+    bne t1, t2, 100b
+101: // end of loop for data
+    la t1, _sbss
+    la t2, _ebss
+    beq t1, t2, 201f
+200: // loop for bss
+    sw zero, 0(t1)
+    addi t1, t1, 4
+    bne t1, t2, 200b
+201: // end of loop for bss
+.if {paint_stack}
+    la t1, _stack_limit
+    li t3, {paint}
+    bgeu t1, sp, 301f
+300: // loop to paint the stack
+    sw t3, 0(t1)
+    addi t1, t1, 4
+    bltu t1, sp, 300b
+301: // end of loop to paint the stack
+.endif
+
+    li tp, 0
+    li t0, 0
+    li t1, 0
+    li t2, 0
+    li t3, 0
+    li t4, 0
+    li t5, 0
+    li t6, 0
+    li s1, 0
+    li s2, 0
+    li s3, 0
+    li s4, 0
+    li s5, 0
+    li s6, 0
+    li s7, 0
+    li s8, 0
+    li s9, 0
+    li s10, 0
+    li s11, 0
+    li a0, 0
+    li a1, 0
+    li a2, 0
+    li a3, 0
+    li a4, 0
+    li a5, 0
+    li a6, 0
+    li a7, 0
+    jal _start_rust
+"#,
+    paint_stack = const cfg!(feature = "paint-stack") as u32,
+    paint = const STACK_PAINT as i32,
+);
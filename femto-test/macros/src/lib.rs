@@ -0,0 +1,166 @@
+//! Attribute macros re-exported by `femto-test`.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Error, Item, ItemFn, ItemMod, LitStr, Meta,
+    ReturnType,
+};
+
+/// Marks the module holding a test binary's tests.
+///
+/// Functions in it marked `#[test]` are collected, in order, and the
+/// program's entry point is generated to run them with `femto_test::run`.
+/// `#[should_panic]` and `#[ignore]` work as they do with the standard
+/// harness, except that `#[should_panic]` can't check the message, since
+/// the harness never sees it.
+///
+/// Test functions must have the signature `fn()`. Anything else in the module
+/// is left as it is.
+#[proc_macro_attribute]
+pub fn tests(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return Error::new(
+            Span::call_site(),
+            "`#[femto_test::tests]` takes no arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let mut module = parse_macro_input!(input as ItemMod);
+    match collect(&mut module) {
+        Ok(tests) => expand(module, tests),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct TestFn {
+    ident: syn::Ident,
+    should_panic: bool,
+    ignore: bool,
+}
+
+/// Take the test attributes off the functions in `module`, and return the
+/// tests they marked.
+fn collect(module: &mut ItemMod) -> Result<Vec<TestFn>, Error> {
+    let Some((_, items)) = &mut module.content else {
+        return Err(Error::new(
+            module.span(),
+            "`#[femto_test::tests]` needs the tests inline, as `mod tests { ... }`",
+        ));
+    };
+
+    let mut tests = Vec::new();
+    for item in items {
+        let Item::Fn(f) = item else { continue };
+        let test = take_attr(&mut f.attrs, "test")?;
+        let should_panic = take_attr(&mut f.attrs, "should_panic")?;
+        let ignore = take_attr(&mut f.attrs, "ignore")?;
+        if test.is_none() {
+            if let Some(attr) = should_panic.or(ignore) {
+                return Err(Error::new(
+                    attr.span(),
+                    "only a `#[test]` function can have this",
+                ));
+            }
+            continue;
+        }
+        if let Some(attr) = &should_panic {
+            if !matches!(attr.meta, Meta::Path(_)) {
+                return Err(Error::new(
+                    attr.span(),
+                    "the harness never sees the panic message, so `expected` can't be checked",
+                ));
+            }
+        }
+        check_signature(f)?;
+        tests.push(TestFn {
+            ident: f.sig.ident.clone(),
+            should_panic: should_panic.is_some(),
+            ignore: ignore.is_some(),
+        });
+    }
+    Ok(tests)
+}
+
+/// Remove the attribute called `name` from `attrs`, if it's there.
+fn take_attr(attrs: &mut Vec<Attribute>, name: &str) -> Result<Option<Attribute>, Error> {
+    let mut found = None;
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path().is_ident(name) {
+            return true;
+        }
+        if found.is_some() {
+            result = Err(Error::new(attr.span(), format!("duplicate `#[{name}]`")));
+        }
+        found = Some(attr.clone());
+        false
+    });
+    result.map(|_| found)
+}
+
+fn check_signature(f: &ItemFn) -> Result<(), Error> {
+    let valid = f.sig.constness.is_none()
+        && f.sig.asyncness.is_none()
+        && f.sig.unsafety.is_none()
+        && f.sig.abi.is_none()
+        && f.sig.inputs.is_empty()
+        && f.sig.generics.params.is_empty()
+        && f.sig.generics.where_clause.is_none()
+        && f.sig.variadic.is_none()
+        && matches!(f.sig.output, ReturnType::Default);
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::new(
+            f.sig.span(),
+            "`#[test]` function must have signature `fn()`",
+        ))
+    }
+}
+
+fn expand(module: ItemMod, tests: Vec<TestFn>) -> TokenStream {
+    let ItemMod {
+        attrs,
+        vis,
+        unsafety,
+        mod_token,
+        ident,
+        content,
+        ..
+    } = module;
+    let items = content.map(|(_, items)| items).unwrap_or_default();
+
+    let count = tests.len();
+    let entries = tests.iter().map(|test| {
+        let name = LitStr::new(&format!("{ident}::{}", test.ident), test.ident.span());
+        let run = &test.ident;
+        let should_panic = test.should_panic;
+        let ignore = test.ignore;
+        quote!(::femto_test::Test {
+            name: #name,
+            run: #run,
+            should_panic: #should_panic,
+            ignore: #ignore,
+        })
+    });
+
+    quote!(
+        #(#attrs)*
+        #vis #unsafety #mod_token #ident {
+            #(#items)*
+
+            #[doc(hidden)]
+            pub(super) static __FEMTO_TESTS: [::femto_test::Test; #count] = [#(#entries),*];
+        }
+
+        #[::femto_test::__entry]
+        fn __femto_test_main() -> ! {
+            ::femto_test::run(&#ident::__FEMTO_TESTS)
+        }
+    )
+    .into()
+}
@@ -0,0 +1,333 @@
+//! A test harness that runs on the RiscvFemto core.
+//!
+//! The standard test harness needs `std`, so tests of code that only runs on
+//! the target go in a `#[femto_test::tests]` module instead, in a test target
+//! with `harness = false`:
+//!
+//! ```toml
+//! [[test]]
+//! name = "hal"
+//! harness = false
+//! ```
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! #[femto_test::tests]
+//! mod tests {
+//!     #[test]
+//!     fn adds() {
+//!         assert_eq!(core::hint::black_box(1) + 1, 2);
+//!     }
+//!
+//!     #[test]
+//!     #[should_panic]
+//!     fn overflows() {
+//!         let _ = core::hint::black_box(u32::MAX) + 1;
+//!     }
+//! }
+//! ```
+//!
+//! `cargo test` then builds it and runs it on `femto-sim`, the cargo runner.
+//!
+//! Before each test, the harness zeroes `.bss` and copies `.data` back out
+//! of ROM (if it's copied at all) the same way `_start` does, so every test
+//! starts with statics as they were at reset and peripherals nobody has
+//! taken yet. A panic fails the test, and the harness carries on with the
+//! next one from where it called the test, using the registers it saved
+//! before the call. Once they've all run, the core stops with exit code 0 if
+//! they all passed, or [`PANIC_EXIT_CODE`](femto_rt::PANIC_EXIT_CODE) if any
+//! failed.
+//!
+//! The harness reports over the UAT, a line at a time, which `femto-sim`
+//! turns into the usual `cargo test` output:
+//!
+//! ```text
+//! femto-test: start 3
+//! femto-test: run tests::adds
+//! femto-test: ok tests::adds
+//! femto-test: run tests::fails
+//! femto-test: fail tests::fails panicked at tests/hal.rs:20:9
+//! femto-test: ignored tests::slow
+//! femto-test: done 1 1 1
+//! ```
+//!
+//! `start` has the number of tests, and `done` the number that passed,
+//! failed and were ignored. A `#[should_panic]` test that returns fails with
+//! `did not panic` in place of the location. Anything the tests print goes
+//! out between the `run` and the result.
+//!
+//! The panic message isn't sent, just where it came from. Formatting it would
+//! pull in `core::fmt`, which is bigger than the whole 1 KiB `BRAM`.
+//!
+//! This crate has the `#[panic_handler]`, so the `panic-uat` features of
+//! `femto-hal` can't be on in a test build.
+
+#![no_std]
+
+use core::arch::global_asm;
+use core::panic::PanicInfo;
+
+use femto_hal::{Peripherals, Uat};
+
+pub use femto_test_macros::tests;
+
+#[doc(hidden)]
+pub use femto_rt::entry as __entry;
+
+/// Starts every line the harness sends.
+pub const PREFIX: &str = "femto-test: ";
+
+/// A test, as collected by [`tests`].
+pub struct Test {
+    /// The path of the test function within the crate, like `tests::adds`.
+    pub name: &'static str,
+    pub run: fn(),
+    /// Passes if it panics, and fails if it doesn't.
+    pub should_panic: bool,
+    /// Reported as ignored without being run.
+    pub ignore: bool,
+}
+
+/// What the panic handler needs to get back to the harness.
+struct State {
+    /// The test that's running, if one is.
+    test: Option<&'static Test>,
+    /// `ra`, `sp` and `s0` to `s11` where the harness called the test.
+    registers: [u32; 14],
+}
+
+// Exported under a fixed name so `femto-sim` can tell a test binary from any
+// other program. It's in `.bss`, so it's zeroed along with everything else
+// before each test, and filled in again after.
+#[export_name = "__femto_test_state"]
+static mut STATE: State = State {
+    test: None,
+    registers: [0; 14],
+};
+
+extern "C" {
+    /// Put `.data` and `.bss` back the way `_start` left them.
+    fn __femto_test_reset();
+    /// Save the registers a call has to keep in `registers`, then run the
+    /// test in `STATE`. Returns 0 if it returns, or 1 if the panic handler
+    /// comes back through `__femto_test_resume`.
+    fn __femto_test_call(registers: *mut [u32; 14]) -> u32;
+    /// Return 1 from the `__femto_test_call` that saved `registers`.
+    fn __femto_test_resume(registers: *const [u32; 14]) -> !;
+}
+
+extern "C" fn run_test() {
+    let state = &raw const STATE;
+    // SAFETY: the harness has set the test and let go of the state.
+    if let Some(test) = unsafe { (*state).test } {
+        (test.run)()
+    }
+}
+
+global_asm!(r#"
+    .section .text.__femto_test_reset, "ax"
+    .global __femto_test_reset
+__femto_test_reset:
+    la t0, _sidata
+    la t1, _sdata
+    la t2, _edata
+    beq t0, t1, 101f
+    beq t1, t2, 101f
+100: // loop for data
+    lw t3, 0(t0)
+    sw t3, 0(t1)
+    addi t0, t0, 4
+    addi t1, t1, 4
+    bne t1, t2, 100b
+101: // end of loop for data
+    la t1, _sbss
+    la t2, _ebss
+    beq t1, t2, 201f
+200: // loop for bss
+    sw zero, 0(t1)
+    addi t1, t1, 4
+    bne t1, t2, 200b
+201: // end of loop for bss
+    ret
+
+    .section .text.__femto_test_call, "ax"
+    .global __femto_test_call
+__femto_test_call:
+    sw ra, 0(a0)
+    sw sp, 4(a0)
+    sw s0, 8(a0)
+    sw s1, 12(a0)
+    sw s2, 16(a0)
+    sw s3, 20(a0)
+    sw s4, 24(a0)
+    sw s5, 28(a0)
+    sw s6, 32(a0)
+    sw s7, 36(a0)
+    sw s8, 40(a0)
+    sw s9, 44(a0)
+    sw s10, 48(a0)
+    sw s11, 52(a0)
+    // s0 is kept by the call, and the caller's is saved.
+    mv s0, a0
+    call {run_test}
+    lw ra, 0(s0)
+    lw s0, 8(s0)
+    li a0, 0
+    ret
+
+    .section .text.__femto_test_resume, "ax"
+    .global __femto_test_resume
+__femto_test_resume:
+    lw ra, 0(a0)
+    lw sp, 4(a0)
+    lw s0, 8(a0)
+    lw s1, 12(a0)
+    lw s2, 16(a0)
+    lw s3, 20(a0)
+    lw s4, 24(a0)
+    lw s5, 28(a0)
+    lw s6, 32(a0)
+    lw s7, 36(a0)
+    lw s8, 40(a0)
+    lw s9, 44(a0)
+    lw s10, 48(a0)
+    lw s11, 52(a0)
+    li a0, 1
+    ret
+"#,
+    run_test = sym run_test,
+);
+
+/// Run `test` from a fresh `.data` and `.bss`, and tell whether it panicked.
+fn run_one(test: &'static Test) -> bool {
+    let state = &raw mut STATE;
+    // SAFETY: nothing the harness is using lives in `.data` or `.bss`, and
+    // `STATE` is written again once they're reset. Only the harness and its
+    // panic handler use `STATE`, and never at the same time.
+    unsafe {
+        __femto_test_reset();
+        (*state).test = Some(test);
+        let panicked = __femto_test_call(&raw mut (*state).registers) != 0;
+        (*state).test = None;
+        panicked
+    }
+}
+
+/// Run `tests`, one at a time, and stop the core when they're done.
+///
+/// This is what [`tests`] calls from the program's entry point.
+pub fn run(tests: &'static [Test]) -> ! {
+    let (mut passed, mut failed, mut ignored) = (0, 0, 0);
+    report(&[b"start"], &[tests.len() as u32]);
+
+    for test in tests {
+        if test.ignore {
+            ignored += 1;
+            report(&[b"ignored ", test.name.as_bytes()], &[]);
+            continue;
+        }
+        report(&[b"run ", test.name.as_bytes()], &[]);
+        match (run_one(test), test.should_panic) {
+            (false, false) | (true, true) => {
+                passed += 1;
+                report(&[b"ok ", test.name.as_bytes()], &[]);
+            }
+            (false, true) => {
+                failed += 1;
+                report(&[b"fail ", test.name.as_bytes(), b" did not panic"], &[]);
+            }
+            // The panic handler has already said where.
+            (true, false) => failed += 1,
+        }
+    }
+
+    report(&[b"done"], &[passed, failed, ignored]);
+    femto_rt::exit(if failed == 0 {
+        0
+    } else {
+        femto_rt::PANIC_EXIT_CODE
+    })
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let state = &raw mut STATE;
+    // SAFETY: the harness has let go of the state while the test runs.
+    let Some(test) = (unsafe { (*state).test.take() }) else {
+        // Not in a test. The harness itself shouldn't panic, but just in case.
+        report(&[b"panicked"], &[]);
+        femto_rt::exit(femto_rt::PANIC_EXIT_CODE)
+    };
+    if !test.should_panic {
+        let mut uat = uat();
+        uat.write_bytes(PREFIX.as_bytes());
+        uat.write_bytes(b"fail ");
+        uat.write_bytes(test.name.as_bytes());
+        uat.write_bytes(b" panicked");
+        if let Some(location) = info.location() {
+            uat.write_bytes(b" at ");
+            uat.write_bytes(location.file().as_bytes());
+            uat.write_byte(b':');
+            write_decimal(&mut uat, location.line());
+            uat.write_byte(b':');
+            write_decimal(&mut uat, location.column());
+        }
+        uat.write_byte(b'\n');
+    }
+    // SAFETY: the registers were saved by the call to the test that's just
+    // panicked, and that call's caller is still waiting for it to return.
+    unsafe { __femto_test_resume(&raw const (*state).registers) }
+}
+
+fn uat() -> Uat {
+    // SAFETY: whatever the test did with the UAT, it's done with it now, and
+    // `write_byte` waits for ready, so the worst that can happen is output
+    // from the two getting mixed up.
+    Uat::new(unsafe { Peripherals::steal() }.uat)
+}
+
+/// Send a line: the prefix, `parts`, and then each of `numbers` after a
+/// space.
+fn report(parts: &[&[u8]], numbers: &[u32]) {
+    let mut uat = uat();
+    uat.write_bytes(PREFIX.as_bytes());
+    for part in parts {
+        uat.write_bytes(part);
+    }
+    for &number in numbers {
+        uat.write_byte(b' ');
+        write_decimal(&mut uat, number);
+    }
+    uat.write_byte(b'\n');
+}
+
+/// Send `value` in decimal, by subtracting powers of ten, since RV32I has no
+/// divide and the library routine for it is bigger than this.
+fn write_decimal(uat: &mut Uat, mut value: u32) {
+    static POWERS: [u32; 10] = [
+        1_000_000_000,
+        100_000_000,
+        10_000_000,
+        1_000_000,
+        100_000,
+        10_000,
+        1_000,
+        100,
+        10,
+        1,
+    ];
+    let mut started = false;
+    for &power in &POWERS {
+        let mut digit = 0;
+        while value >= power {
+            value -= power;
+            digit += 1;
+        }
+        if digit > 0 || started || power == 1 {
+            uat.write_byte(b'0' + digit);
+            started = true;
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Links each step with the linker script it had in the README.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Each binary, and the script it's linked with. A step that didn't change
+/// the script uses the one from the step that last did.
+const SCRIPTS: &[(&str, Option<&str>)] = &[
+    ("step2", None),
+    ("step3", Some("step3.ld")),
+    ("step6", Some("step6.ld")),
+    ("step7", Some("step6.ld")),
+    ("step8", Some("step8.ld")),
+    ("step9", Some("step8.ld")),
+];
+
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+
+    // `.cargo/config.toml` links everything with `-Tlinker.ld`, which is
+    // meant for femto-rt's script. These steps come before femto-rt, so give
+    // that an empty script to find, and add each step's own after it.
+    fs::write(out_dir.join("linker.ld"), "").unwrap();
+    println!("cargo:rustc-link-search={}", out_dir.display());
+
+    for (bin, script) in SCRIPTS {
+        if let Some(script) = script {
+            let path = dir.join(script);
+            println!("cargo:rustc-link-arg-bin={bin}=-T{}", path.display());
+            println!("cargo:rerun-if-changed={script}");
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,19 @@
+//! Step 10: Packaging the Runtime
+//!
+//! Everything from the steps before is in femto-rt now, and this is all
+//! that's left of the program.
+
+#![no_std]
+#![no_main]
+
+use femto_rt::entry;
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+#[entry]
+fn main() -> ! {
+    loop {}
+}
@@ -0,0 +1,18 @@
+//! Step 2: Make Compilation Succeed
+//!
+//! This builds, but there's no linker script yet, so nothing tells the linker
+//! `start` is where the program begins, and the ELF comes out with no code in
+//! it at all.
+
+#![no_std]
+#![no_main]
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+#[no_mangle]
+pub extern "C" fn start() -> ! {
+    panic!()
+}
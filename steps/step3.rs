@@ -0,0 +1,20 @@
+//! Step 3: Adding a Linker Script
+//!
+//! `step3.ld` puts `_start` first in `BRAM`, so now there's code. It also
+//! runs for steps 4 and 5, which load it onto the core and shrink it. There's
+//! no stack yet, so the first thing the panic handler saves lands up in the
+//! I/O registers.
+
+#![no_std]
+#![no_main]
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+#[link_section = ".init"]
+#[export_name = "_start"]
+pub extern "C" fn start() -> ! {
+    panic!()
+}
@@ -0,0 +1,66 @@
+//! Step 8: More linker script sections - `.rodata`, `.data`, `.bss`
+//!
+//! The program now has global variables, and `step8.ld` has somewhere to put
+//! them. Nothing sets them up yet: `gp` isn't loaded, `.data` isn't copied and
+//! `.bss` isn't cleared, so they only hold the right values because the image
+//! was loaded with them in place.
+
+#![no_std]
+#![no_main]
+
+// `#[used]` keeps these in the ELF, since nothing reads them yet.
+#[used]
+static mut UAT_VAL: u8 = 5;
+#[used]
+static mut UAT_STAT: u16 = 0;
+
+use core::arch::global_asm;
+
+global_asm!(
+    r#"
+    .section .init, "ax"
+    .global _start
+_start:
+    la sp, _stack_start
+    mv fp, sp
+    li tp, 0
+    li t0, 0
+    li t1, 0
+    li t2, 0
+    li t3, 0
+    li t4, 0
+    li t5, 0
+    li t6, 0
+    li s1, 0
+    li s2, 0
+    li s3, 0
+    li s4, 0
+    li s5, 0
+    li s6, 0
+    li s7, 0
+    li s8, 0
+    li s9, 0
+    li s10, 0
+    li s11, 0
+    li a0, 0
+    li a1, 0
+    li a2, 0
+    li a3, 0
+    li a4, 0
+    li a5, 0
+    li a6, 0
+    li a7, 0
+    jal _start_rust
+"#
+);
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+#[link_section = ".init.rust"]
+#[export_name = "_start_rust"]
+pub extern "C" fn start() -> ! {
+    panic!()
+}
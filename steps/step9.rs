@@ -1,12 +1,21 @@
+//! Step 9: Global Variable Setup
+//!
+//! `_start` now points `gp` into the small data, copies `.data` into place
+//! and clears `.bss`, and that's a whole runtime.
+
 #![no_std]
 #![no_main]
 
+// `#[used]` keeps these in the ELF, since nothing reads them yet.
+#[used]
 static mut UAT_VAL: u8 = 5;
+#[used]
 static mut UAT_STAT: u16 = 0;
 
 use core::arch::global_asm;
 
-global_asm!(r#"
+global_asm!(
+    r#"
     .section .init, "ax"
     .global _start
 _start:
@@ -64,7 +73,8 @@ _start:
     li a6, 0
     li a7, 0
     jal _start_rust
-"#);
+"#
+);
 
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
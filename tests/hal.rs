@@ -0,0 +1,57 @@
+//! femto-hal on the core itself. Run with `cargo test`.
+
+#![no_std]
+#![no_main]
+
+#[femto_test::tests]
+mod tests {
+    use core::hint::black_box;
+
+    use embedded_hal::digital::{OutputPin, StatefulOutputPin};
+    use femto_hal::register::Field;
+    use femto_hal::{Leds, Peripherals, Uat};
+
+    // `.bss` is zeroed before every test, so each one can take these.
+    #[test]
+    fn take_peripherals_once() {
+        assert!(Peripherals::take().is_some());
+        assert!(Peripherals::take().is_none());
+    }
+
+    #[test]
+    fn field_get_and_set() {
+        let field = Field::new(4, 3);
+        assert!(field.mask::<u32>() == 0x70);
+        assert!(field.get(black_box(0xabu32)) == 0x2);
+        assert!(field.set(black_box(0xffu32), 0) == 0x8f);
+        // Bits that don't fit the field are dropped.
+        assert!(field.set(black_box(0u32), 0xf) == 0x70);
+    }
+
+    #[test]
+    fn led_pins_share_the_register() {
+        let leds = Leds::new(Peripherals::take().unwrap().leds);
+        let [mut led0, _, mut led2, ..] = leds.split();
+        led0.set_high().unwrap();
+        led2.set_high().unwrap();
+        led0.toggle().unwrap();
+        assert!(leds.get() == 0b100);
+        assert!(led2.is_set_high().unwrap());
+    }
+
+    #[test]
+    #[should_panic]
+    fn no_ninth_led() {
+        let leds = Leds::new(Peripherals::take().unwrap().leds);
+        leds.pin(black_box(8));
+    }
+
+    #[test]
+    fn uat_comes_back_ready() {
+        // Whether it's still busy by the time the status is read depends on
+        // the build, but it has to be ready again once the byte is out.
+        let mut uat = Uat::new(Peripherals::take().unwrap().uat);
+        uat.write_bytes(b"uat\n");
+        while !uat.is_ready() {}
+    }
+}
@@ -0,0 +1,157 @@
+//! femto-rt's heaps on the core itself. Run with `cargo test`, which turns on
+//! `heap-free-list`, so [`FreeList`](femto_rt::heap::FreeList) is the global
+//! allocator. The [`Bump`] tests make their own over the same arena, and
+//! leave the global one alone.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[femto_test::tests]
+mod tests {
+    use alloc::alloc::{alloc, dealloc};
+    use alloc::vec::Vec;
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::hint::black_box;
+
+    use femto_hal::{report_alloc_error, Peripherals, Uat};
+    use femto_rt::heap::{failed_alloc, Bump, HeapStats};
+    use femto_rt::heap_stats;
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    fn stats(used: usize, free: usize) -> HeapStats {
+        HeapStats { used, free }
+    }
+
+    #[test]
+    fn bump_allocates_in_order_until_it_runs_out() {
+        let heap = Bump::new();
+        let HeapStats { used, free } = heap.stats();
+        assert!(used == 0);
+        let total = free;
+
+        let a = unsafe { heap.alloc(layout(12, 4)) } as usize;
+        let b = unsafe { heap.alloc(layout(4, 16)) } as usize;
+        assert!(a != 0 && b.is_multiple_of(16) && b >= a + 12);
+        let used = heap.stats().used;
+        assert!(used == b + 4 - a);
+
+        // Too big leaves the heap as it was.
+        let free = total - used;
+        assert!(unsafe { heap.alloc(layout(free + 1, 1)) }.is_null());
+        assert!(heap.stats().used == used);
+
+        // Exactly what's left fits, and then nothing does.
+        assert!(!unsafe { heap.alloc(layout(free, 1)) }.is_null());
+        assert!(heap.stats() == stats(total, 0));
+        assert!(unsafe { heap.alloc(layout(1, 1)) }.is_null());
+    }
+
+    #[test]
+    fn bump_only_takes_back_the_latest() {
+        let heap = Bump::new();
+        let a = unsafe { heap.alloc(layout(8, 4)) };
+        let b = unsafe { heap.alloc(layout(8, 4)) };
+        let used = heap.stats().used;
+
+        unsafe { heap.dealloc(a, layout(8, 4)) };
+        assert!(heap.stats().used == used);
+        unsafe { heap.dealloc(b, layout(8, 4)) };
+        assert!(heap.stats().used == used - 8);
+
+        // `a` is the latest again, so it grows where it is.
+        let grown = unsafe { heap.realloc(a, layout(8, 4), 32) };
+        assert!(grown == a);
+        assert!(heap.stats().used == used - 8 + 24);
+
+        // Growing past the end fails, and leaves it alone.
+        let too_big = heap.stats().free + 33;
+        assert!(unsafe { heap.realloc(a, layout(32, 4), too_big) }.is_null());
+        assert!(heap.stats().used == used + 16);
+    }
+
+    #[test]
+    fn free_list_reuses_freed_blocks() {
+        let a = unsafe { alloc(layout(16, 4)) };
+        let b = unsafe { alloc(layout(16, 4)) };
+        unsafe { dealloc(black_box(a), layout(16, 4)) };
+        // The first fit is where `a` was.
+        let c = unsafe { alloc(layout(12, 4)) };
+        assert!(c == a);
+        // Which is now full, so the next one goes after `b`.
+        let d = unsafe { alloc(layout(8, 4)) };
+        assert!(d as usize >= b as usize + 16);
+    }
+
+    #[test]
+    fn free_list_merges_neighbours() {
+        let blocks = [0; 3].map(|_| unsafe { alloc(layout(16, 4)) });
+        assert!(blocks[1] as usize == blocks[0] as usize + 16);
+        assert!(blocks[2] as usize == blocks[1] as usize + 16);
+        // Free the outside ones first, so the middle one has to join both.
+        for i in [0, 2, 1] {
+            unsafe { dealloc(black_box(blocks[i]), layout(16, 4)) };
+        }
+        assert!(heap_stats().used == 0);
+        let all = unsafe { alloc(layout(48, 4)) };
+        assert!(all == blocks[0]);
+    }
+
+    #[test]
+    fn heap_stats_counts_whole_blocks() {
+        let HeapStats { used, free } = heap_stats();
+        assert!(used == 0);
+        let total = free;
+
+        // Sizes round up to 8 bytes, and alignment padding stays free.
+        let a = unsafe { alloc(layout(5, 1)) };
+        assert!(heap_stats() == stats(8, total - 8));
+        let b = unsafe { alloc(layout(16, 64)) };
+        assert!((b as usize).is_multiple_of(64));
+        assert!(heap_stats() == stats(24, total - 24));
+
+        unsafe { dealloc(black_box(a), layout(5, 1)) };
+        assert!(heap_stats().used == 16);
+        unsafe { dealloc(black_box(b), layout(16, 64)) };
+        assert!(heap_stats() == stats(0, total));
+    }
+
+    #[test]
+    fn running_out_is_an_error_to_try_reserve() {
+        let mut v: Vec<u8> = Vec::new();
+        assert!(v.try_reserve(heap_stats().free + 1).is_err());
+        assert!(v.try_reserve(16).is_ok());
+        assert!(heap_stats().used == 16);
+    }
+
+    #[test]
+    #[should_panic]
+    fn running_out_panics_otherwise() {
+        let v: Vec<u8> = Vec::with_capacity(black_box(heap_stats().free + 1));
+        black_box(v);
+    }
+
+    #[test]
+    fn running_out_is_reported_over_the_uat() {
+        let mut uat = Uat::new(Peripherals::take().unwrap().uat);
+        // Nothing has failed, so there's nothing to say.
+        assert!(!report_alloc_error(&mut uat));
+
+        let mut v: Vec<u8> = Vec::new();
+        let size = heap_stats().free + 1;
+        assert!(v.try_reserve(size).is_err());
+        assert!(failed_alloc().is_some_and(|layout| layout.size() == size));
+        // Sent with the rest of the test's output, which `cargo test` shows
+        // if it fails.
+        assert!(report_alloc_error(&mut uat));
+
+        // An allocation that works means the heap isn't out any more.
+        assert!(v.try_reserve(16).is_ok());
+        assert!(failed_alloc().is_none());
+        assert!(!report_alloc_error(&mut uat));
+    }
+}
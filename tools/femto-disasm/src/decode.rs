@@ -0,0 +1,451 @@
+//! Turning instruction words into [`Instr`]s.
+
+/// An RV32I or Zicsr operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Lui,
+    Auipc,
+    Jal,
+    Jalr,
+    Beq,
+    Bne,
+    Blt,
+    Bge,
+    Bltu,
+    Bgeu,
+    Lb,
+    Lh,
+    Lw,
+    Lbu,
+    Lhu,
+    Sb,
+    Sh,
+    Sw,
+    Addi,
+    Slti,
+    Sltiu,
+    Xori,
+    Ori,
+    Andi,
+    Slli,
+    Srli,
+    Srai,
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Fence,
+    Ecall,
+    Ebreak,
+    Csrrw,
+    Csrrs,
+    Csrrc,
+    Csrrwi,
+    Csrrsi,
+    Csrrci,
+}
+
+/// The instruction formats, which decide where the operands are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    R,
+    I,
+    S,
+    B,
+    U,
+    J,
+}
+
+impl Op {
+    /// The mnemonic, as the assembler spells it.
+    pub fn mnemonic(self) -> &'static str {
+        use Op::*;
+        match self {
+            Lui => "lui",
+            Auipc => "auipc",
+            Jal => "jal",
+            Jalr => "jalr",
+            Beq => "beq",
+            Bne => "bne",
+            Blt => "blt",
+            Bge => "bge",
+            Bltu => "bltu",
+            Bgeu => "bgeu",
+            Lb => "lb",
+            Lh => "lh",
+            Lw => "lw",
+            Lbu => "lbu",
+            Lhu => "lhu",
+            Sb => "sb",
+            Sh => "sh",
+            Sw => "sw",
+            Addi => "addi",
+            Slti => "slti",
+            Sltiu => "sltiu",
+            Xori => "xori",
+            Ori => "ori",
+            Andi => "andi",
+            Slli => "slli",
+            Srli => "srli",
+            Srai => "srai",
+            Add => "add",
+            Sub => "sub",
+            Sll => "sll",
+            Slt => "slt",
+            Sltu => "sltu",
+            Xor => "xor",
+            Srl => "srl",
+            Sra => "sra",
+            Or => "or",
+            And => "and",
+            Fence => "fence",
+            Ecall => "ecall",
+            Ebreak => "ebreak",
+            Csrrw => "csrrw",
+            Csrrs => "csrrs",
+            Csrrc => "csrrc",
+            Csrrwi => "csrrwi",
+            Csrrsi => "csrrsi",
+            Csrrci => "csrrci",
+        }
+    }
+
+    pub fn format(self) -> Format {
+        use Op::*;
+        match self {
+            Lui | Auipc => Format::U,
+            Jal => Format::J,
+            Beq | Bne | Blt | Bge | Bltu | Bgeu => Format::B,
+            Sb | Sh | Sw => Format::S,
+            Add | Sub | Sll | Slt | Sltu | Xor | Srl | Sra | Or | And => Format::R,
+            _ => Format::I,
+        }
+    }
+
+    pub fn is_branch(self) -> bool {
+        self.format() == Format::B
+    }
+
+    pub fn is_load(self) -> bool {
+        matches!(self, Op::Lb | Op::Lh | Op::Lw | Op::Lbu | Op::Lhu)
+    }
+
+    pub fn is_store(self) -> bool {
+        self.format() == Format::S
+    }
+
+    pub fn is_csr(self) -> bool {
+        matches!(
+            self,
+            Op::Csrrw | Op::Csrrs | Op::Csrrc | Op::Csrrwi | Op::Csrrsi | Op::Csrrci
+        )
+    }
+
+    /// True if the instruction writes `rd`. `x0` is still counted, so check
+    /// for that separately.
+    pub fn writes_rd(self) -> bool {
+        !self.is_branch() && !self.is_store() && !matches!(self, Op::Fence | Op::Ecall | Op::Ebreak)
+    }
+}
+
+/// A decoded instruction.
+///
+/// Registers that the format doesn't have are 0. `imm` is sign-extended and
+/// already shifted into place, so it's the number the instruction adds (for
+/// `lui` and `auipc`, the upper 20 bits with the low 12 clear). For the CSR
+/// instructions, `imm` is the CSR number, and for the immediate forms `rs1`
+/// is the 5-bit immediate. For `fence`, it's the predecessor and successor
+/// sets, in bits 7:4 and 3:0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instr {
+    pub op: Op,
+    pub rd: u8,
+    pub rs1: u8,
+    pub rs2: u8,
+    pub imm: i32,
+}
+
+impl Instr {
+    /// Decode an instruction word, or `None` if it isn't RV32I or Zicsr.
+    pub fn decode(word: u32) -> Option<Self> {
+        use Op::*;
+        let rd = ((word >> 7) & 0x1f) as u8;
+        let rs1 = ((word >> 15) & 0x1f) as u8;
+        let rs2 = ((word >> 20) & 0x1f) as u8;
+        let funct3 = (word >> 12) & 0x7;
+        let funct7 = word >> 25;
+
+        let imm_i = (word as i32) >> 20;
+        let imm_s = ((word as i32) >> 25) << 5 | ((word >> 7) & 0x1f) as i32;
+        let imm_b = ((word as i32) >> 31) << 12
+            | ((word << 4) & 0x800) as i32
+            | ((word >> 20) & 0x7e0) as i32
+            | ((word >> 7) & 0x1e) as i32;
+        let imm_u = (word & 0xffff_f000) as i32;
+        let imm_j = ((word as i32) >> 31) << 20
+            | (word & 0xf_f000) as i32
+            | ((word >> 9) & 0x800) as i32
+            | ((word >> 20) & 0x7fe) as i32;
+
+        let instr = |op, rd, rs1, rs2, imm| {
+            Some(Self {
+                op,
+                rd,
+                rs1,
+                rs2,
+                imm,
+            })
+        };
+        match word & 0x7f {
+            0b0110111 => instr(Lui, rd, 0, 0, imm_u),
+            0b0010111 => instr(Auipc, rd, 0, 0, imm_u),
+            0b1101111 => instr(Jal, rd, 0, 0, imm_j),
+            0b1100111 if funct3 == 0 => instr(Jalr, rd, rs1, 0, imm_i),
+            0b1100011 => {
+                let op = match funct3 {
+                    0b000 => Beq,
+                    0b001 => Bne,
+                    0b100 => Blt,
+                    0b101 => Bge,
+                    0b110 => Bltu,
+                    0b111 => Bgeu,
+                    _ => return None,
+                };
+                instr(op, 0, rs1, rs2, imm_b)
+            }
+            0b0000011 => {
+                let op = match funct3 {
+                    0b000 => Lb,
+                    0b001 => Lh,
+                    0b010 => Lw,
+                    0b100 => Lbu,
+                    0b101 => Lhu,
+                    _ => return None,
+                };
+                instr(op, rd, rs1, 0, imm_i)
+            }
+            0b0100011 => {
+                let op = match funct3 {
+                    0b000 => Sb,
+                    0b001 => Sh,
+                    0b010 => Sw,
+                    _ => return None,
+                };
+                instr(op, 0, rs1, rs2, imm_s)
+            }
+            0b0010011 => {
+                let (op, imm) = match (funct3, funct7) {
+                    (0b000, _) => (Addi, imm_i),
+                    (0b010, _) => (Slti, imm_i),
+                    (0b011, _) => (Sltiu, imm_i),
+                    (0b100, _) => (Xori, imm_i),
+                    (0b110, _) => (Ori, imm_i),
+                    (0b111, _) => (Andi, imm_i),
+                    (0b001, 0) => (Slli, rs2 as i32),
+                    (0b101, 0) => (Srli, rs2 as i32),
+                    (0b101, 0b010_0000) => (Srai, rs2 as i32),
+                    _ => return None,
+                };
+                instr(op, rd, rs1, 0, imm)
+            }
+            0b0110011 => {
+                let op = match (funct3, funct7) {
+                    (0b000, 0) => Add,
+                    (0b000, 0b010_0000) => Sub,
+                    (0b001, 0) => Sll,
+                    (0b010, 0) => Slt,
+                    (0b011, 0) => Sltu,
+                    (0b100, 0) => Xor,
+                    (0b101, 0) => Srl,
+                    (0b101, 0b010_0000) => Sra,
+                    (0b110, 0) => Or,
+                    (0b111, 0) => And,
+                    _ => return None,
+                };
+                instr(op, rd, rs1, rs2, 0)
+            }
+            0b0001111 if funct3 == 0 => instr(Fence, 0, 0, 0, ((word >> 20) & 0xff) as i32),
+            0b1110011 => {
+                let csr = (word >> 20) as i32;
+                match funct3 {
+                    0b000 if rd == 0 && rs1 == 0 && csr == 0 => instr(Ecall, 0, 0, 0, 0),
+                    0b000 if rd == 0 && rs1 == 0 && csr == 1 => instr(Ebreak, 0, 0, 0, 0),
+                    0b001 => instr(Csrrw, rd, rs1, 0, csr),
+                    0b010 => instr(Csrrs, rd, rs1, 0, csr),
+                    0b011 => instr(Csrrc, rd, rs1, 0, csr),
+                    0b101 => instr(Csrrwi, rd, rs1, 0, csr),
+                    0b110 => instr(Csrrsi, rd, rs1, 0, csr),
+                    0b111 => instr(Csrrci, rd, rs1, 0, csr),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Where a branch or `jal` at `pc` goes, if taken.
+    pub fn target(&self, pc: u32) -> Option<u32> {
+        (self.op.is_branch() || self.op == Op::Jal).then(|| pc.wrapping_add(self.imm as u32))
+    }
+}
+
+/// ABI names of the integer registers.
+pub const REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// The ABI name of register `x<reg>`.
+pub fn reg_name(reg: u8) -> &'static str {
+    REGISTER_NAMES[reg as usize & 0x1f]
+}
+
+/// The name of a standard CSR, if it has one.
+pub fn csr_name(csr: u16) -> Option<&'static str> {
+    Some(match csr {
+        0x001 => "fflags",
+        0x002 => "frm",
+        0x003 => "fcsr",
+        0x300 => "mstatus",
+        0x301 => "misa",
+        0x304 => "mie",
+        0x305 => "mtvec",
+        0x340 => "mscratch",
+        0x341 => "mepc",
+        0x342 => "mcause",
+        0x343 => "mtval",
+        0x344 => "mip",
+        0xb00 => "mcycle",
+        0xb02 => "minstret",
+        0xb80 => "mcycleh",
+        0xb82 => "minstreth",
+        0xc00 => "cycle",
+        0xc01 => "time",
+        0xc02 => "instret",
+        0xc80 => "cycleh",
+        0xc81 => "timeh",
+        0xc82 => "instreth",
+        0xf11 => "mvendorid",
+        0xf12 => "marchid",
+        0xf13 => "mimpid",
+        0xf14 => "mhartid",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Op::*;
+
+    /// One word per operation, from `llvm-mc`, with what it decodes to:
+    /// `(word, op, rd, rs1, rs2, imm)`.
+    const WORDS: &[(u32, Op, u8, u8, u8, i32)] = &[
+        (0x12345537, Lui, 10, 0, 0, 0x12345000),
+        (0x00001197, Auipc, 3, 0, 0, 0x1000),
+        (0x010000ef, Jal, 1, 0, 0, 16),
+        (0x801ff06f, Jal, 0, 0, 0, -2048),
+        (0xffc582e7, Jalr, 5, 11, 0, -4),
+        (0xfeb50ce3, Beq, 0, 10, 11, -8),
+        (0x00b51863, Bne, 0, 10, 11, 16),
+        (0x80d640e3, Blt, 0, 12, 13, -2048),
+        (0x7ed65fe3, Bge, 0, 12, 13, 4094),
+        (0x80f76063, Bltu, 0, 14, 15, -4096),
+        (0x00f77663, Bgeu, 0, 14, 15, 12),
+        (0xfff10503, Lb, 10, 2, 0, -1),
+        (0x00211583, Lh, 11, 2, 0, 2),
+        (0x7ff12603, Lw, 12, 2, 0, 2047),
+        (0x8001c683, Lbu, 13, 3, 0, -2048),
+        (0x00645703, Lhu, 14, 8, 0, 6),
+        (0xfea10fa3, Sb, 0, 2, 10, -1),
+        (0x00b11123, Sh, 0, 2, 11, 2),
+        (0x80c12023, Sw, 0, 2, 12, -2048),
+        (0xfd658513, Addi, 10, 11, 0, -42),
+        (0x0055a513, Slti, 10, 11, 0, 5),
+        (0x0075b513, Sltiu, 10, 11, 0, 7),
+        (0x7ff5c513, Xori, 10, 11, 0, 2047),
+        (0xfff5e513, Ori, 10, 11, 0, -1),
+        (0x0ff5f513, Andi, 10, 11, 0, 255),
+        (0x01f59513, Slli, 10, 11, 0, 31),
+        (0x0015d513, Srli, 10, 11, 0, 1),
+        (0x4075d513, Srai, 10, 11, 0, 7),
+        (0x013904b3, Add, 9, 18, 19, 0),
+        (0x413904b3, Sub, 9, 18, 19, 0),
+        (0x013914b3, Sll, 9, 18, 19, 0),
+        (0x013924b3, Slt, 9, 18, 19, 0),
+        (0x013934b3, Sltu, 9, 18, 19, 0),
+        (0x013944b3, Xor, 9, 18, 19, 0),
+        (0x013954b3, Srl, 9, 18, 19, 0),
+        (0x413954b3, Sra, 9, 18, 19, 0),
+        (0x013964b3, Or, 9, 18, 19, 0),
+        (0x013974b3, And, 9, 18, 19, 0),
+        // `fence rw, w` and `fence`: predecessors in bits 7:4.
+        (0x0310000f, Fence, 0, 0, 0, 0x31),
+        (0x0ff0000f, Fence, 0, 0, 0, 0xff),
+        (0x00000073, Ecall, 0, 0, 0, 0),
+        (0x00100073, Ebreak, 0, 0, 0, 0),
+        (0x300312f3, Csrrw, 5, 6, 0, 0x300),
+        (0x341322f3, Csrrs, 5, 6, 0, 0x341),
+        (0x7c0332f3, Csrrc, 5, 6, 0, 0x7c0),
+        // The immediate forms keep the 5-bit immediate in `rs1`.
+        (0x305fd2f3, Csrrwi, 5, 31, 0, 0x305),
+        (0x304462f3, Csrrsi, 5, 8, 0, 0x304),
+        (0x3440f2f3, Csrrci, 5, 1, 0, 0x344),
+    ];
+
+    #[test]
+    fn every_operation_decodes() {
+        for &(word, op, rd, rs1, rs2, imm) in WORDS {
+            let expected = Instr {
+                op,
+                rd,
+                rs1,
+                rs2,
+                imm,
+            };
+            assert_eq!(Instr::decode(word), Some(expected), "{word:#010x}");
+        }
+    }
+
+    #[test]
+    fn other_words_dont() {
+        for word in [
+            // All zeros and all ones are the usual unprogrammed memory.
+            0x0000_0000,
+            0xffff_ffff,
+            // `mul s1, s2, s3` from the M extension.
+            0x033904b3,
+            // `slli` with a shift amount over 31.
+            0x02059513,
+            // Load and store widths RV32I doesn't have.
+            0x00013503,
+            0x00213023,
+            // `jalr` and `fence` with a nonzero funct3.
+            0x000590e7,
+            0x0000100f,
+            // `ecall` with a register in it.
+            0x00050073,
+            // A CSR funct3 of 0b100.
+            0x300342f3,
+        ] {
+            assert_eq!(Instr::decode(word), None, "{word:#010x}");
+        }
+    }
+
+    #[test]
+    fn branch_and_jump_targets() {
+        let beq = Instr::decode(0xfeb50ce3).unwrap();
+        assert_eq!(beq.target(0x1000), Some(0xff8));
+        let jal = Instr::decode(0x010000ef).unwrap();
+        assert_eq!(jal.target(0x1000), Some(0x1010));
+        let jalr = Instr::decode(0xffc582e7).unwrap();
+        assert_eq!(jalr.target(0x1000), None);
+    }
+}
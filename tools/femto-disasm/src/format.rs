@@ -0,0 +1,270 @@
+//! Assembly text, in the style of `llvm-objdump -d`.
+//!
+//! Registers get their ABI names, immediates are decimal, and the usual
+//! aliases are used where an instruction matches one (`li`, `mv`, `ret`,
+//! `beqz`, `csrr`, ...), so the output lines up with what the compiler's
+//! `--emit asm` and the objdump listings in the tutorial show.
+
+use crate::decode::{csr_name, reg_name, Format, Instr, Op};
+use crate::symbols::Symbols;
+
+/// `csrrw zero, cycle, zero`, which the assembler emits for `unimp`.
+const UNIMP: u32 = 0xc000_1073;
+
+impl Instr {
+    /// The assembly for this instruction at `pc`, with branch and jump
+    /// targets named from `symbols`.
+    pub fn text(&self, pc: u32, symbols: &Symbols) -> String {
+        let (mnemonic, operands) = self.parts(pc, symbols);
+        if operands.is_empty() {
+            mnemonic.to_string()
+        } else {
+            format!("{mnemonic:<7} {operands}")
+        }
+    }
+
+    fn parts(&self, pc: u32, symbols: &Symbols) -> (&'static str, String) {
+        use Op::*;
+        let Instr {
+            op,
+            rd,
+            rs1,
+            rs2,
+            imm,
+        } = *self;
+        let (rd_, rs1_, rs2_) = (reg_name(rd), reg_name(rs1), reg_name(rs2));
+        let target = || symbols.describe(pc.wrapping_add(imm as u32));
+
+        match op {
+            Lui | Auipc => (op.mnemonic(), format!("{rd_}, {}", (imm as u32) >> 12)),
+            Jal => match rd {
+                0 => ("j", target()),
+                1 => ("jal", target()),
+                _ => ("jal", format!("{rd_}, {}", target())),
+            },
+            Jalr => match (rd, rs1, imm) {
+                (0, 1, 0) => ("ret", String::new()),
+                (0, _, 0) => ("jr", rs1_.to_string()),
+                (0, _, _) => ("jr", format!("{imm}({rs1_})")),
+                (1, _, _) => ("jalr", format!("{imm}({rs1_})")),
+                _ => ("jalr", format!("{rd_}, {imm}({rs1_})")),
+            },
+            _ if op.is_branch() => {
+                let zero = match (op, rs1, rs2) {
+                    (Beq, _, 0) => Some(("beqz", rs1_)),
+                    (Bne, _, 0) => Some(("bnez", rs1_)),
+                    (Blt, _, 0) => Some(("bltz", rs1_)),
+                    (Bge, _, 0) => Some(("bgez", rs1_)),
+                    (Blt, 0, _) => Some(("bgtz", rs2_)),
+                    (Bge, 0, _) => Some(("blez", rs2_)),
+                    _ => None,
+                };
+                match zero {
+                    Some((mnemonic, reg)) => (mnemonic, format!("{reg}, {}", target())),
+                    None => (op.mnemonic(), format!("{rs1_}, {rs2_}, {}", target())),
+                }
+            }
+            _ if op.is_load() => (op.mnemonic(), format!("{rd_}, {imm}({rs1_})")),
+            _ if op.is_store() => (op.mnemonic(), format!("{rs2_}, {imm}({rs1_})")),
+            Addi if rd == 0 && rs1 == 0 && imm == 0 => ("nop", String::new()),
+            Addi if rs1 == 0 => ("li", format!("{rd_}, {imm}")),
+            Addi if imm == 0 => ("mv", format!("{rd_}, {rs1_}")),
+            Xori if imm == -1 => ("not", format!("{rd_}, {rs1_}")),
+            Sltiu if imm == 1 => ("seqz", format!("{rd_}, {rs1_}")),
+            Sub if rs1 == 0 => ("neg", format!("{rd_}, {rs2_}")),
+            Sltu if rs1 == 0 => ("snez", format!("{rd_}, {rs2_}")),
+            Slt if rs2 == 0 => ("sltz", format!("{rd_}, {rs1_}")),
+            Slt if rs1 == 0 => ("sgtz", format!("{rd_}, {rs2_}")),
+            Fence => match imm {
+                0xff => ("fence", String::new()),
+                _ => (
+                    "fence",
+                    format!("{}, {}", fence_set(imm >> 4), fence_set(imm)),
+                ),
+            },
+            Ecall | Ebreak => (op.mnemonic(), String::new()),
+            _ if op.is_csr() => self.csr_parts(),
+            _ if op.format() == Format::R => (op.mnemonic(), format!("{rd_}, {rs1_}, {rs2_}")),
+            _ => (op.mnemonic(), format!("{rd_}, {rs1_}, {imm}")),
+        }
+    }
+
+    fn csr_parts(&self) -> (&'static str, String) {
+        use Op::*;
+        let Instr { op, rd, rs1, .. } = *self;
+        let csr = self.imm as u16;
+        let name = csr_name(csr).map_or_else(|| csr.to_string(), str::to_string);
+        let (rd_, rs1_) = (reg_name(rd), reg_name(rs1));
+        let immediate = matches!(op, Csrrwi | Csrrsi | Csrrci);
+
+        if op == Csrrs && rs1 == 0 {
+            let counter = match csr {
+                0xc00 => Some("rdcycle"),
+                0xc01 => Some("rdtime"),
+                0xc02 => Some("rdinstret"),
+                0xc80 => Some("rdcycleh"),
+                0xc81 => Some("rdtimeh"),
+                0xc82 => Some("rdinstreth"),
+                _ => None,
+            };
+            return match counter {
+                Some(mnemonic) => (mnemonic, rd_.to_string()),
+                None => ("csrr", format!("{rd_}, {name}")),
+            };
+        }
+        let source = if immediate {
+            rs1.to_string()
+        } else {
+            rs1_.to_string()
+        };
+        if rd == 0 {
+            let mnemonic = match op {
+                Csrrw => "csrw",
+                Csrrs => "csrs",
+                Csrrc => "csrc",
+                Csrrwi => "csrwi",
+                Csrrsi => "csrsi",
+                _ => "csrci",
+            };
+            return (mnemonic, format!("{name}, {source}"));
+        }
+        (op.mnemonic(), format!("{rd_}, {name}, {source}"))
+    }
+}
+
+/// The `iorw` letters of a `fence` predecessor or successor set.
+fn fence_set(bits: i32) -> String {
+    let set: String = "iorw"
+        .chars()
+        .enumerate()
+        .filter(|&(i, _)| bits & (8 >> i) != 0)
+        .map(|(_, c)| c)
+        .collect();
+    if set.is_empty() {
+        "0".to_string()
+    } else {
+        set
+    }
+}
+
+/// The assembly for an instruction word at `pc`, or a `.word` directive if
+/// it doesn't decode.
+pub fn disassemble_word(word: u32, pc: u32, symbols: &Symbols) -> String {
+    if word == UNIMP {
+        return "unimp".to_string();
+    }
+    match Instr::decode(word) {
+        Some(instr) => instr.text(pc, symbols),
+        None => format!(".word   {word:#010x}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Words from `llvm-mc`, at the address they're disassembled at, and
+    /// what `llvm-objdump -d` makes of them.
+    const LINES: &[(u32, u32, &str)] = &[
+        (0x1000, 0x12345537, "lui     a0, 74565"),
+        (0x1000, 0x00001197, "auipc   gp, 1"),
+        (0x1000, 0x010000ef, "jal     0x1010 <main+0x10>"),
+        (0x1000, 0x008002ef, "jal     t0, 0x1008 <main+0x8>"),
+        (0x1000, 0x801ff06f, "j       0x800 <memcpy>"),
+        (0x1000, 0xffc582e7, "jalr    t0, -4(a1)"),
+        (0x1000, 0xffc580e7, "jalr    -4(a1)"),
+        (0x1000, 0x00008067, "ret"),
+        (0x1000, 0x00078067, "jr      a5"),
+        (0x1000, 0x00878067, "jr      8(a5)"),
+        (0x1010, 0xfeb50ce3, "beq     a0, a1, 0x1008 <main+0x8>"),
+        (0x1000, 0x00b51863, "bne     a0, a1, 0x1010 <main+0x10>"),
+        (0x1000, 0x80d640e3, "blt     a2, a3, 0x800 <memcpy>"),
+        (0x1000, 0x7ed65fe3, "bge     a2, a3, 0x1ffe"),
+        (0x1000, 0x80f76063, "bltu    a4, a5, 0x0"),
+        (0x1000, 0x00f77663, "bgeu    a4, a5, 0x100c <main+0xc>"),
+        (0x1010, 0xfe050ce3, "beqz    a0, 0x1008 <main+0x8>"),
+        (0x1000, 0x00051463, "bnez    a0, 0x1008 <main+0x8>"),
+        (0x1000, 0x00054463, "bltz    a0, 0x1008 <main+0x8>"),
+        (0x1000, 0x00055463, "bgez    a0, 0x1008 <main+0x8>"),
+        (0x1000, 0x00a04463, "bgtz    a0, 0x1008 <main+0x8>"),
+        (0x1000, 0x00a05463, "blez    a0, 0x1008 <main+0x8>"),
+        (0x1000, 0xfff10503, "lb      a0, -1(sp)"),
+        (0x1000, 0x00211583, "lh      a1, 2(sp)"),
+        (0x1000, 0x7ff12603, "lw      a2, 2047(sp)"),
+        (0x1000, 0x8001c683, "lbu     a3, -2048(gp)"),
+        (0x1000, 0x00645703, "lhu     a4, 6(s0)"),
+        (0x1000, 0xfea10fa3, "sb      a0, -1(sp)"),
+        (0x1000, 0x00b11123, "sh      a1, 2(sp)"),
+        (0x1000, 0x80c12023, "sw      a2, -2048(sp)"),
+        (0x1000, 0xfd658513, "addi    a0, a1, -42"),
+        (0x1000, 0x0055a513, "slti    a0, a1, 5"),
+        (0x1000, 0x0075b513, "sltiu   a0, a1, 7"),
+        (0x1000, 0x7ff5c513, "xori    a0, a1, 2047"),
+        (0x1000, 0xfff5e513, "ori     a0, a1, -1"),
+        (0x1000, 0x0ff5f513, "andi    a0, a1, 255"),
+        (0x1000, 0x01f59513, "slli    a0, a1, 31"),
+        (0x1000, 0x0015d513, "srli    a0, a1, 1"),
+        (0x1000, 0x4075d513, "srai    a0, a1, 7"),
+        (0x1000, 0x00000013, "nop"),
+        (0x1000, 0xfff00513, "li      a0, -1"),
+        (0x1000, 0x00058513, "mv      a0, a1"),
+        (0x1000, 0xfff5c513, "not     a0, a1"),
+        (0x1000, 0x0015b513, "seqz    a0, a1"),
+        (0x1000, 0x013904b3, "add     s1, s2, s3"),
+        (0x1000, 0x413904b3, "sub     s1, s2, s3"),
+        (0x1000, 0x013914b3, "sll     s1, s2, s3"),
+        (0x1000, 0x013924b3, "slt     s1, s2, s3"),
+        (0x1000, 0x013934b3, "sltu    s1, s2, s3"),
+        (0x1000, 0x013944b3, "xor     s1, s2, s3"),
+        (0x1000, 0x013954b3, "srl     s1, s2, s3"),
+        (0x1000, 0x413954b3, "sra     s1, s2, s3"),
+        (0x1000, 0x013964b3, "or      s1, s2, s3"),
+        (0x1000, 0x013974b3, "and     s1, s2, s3"),
+        (0x1000, 0x40b00533, "neg     a0, a1"),
+        (0x1000, 0x00b03533, "snez    a0, a1"),
+        (0x1000, 0x0005a533, "sltz    a0, a1"),
+        (0x1000, 0x00b02533, "sgtz    a0, a1"),
+        (0x1000, 0x0310000f, "fence   rw, w"),
+        (0x1000, 0x0840000f, "fence   i, o"),
+        (0x1000, 0x0f00000f, "fence   iorw, 0"),
+        // Both sets full is the plain `fence` the assembler takes.
+        (0x1000, 0x0ff0000f, "fence"),
+        (0x1000, 0x00000073, "ecall"),
+        (0x1000, 0x00100073, "ebreak"),
+        (0x1000, 0x300312f3, "csrrw   t0, mstatus, t1"),
+        (0x1000, 0x341322f3, "csrrs   t0, mepc, t1"),
+        (0x1000, 0x7c0332f3, "csrrc   t0, 1984, t1"),
+        (0x1000, 0x305fd2f3, "csrrwi  t0, mtvec, 31"),
+        (0x1000, 0x304462f3, "csrrsi  t0, mie, 8"),
+        (0x1000, 0x3440f2f3, "csrrci  t0, mip, 1"),
+        (0x1000, 0x34202573, "csrr    a0, mcause"),
+        (0x1000, 0xc0002573, "rdcycle a0"),
+        (0x1000, 0xc82025f3, "rdinstreth a1"),
+        (0x1000, 0x30551073, "csrw    mtvec, a0"),
+        (0x1000, 0x30452073, "csrs    mie, a0"),
+        (0x1000, 0x30053073, "csrc    mstatus, a0"),
+        (0x1000, 0x3402d073, "csrwi   mscratch, 5"),
+        (0x1000, 0x30046073, "csrsi   mstatus, 8"),
+        (0x1000, 0x30047073, "csrci   mstatus, 8"),
+        (0x1000, 0xc0001073, "unimp"),
+        (0x1000, 0x00000000, ".word   0x00000000"),
+    ];
+
+    #[test]
+    fn every_operation_and_alias() {
+        let symbols = Symbols::with_code(&[("memcpy", 0x800, 0x40), ("main", 0x1000, 0x100)]);
+        for &(pc, word, text) in LINES {
+            assert_eq!(disassemble_word(word, pc, &symbols), text, "{word:#010x}");
+        }
+    }
+
+    #[test]
+    fn targets_are_plain_addresses_without_symbols() {
+        let symbols = Symbols::default();
+        assert_eq!(
+            disassemble_word(0xfeb50ce3, 0x1010, &symbols),
+            "beq     a0, a1, 0x1008"
+        );
+        assert_eq!(disassemble_word(0x010000ef, 0, &symbols), "jal     0x10");
+    }
+}
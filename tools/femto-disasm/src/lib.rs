@@ -0,0 +1,144 @@
+//! An RV32I and Zicsr disassembler for the femto-riscv host tools.
+//!
+//! [`Instr::decode`] takes an instruction word apart, and
+//! [`Instr::text`] prints it the way `llvm-objdump -d` would, naming
+//! branch and jump targets after the ELF symbols they land in. On top of
+//! that, a [`Disassembler`] remembers the `auipc` or `lui` before an
+//! instruction, so the address a `call` or `la` builds gets named too:
+//!
+//! ```text
+//!        0:  00001197  auipc   gp, 1
+//!        4:  a0418193  addi    gp, gp, -1532  # 0xa04 <__global_pointer$>
+//! ```
+//!
+//! The simulator's trace and the static analyses in `xtask` decode with this
+//! crate, so they all agree on what an instruction is.
+
+mod decode;
+mod format;
+mod readmemh;
+mod symbols;
+
+use std::fmt;
+
+use femto_elf::Elf;
+
+pub use decode::{csr_name, reg_name, Format, Instr, Op, REGISTER_NAMES};
+pub use format::disassemble_word;
+pub use readmemh::{parse_readmemh, Endian, ParseError};
+pub use symbols::Symbols;
+
+/// One disassembled instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u32,
+    pub word: u32,
+    pub text: String,
+    /// The code symbol that starts here, if any.
+    pub label: Option<String>,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:8x}:  {:08x}  {}", self.addr, self.word, self.text)
+    }
+}
+
+/// Disassembles a run of instructions, naming the addresses that `auipc`
+/// and `lui` pairs build.
+#[derive(Debug, Clone)]
+pub struct Disassembler<'a> {
+    symbols: &'a Symbols,
+    /// The register the last instruction put an upper immediate in, and
+    /// the value.
+    upper: Option<(u8, u32)>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(symbols: &'a Symbols) -> Self {
+        Self {
+            symbols,
+            upper: None,
+        }
+    }
+
+    /// Disassemble the instruction `word` at `pc`. The one before it is
+    /// taken to be the last one passed in, so after a jump, call
+    /// [`reset`](Self::reset) first. Words outside the code sections come
+    /// out as `.word`.
+    pub fn line(&mut self, pc: u32, word: u32) -> Line {
+        if !self.symbols.is_code(pc) {
+            self.upper = None;
+            return Line {
+                addr: pc,
+                word,
+                text: format!(".word   {word:#010x}"),
+                label: None,
+            };
+        }
+        let mut text = disassemble_word(word, pc, self.symbols);
+        let instr = Instr::decode(word);
+
+        if let (Some(instr), Some((reg, upper))) = (instr, self.upper) {
+            let adds = instr.op == Op::Addi
+                || instr.op == Op::Jalr
+                || instr.op.is_load()
+                || instr.op.is_store();
+            if adds && instr.rs1 == reg && reg != 0 {
+                let addr = upper.wrapping_add(instr.imm as u32);
+                let name = if instr.op == Op::Jalr {
+                    self.symbols.describe(addr)
+                } else {
+                    self.symbols.describe_any(addr)
+                };
+                text = format!("{text}  # {name}");
+            }
+        }
+        self.upper = match instr {
+            Some(i) if i.op == Op::Auipc => Some((i.rd, pc.wrapping_add(i.imm as u32))),
+            Some(i) if i.op == Op::Lui => Some((i.rd, i.imm as u32)),
+            _ => None,
+        };
+
+        Line {
+            addr: pc,
+            word,
+            text,
+            label: self.symbols.label(pc).map(str::to_string),
+        }
+    }
+
+    /// Forget the previous instruction.
+    pub fn reset(&mut self) {
+        self.upper = None;
+    }
+
+    /// Disassemble the little-endian instruction words in `code`, which
+    /// starts at `addr`. A trailing partial word is left out.
+    pub fn block(&mut self, addr: u32, code: &[u8]) -> Vec<Line> {
+        self.reset();
+        code.chunks_exact(4)
+            .enumerate()
+            .map(|(i, word)| {
+                let word = u32::from_le_bytes(word.try_into().unwrap());
+                self.line(addr + 4 * i as u32, word)
+            })
+            .collect()
+    }
+}
+
+/// Disassemble every code section of an ELF file, in address order.
+pub fn disassemble_elf(elf: &Elf, symbols: &Symbols) -> Vec<Line> {
+    let mut sections: Vec<_> = elf
+        .sections()
+        .iter()
+        .filter(|s| s.is_code() && s.is_alloc())
+        .collect();
+    sections.sort_by_key(|s| s.addr);
+
+    let mut disassembler = Disassembler::new(symbols);
+    sections
+        .into_iter()
+        .flat_map(|s| disassembler.block(s.addr, elf.section_data(s)))
+        .collect()
+}
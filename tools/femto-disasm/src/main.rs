@@ -0,0 +1,126 @@
+use std::io::Write;
+use std::process::ExitCode;
+
+use femto_disasm::{disassemble_elf, parse_readmemh, Disassembler, Endian, Line, Symbols};
+use femto_elf::Elf;
+
+const USAGE: &str = "\
+Usage: femto-disasm [OPTIONS] <FILE>
+
+Disassemble RV32I and Zicsr machine code, from an ELF file or from a
+$readmemh file like program.mem. Branch and jump targets, and the addresses
+auipc/lui pairs build, are named after the ELF's symbols.
+
+Options:
+      --symbols <ELF>    Take symbol names from this ELF, for a $readmemh file
+      --endian <ENDIAN>  Byte order of the $readmemh words, as given to
+                         `xtask image` [default: big] [possible values: big,
+                         little]
+      --all              Keep the zero words that pad a $readmemh file out to
+                         the memory depth
+  -h, --help             Print this help
+";
+
+struct Args {
+    file: String,
+    symbols: Option<String>,
+    endian: Endian,
+    all: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut file = None;
+    let mut symbols = None;
+    let mut endian = Endian::default();
+    let mut all = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                std::process::exit(0);
+            }
+            "--symbols" => symbols = Some(args.next().ok_or("`--symbols` needs a value")?),
+            "--endian" => {
+                let value = args.next().ok_or("`--endian` needs a value")?;
+                endian = Endian::from_name(&value)
+                    .ok_or_else(|| format!("invalid value `{value}` for `--endian`"))?;
+            }
+            "--all" => all = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ if file.is_none() => file = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+
+    Ok(Args {
+        file: file.ok_or("no file given")?,
+        symbols,
+        endian,
+        all,
+    })
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("couldn't read {path}: {e}"))
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let data = read(&args.file)?;
+    let lines = if data.starts_with(b"\x7fELF") {
+        let elf = Elf::parse(&data).map_err(|e| format!("{}: {e}", args.file))?;
+        disassemble_elf(&elf, &Symbols::new(&elf))
+    } else {
+        let symbols = match &args.symbols {
+            Some(path) => {
+                let data = read(path)?;
+                let elf = Elf::parse(&data).map_err(|e| format!("{path}: {e}"))?;
+                Symbols::new(&elf)
+            }
+            None => Symbols::default(),
+        };
+        let text = String::from_utf8_lossy(&data);
+        let mut memory =
+            parse_readmemh(&text, args.endian).map_err(|e| format!("{}: {e}", args.file))?;
+        if !args.all {
+            let used = memory.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+            memory.truncate(used.next_multiple_of(4));
+        }
+        Disassembler::new(&symbols).block(0, &memory)
+    };
+    // A closed pipe (`| head`) isn't worth complaining about.
+    print(&lines).ok();
+    Ok(())
+}
+
+fn print(lines: &[Line]) -> std::io::Result<()> {
+    let mut out = std::io::stdout().lock();
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(label) = &line.label {
+            if i != 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "{:08x} <{label}>:", line.addr)?;
+        }
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
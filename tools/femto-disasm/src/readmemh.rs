@@ -0,0 +1,91 @@
+//! Reading the `$readmemh` files that `xtask image` writes and RiscvMem
+//! loads.
+
+use std::fmt;
+
+/// How the hex digits of a word map to memory, matching `xtask image
+/// --endian`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Endian {
+    /// The bytes appear in address order, so `97110000` is the instruction
+    /// `0x00001197`. This is what `xtask image` writes by default.
+    #[default]
+    Big,
+    /// Each word is the little-endian value, so the file holds `00001197`.
+    Little,
+}
+
+impl Endian {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "big" => Some(Endian::Big),
+            "little" => Some(Endian::Little),
+            _ => None,
+        }
+    }
+}
+
+/// A line of a `$readmemh` file that doesn't make sense.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Turn a `$readmemh` file back into the bytes it puts in memory, starting
+/// at address 0. Anything the file skips over with `@` is zero.
+///
+/// The file can hold 32-bit words (8 digits each) or bytes (2 digits each),
+/// but not both. `@` addresses count in those units, like `$readmemh` does.
+pub fn parse_readmemh(text: &str, endian: Endian) -> Result<Vec<u8>, ParseError> {
+    let mut memory = Vec::new();
+    let mut width = None;
+    let mut addr = 0usize;
+
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| ParseError {
+            line: i + 1,
+            message,
+        };
+        let line = line.split("//").next().unwrap_or("");
+        for token in line.split_whitespace() {
+            let token = token.replace('_', "");
+            if let Some(target) = token.strip_prefix('@') {
+                addr = usize::from_str_radix(target, 16)
+                    .map_err(|_| error(format!("invalid address `@{target}`")))?;
+                continue;
+            }
+            let digits = token.len();
+            if digits != 2 && digits != 8 {
+                return Err(error(format!("`{token}` is neither a byte nor a word")));
+            }
+            let bytes = digits / 2;
+            if *width.get_or_insert(bytes) != bytes {
+                return Err(error(format!("`{token}` doesn't match the earlier values")));
+            }
+            let value = u32::from_str_radix(&token, 16)
+                .map_err(|_| error(format!("invalid hex value `{token}`")))?;
+
+            let start = addr * bytes;
+            if memory.len() < start + bytes {
+                memory.resize(start + bytes, 0);
+            }
+            let data = match (bytes, endian) {
+                (1, _) => vec![value as u8],
+                (_, Endian::Big) => value.to_be_bytes().to_vec(),
+                (_, Endian::Little) => value.to_le_bytes().to_vec(),
+            };
+            memory[start..start + bytes].copy_from_slice(&data);
+            addr += 1;
+        }
+    }
+    Ok(memory)
+}
@@ -0,0 +1,159 @@
+//! Names for the addresses that show up in the machine code.
+
+use std::ops::Range;
+
+use femto_elf::{demangle, Elf, SymbolKind, SymbolMap};
+
+/// A named address range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Named {
+    addr: u32,
+    size: u32,
+    name: String,
+}
+
+/// The symbols of an ELF file, demangled, for naming branch targets and the
+/// addresses `auipc` and `lui` build.
+///
+/// Code addresses are named after the function (or label, like `_start`)
+/// they fall in, the way `llvm-objdump` does. Other addresses are only named
+/// if they're inside a sized object like a `static`, or a symbol sits right
+/// on them, like `__global_pointer$` or `_stack_start`.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    code: Vec<Named>,
+    objects: Vec<Named>,
+    /// Symbols without a size, section ones first.
+    labels: Vec<Named>,
+    /// The code sections, so data in a memory image isn't taken for code.
+    code_sections: Vec<Range<u32>>,
+}
+
+impl Symbols {
+    pub fn new(elf: &Elf) -> Self {
+        let code = SymbolMap::new(elf)
+            .symbols()
+            .iter()
+            .map(|s| Named {
+                addr: s.value,
+                size: s.size,
+                name: demangle(s.name),
+            })
+            .collect();
+
+        let mut objects = Vec::new();
+        let mut labels = Vec::new();
+        for sym in elf.symbols().iter().filter(|s| !s.is_internal()) {
+            let named = Named {
+                addr: sym.value,
+                size: sym.size,
+                name: demangle(sym.name),
+            };
+            match sym.kind {
+                SymbolKind::Object if sym.size != 0 => objects.push(named),
+                SymbolKind::NoType | SymbolKind::Object => {
+                    labels.push((sym.section.is_none(), !sym.global, named))
+                }
+                _ => {}
+            }
+        }
+        objects.sort_by_key(|o| o.addr);
+        labels.sort_by_key(|&(absolute, local, ref l)| (absolute, local, l.addr));
+        let code_sections = elf
+            .sections()
+            .iter()
+            .filter(|s| s.is_code() && s.is_alloc())
+            .map(|s| s.addr..s.addr + s.size)
+            .collect();
+        Self {
+            code,
+            objects,
+            labels: labels.into_iter().map(|(_, _, l)| l).collect(),
+            code_sections,
+        }
+    }
+
+    /// The code symbol containing `addr`, and how far into it `addr` is.
+    /// Symbols without a size run up to the next one.
+    pub fn lookup(&self, addr: u32) -> Option<(&str, u32)> {
+        let index = self
+            .code
+            .partition_point(|s| s.addr <= addr)
+            .checked_sub(1)?;
+        let sym = &self.code[index];
+        let offset = addr - sym.addr;
+        if sym.size != 0 && offset >= sym.size {
+            return None;
+        }
+        Some((&sym.name, offset))
+    }
+
+    /// True if `addr` is in a code section. Without an ELF to go by,
+    /// everything is.
+    pub fn is_code(&self, addr: u32) -> bool {
+        self.code_sections.is_empty() || self.code_sections.iter().any(|s| s.contains(&addr))
+    }
+
+    /// The code symbol that starts at `addr`.
+    pub fn label(&self, addr: u32) -> Option<&str> {
+        self.lookup(addr)
+            .filter(|&(_, offset)| offset == 0)
+            .map(|(name, _)| name)
+    }
+
+    /// A name for any address: code first, then objects, then a symbol
+    /// sitting right on it.
+    pub fn name(&self, addr: u32) -> Option<(&str, u32)> {
+        if let Some(found) = self.lookup(addr) {
+            return Some(found);
+        }
+        let index = self.objects.partition_point(|o| o.addr <= addr);
+        if let Some(object) = index.checked_sub(1).map(|i| &self.objects[i]) {
+            if addr - object.addr < object.size {
+                return Some((&object.name, addr - object.addr));
+            }
+        }
+        self.labels
+            .iter()
+            .find(|l| l.addr == addr)
+            .map(|l| (l.name.as_str(), 0))
+    }
+
+    /// `addr` in hex, followed by its code symbol like `0x48 <main+0x8>`.
+    pub fn describe(&self, addr: u32) -> String {
+        annotate(addr, self.lookup(addr))
+    }
+
+    /// Like [`describe`](Self::describe), but for an address that might not
+    /// be code.
+    pub fn describe_any(&self, addr: u32) -> String {
+        annotate(addr, self.name(addr))
+    }
+}
+
+#[cfg(test)]
+impl Symbols {
+    /// Just code symbols, as `(name, addr, size)`, without an ELF to read
+    /// them from.
+    pub(crate) fn with_code(code: &[(&str, u32, u32)]) -> Self {
+        Self {
+            code: code
+                .iter()
+                .map(|&(name, addr, size)| Named {
+                    addr,
+                    size,
+                    name: name.to_string(),
+                })
+                .collect(),
+            ..Self::default()
+        }
+    }
+}
+
+fn annotate(addr: u32, name: Option<(&str, u32)>) -> String {
+    match name {
+        Some((name, 0)) => format!("{addr:#x} <{name}>"),
+        Some((name, offset)) => format!("{addr:#x} <{name}+{offset:#x}>"),
+        None => format!("{addr:#x}"),
+    }
+}
@@ -0,0 +1,405 @@
+//! Just enough ELF parsing for the femto-riscv host tools.
+//!
+//! Only 32-bit little-endian RISC-V executables are supported, which is all
+//! `rust-lld` will ever hand us for `riscv32i-unknown-none-elf`. Everything is
+//! borrowed from the file's bytes, so parsing is cheap and nothing is copied
+//! until a caller asks for it.
+
+use std::fmt;
+
+/// `p_type` of a loadable segment.
+pub const PT_LOAD: u32 = 1;
+
+/// `sh_type` of a section that takes up no space in the file (e.g. `.bss`).
+pub const SHT_NOBITS: u32 = 8;
+
+/// `sh_flags` bit for sections that occupy memory at run time.
+pub const SHF_ALLOC: u32 = 0x2;
+/// `sh_flags` bit for sections containing instructions.
+pub const SHF_EXECINSTR: u32 = 0x4;
+
+const EM_RISCV: u16 = 243;
+const SHT_SYMTAB: u32 = 2;
+const SHN_ABS: u16 = 0xfff1;
+
+/// Errors from [`Elf::parse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The file ended before a header or table it points to.
+    Truncated(&'static str),
+    /// The file doesn't start with `\x7fELF`.
+    BadMagic,
+    /// The file is an ELF, just not one we can handle.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Truncated(what) => write!(f, "ELF file is truncated ({what})"),
+            Error::BadMagic => write!(f, "not an ELF file"),
+            Error::Unsupported(why) => write!(f, "unsupported ELF file: {why}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// One entry of the program header table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub p_type: u32,
+    pub offset: u32,
+    /// Address the segment runs at.
+    pub vaddr: u32,
+    /// Address the segment is loaded at. This is what ends up in the memory
+    /// image, and only differs from `vaddr` for things like `.data`.
+    pub paddr: u32,
+    pub filesz: u32,
+    pub memsz: u32,
+    pub flags: u32,
+}
+
+impl Segment {
+    /// True for segments with bytes that need to be placed in memory.
+    pub fn is_load(&self) -> bool {
+        self.p_type == PT_LOAD && self.filesz > 0
+    }
+}
+
+/// One entry of the section header table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Section<'a> {
+    pub name: &'a str,
+    pub sh_type: u32,
+    pub flags: u32,
+    pub addr: u32,
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl Section<'_> {
+    /// True for sections that take up memory on the target.
+    pub fn is_alloc(&self) -> bool {
+        self.flags & SHF_ALLOC != 0
+    }
+
+    /// True for sections holding code.
+    pub fn is_code(&self) -> bool {
+        self.flags & SHF_EXECINSTR != 0
+    }
+}
+
+/// The kind of thing a symbol names, from the low nibble of `st_info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    NoType,
+    Object,
+    Func,
+    Section,
+    File,
+    Other(u8),
+}
+
+/// One entry of `.symtab`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    pub value: u32,
+    pub size: u32,
+    pub kind: SymbolKind,
+    pub global: bool,
+    /// Index of the section the symbol lives in, or `None` for undefined and
+    /// absolute symbols.
+    pub section: Option<u16>,
+}
+
+impl Symbol<'_> {
+    /// True for compiler- and assembler-generated names like `.L0` and the
+    /// `$x`/`$d` mapping symbols, which nobody wants to see in a report.
+    pub fn is_internal(&self) -> bool {
+        self.name.is_empty() || self.name.starts_with(".L") || self.name.starts_with('$')
+    }
+}
+
+/// A parsed ELF file.
+#[derive(Debug, Clone)]
+pub struct Elf<'a> {
+    data: &'a [u8],
+    entry: u32,
+    segments: Vec<Segment>,
+    sections: Vec<Section<'a>>,
+    symbols: Vec<Symbol<'a>>,
+}
+
+impl<'a> Elf<'a> {
+    /// Parse an ELF file held in memory.
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        if data.len() < 52 {
+            return Err(Error::Truncated("file header"));
+        }
+        if &data[..4] != b"\x7fELF" {
+            return Err(Error::BadMagic);
+        }
+        if data[4] != 1 {
+            return Err(Error::Unsupported("not a 32-bit ELF"));
+        }
+        if data[5] != 1 {
+            return Err(Error::Unsupported("not little-endian"));
+        }
+        if u16_at(data, 18) != EM_RISCV {
+            return Err(Error::Unsupported("not a RISC-V executable"));
+        }
+
+        let entry = u32_at(data, 24);
+        let phoff = u32_at(data, 28) as usize;
+        let shoff = u32_at(data, 32) as usize;
+        let phentsize = u16_at(data, 42) as usize;
+        let phnum = u16_at(data, 44) as usize;
+        let shentsize = u16_at(data, 46) as usize;
+        let shnum = u16_at(data, 48) as usize;
+        let shstrndx = u16_at(data, 50) as usize;
+
+        let mut segments = Vec::with_capacity(phnum);
+        for i in 0..phnum {
+            let ph = table_entry(data, phoff, phentsize, i, 32, "program headers")?;
+            segments.push(Segment {
+                p_type: u32_at(ph, 0),
+                offset: u32_at(ph, 4),
+                vaddr: u32_at(ph, 8),
+                paddr: u32_at(ph, 12),
+                filesz: u32_at(ph, 16),
+                memsz: u32_at(ph, 20),
+                flags: u32_at(ph, 24),
+            });
+        }
+
+        // Raw section headers first, since names live in one of the sections.
+        let mut raw_sections = Vec::with_capacity(shnum);
+        for i in 0..shnum {
+            raw_sections.push(table_entry(
+                data,
+                shoff,
+                shentsize,
+                i,
+                40,
+                "section headers",
+            )?);
+        }
+        let shstrtab = match raw_sections.get(shstrndx) {
+            Some(sh) => file_range(data, u32_at(sh, 16), u32_at(sh, 20), "section names")?,
+            None => &[],
+        };
+
+        let mut sections = Vec::with_capacity(shnum);
+        for sh in &raw_sections {
+            sections.push(Section {
+                name: str_at(shstrtab, u32_at(sh, 0)),
+                sh_type: u32_at(sh, 4),
+                flags: u32_at(sh, 8),
+                addr: u32_at(sh, 12),
+                offset: u32_at(sh, 16),
+                size: u32_at(sh, 20),
+            });
+        }
+
+        let mut symbols = Vec::new();
+        for sh in &raw_sections {
+            if u32_at(sh, 4) != SHT_SYMTAB {
+                continue;
+            }
+            let table = file_range(data, u32_at(sh, 16), u32_at(sh, 20), "symbol table")?;
+            let strtab = match raw_sections.get(u32_at(sh, 24) as usize) {
+                Some(st) => file_range(data, u32_at(st, 16), u32_at(st, 20), "symbol names")?,
+                None => &[],
+            };
+            // Entry 0 is always the null symbol.
+            for sym in table.chunks_exact(16).skip(1) {
+                let info = sym[12];
+                let shndx = u16_at(sym, 14);
+                symbols.push(Symbol {
+                    name: str_at(strtab, u32_at(sym, 0)),
+                    value: u32_at(sym, 4),
+                    size: u32_at(sym, 8),
+                    kind: match info & 0xf {
+                        0 => SymbolKind::NoType,
+                        1 => SymbolKind::Object,
+                        2 => SymbolKind::Func,
+                        3 => SymbolKind::Section,
+                        4 => SymbolKind::File,
+                        other => SymbolKind::Other(other),
+                    },
+                    global: info >> 4 != 0,
+                    section: (shndx != 0 && shndx < SHN_ABS).then_some(shndx),
+                });
+            }
+        }
+
+        Ok(Self {
+            data,
+            entry,
+            segments,
+            sections,
+            symbols,
+        })
+    }
+
+    /// The entry point from the file header.
+    pub fn entry(&self) -> u32 {
+        self.entry
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The segments with bytes that need to be placed in memory.
+    pub fn load_segments(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.iter().filter(|s| s.is_load())
+    }
+
+    /// The file contents of a segment.
+    pub fn segment_data(&self, segment: &Segment) -> &'a [u8] {
+        let start = segment.offset as usize;
+        let end = start.saturating_add(segment.filesz as usize);
+        self.data.get(start..end).unwrap_or(&[])
+    }
+
+    pub fn sections(&self) -> &[Section<'a>] {
+        &self.sections
+    }
+
+    /// Look up a section by name.
+    pub fn section(&self, name: &str) -> Option<&Section<'a>> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// The file contents of a section. Empty for `NOBITS` sections like `.bss`.
+    pub fn section_data(&self, section: &Section) -> &'a [u8] {
+        if section.sh_type == SHT_NOBITS {
+            return &[];
+        }
+        let start = section.offset as usize;
+        let end = start.saturating_add(section.size as usize);
+        self.data.get(start..end).unwrap_or(&[])
+    }
+
+    pub fn symbols(&self) -> &[Symbol<'a>] {
+        &self.symbols
+    }
+
+    /// Look up a symbol by name, preferring global symbols over local ones.
+    pub fn symbol(&self, name: &str) -> Option<&Symbol<'a>> {
+        let mut found = None;
+        for sym in self.symbols.iter().filter(|s| s.name == name) {
+            if sym.global {
+                return Some(sym);
+            }
+            found.get_or_insert(sym);
+        }
+        found
+    }
+}
+
+/// Code symbols sorted by address, for turning a `pc` back into a name.
+///
+/// This holds functions, plus global labels in code sections like `_start`
+/// that never get a `.type` directive.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolMap<'a> {
+    symbols: Vec<Symbol<'a>>,
+}
+
+impl<'a> SymbolMap<'a> {
+    /// Collect the code symbols of an ELF file.
+    pub fn new(elf: &Elf<'a>) -> Self {
+        let in_code = |sym: &Symbol| {
+            sym.section
+                .and_then(|i| elf.sections().get(i as usize))
+                .is_some_and(|s| s.is_code())
+        };
+        let mut symbols: Vec<Symbol<'a>> = elf
+            .symbols()
+            .iter()
+            .filter(|s| !s.is_internal())
+            .filter(|s| s.kind == SymbolKind::Func || (s.kind == SymbolKind::NoType && s.global))
+            .filter(|s| in_code(s))
+            .copied()
+            .collect();
+        // When two symbols share an address, keep the function over the label.
+        symbols.sort_by_key(|s| (s.value, s.kind != SymbolKind::Func));
+        symbols.dedup_by_key(|s| s.value);
+        Self { symbols }
+    }
+
+    /// The symbols, in address order.
+    pub fn symbols(&self) -> &[Symbol<'a>] {
+        &self.symbols
+    }
+
+    /// Find the symbol containing `addr`, and how far into it `addr` is.
+    /// Symbols without a size are taken to run up to the next symbol.
+    pub fn lookup(&self, addr: u32) -> Option<(&Symbol<'a>, u32)> {
+        let index = self
+            .symbols
+            .partition_point(|s| s.value <= addr)
+            .checked_sub(1)?;
+        let sym = &self.symbols[index];
+        let offset = addr - sym.value;
+        if sym.size != 0 && offset >= sym.size {
+            return None;
+        }
+        Some((sym, offset))
+    }
+}
+
+/// Demangle a Rust symbol name, leaving the hash off. Anything that isn't a
+/// Rust symbol comes back unchanged.
+pub fn demangle(name: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(name))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn file_range<'a>(
+    data: &'a [u8],
+    offset: u32,
+    size: u32,
+    what: &'static str,
+) -> Result<&'a [u8], Error> {
+    let start = offset as usize;
+    let end = start
+        .checked_add(size as usize)
+        .ok_or(Error::Truncated(what))?;
+    data.get(start..end).ok_or(Error::Truncated(what))
+}
+
+fn table_entry<'a>(
+    data: &'a [u8],
+    table: usize,
+    entsize: usize,
+    index: usize,
+    min_size: usize,
+    what: &'static str,
+) -> Result<&'a [u8], Error> {
+    if entsize < min_size {
+        return Err(Error::Unsupported("table entries are too small"));
+    }
+    let start = table + index * entsize;
+    data.get(start..start + entsize)
+        .ok_or(Error::Truncated(what))
+}
+
+/// Read a NUL-terminated string out of a string table. Bad offsets and
+/// non-UTF-8 names come back empty rather than failing the whole parse.
+fn str_at(table: &[u8], offset: u32) -> &str {
+    let bytes = table.get(offset as usize..).unwrap_or(&[]);
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).unwrap_or("")
+}
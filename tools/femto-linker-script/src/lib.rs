@@ -0,0 +1,210 @@
+//! Memory regions, as a linker script's `MEMORY` block declares them.
+//!
+//! femto-rt's build script writes linker scripts from sizes in this syntax,
+//! and xtask reads them back, so both go through [`parse_number`] here.
+
+/// One line of a `MEMORY` block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub origin: u32,
+    pub length: u32,
+}
+
+impl Region {
+    /// One past the last address of the region.
+    pub fn end(&self) -> u64 {
+        self.origin as u64 + self.length as u64
+    }
+
+    /// True if `len` bytes starting at `addr` are all inside the region.
+    pub fn contains(&self, addr: u32, len: u32) -> bool {
+        addr >= self.origin && addr as u64 + len as u64 <= self.end()
+    }
+}
+
+/// Parse the regions declared in a linker script's `MEMORY` block. Only plain
+/// numbers are understood for `ORIGIN` and `LENGTH`, as [`parse_number`]
+/// reads them.
+pub fn memory_regions(script: &str) -> Result<Vec<Region>, String> {
+    let script = strip_comments(script);
+    let start = script
+        .find("MEMORY")
+        .ok_or("linker script has no MEMORY block")?;
+    let body = &script[start + "MEMORY".len()..];
+    let open = body.find('{').ok_or("MEMORY block has no `{`")?;
+    let close = body.find('}').ok_or("MEMORY block has no `}`")?;
+
+    let mut regions = Vec::new();
+    for line in body[open + 1..close].lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (head, attrs) = line
+            .split_once(':')
+            .ok_or_else(|| format!("can't parse memory region `{line}`"))?;
+        // Drop the `(RWX)` part if there is one.
+        let name = head.split('(').next().unwrap_or("").trim();
+
+        let mut origin = None;
+        let mut length = None;
+        for attr in attrs.split(',') {
+            let (key, value) = attr
+                .split_once('=')
+                .ok_or_else(|| format!("can't parse `{}` in memory region {name}", attr.trim()))?;
+            let value = parse_number(value.trim())
+                .ok_or_else(|| format!("can't parse `{}` in memory region {name}", value.trim()))?;
+            match key.trim().to_ascii_uppercase().as_str() {
+                "ORIGIN" | "ORG" | "O" => origin = Some(value),
+                "LENGTH" | "LEN" | "L" => length = Some(value),
+                other => {
+                    return Err(format!(
+                        "unknown attribute `{other}` in memory region {name}"
+                    ))
+                }
+            }
+        }
+        regions.push(Region {
+            name: name.to_string(),
+            origin: origin.ok_or_else(|| format!("memory region {name} has no ORIGIN"))?,
+            length: length.ok_or_else(|| format!("memory region {name} has no LENGTH"))?,
+        });
+    }
+    Ok(regions)
+}
+
+/// The region a `REGION_ALIAS("alias", REGION)` line points `alias` at.
+pub fn region_alias(script: &str, alias: &str) -> Option<String> {
+    let script = strip_comments(script);
+    let mut rest = script.as_str();
+    while let Some(start) = rest.find("REGION_ALIAS") {
+        rest = &rest[start + "REGION_ALIAS".len()..];
+        let args = rest.trim_start().strip_prefix('(')?;
+        let args = &args[..args.find(')')?];
+        let (name, region) = args.split_once(',')?;
+        if name.trim().trim_matches('"') == alias {
+            return Some(region.trim().to_string());
+        }
+    }
+    None
+}
+
+fn strip_comments(script: &str) -> String {
+    let mut out = String::with_capacity(script.len());
+    let mut rest = script;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start..].find("*/") {
+            Some(end) => &rest[start + end + 2..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Parse a number the way a linker script writes it: decimal or `0x` hex,
+/// optionally followed by `K` or `M`. Surrounding whitespace is ignored, and
+/// so are `_`s between digits, like in Rust and TOML.
+pub fn parse_number(s: &str) -> Option<u32> {
+    let s = s.trim().replace('_', "");
+    let s = s.as_str();
+    let (digits, scale) = match s.as_bytes().last()? {
+        b'K' | b'k' => (&s[..s.len() - 1], 1024),
+        b'M' | b'm' => (&s[..s.len() - 1], 1024 * 1024),
+        _ => (s, 1),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    value.checked_mul(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        let cases = [
+            ("0", Some(0)),
+            ("1024", Some(1024)),
+            ("0x400", Some(0x400)),
+            ("0X400", Some(0x400)),
+            ("0xdeadBEEF", Some(0xdead_beef)),
+            ("1K", Some(1024)),
+            ("64k", Some(64 * 1024)),
+            ("0x10K", Some(16 * 1024)),
+            ("4M", Some(4 * 1024 * 1024)),
+            (" 16K ", Some(16 * 1024)),
+            ("0x1_0000", Some(0x1_0000)),
+            ("4_096", Some(4096)),
+            ("4294967295", Some(u32::MAX)),
+            ("", None),
+            ("K", None),
+            ("0x", None),
+            ("-1", None),
+            ("1G", None),
+            ("4194304K", None),
+            ("4294967296", None),
+            ("0x1_0000_0000", None),
+            ("ORIGIN(RAM)", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_number(text), expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn regions_and_aliases() {
+        let script = r#"
+            /* Two regions */
+            MEMORY
+            {
+                ROM (RX) : ORIGIN = 0x00000000, LENGTH = 16K
+                RAM (RWX) : org = 0x10000, len = 0x1000 /* 4K */
+            }
+            REGION_ALIAS("REGION_TEXT", ROM);
+            REGION_ALIAS("REGION_DATA", RAM);
+        "#;
+        let regions = memory_regions(script).unwrap();
+        assert_eq!(
+            regions,
+            [
+                Region {
+                    name: "ROM".into(),
+                    origin: 0,
+                    length: 16 * 1024,
+                },
+                Region {
+                    name: "RAM".into(),
+                    origin: 0x10000,
+                    length: 0x1000,
+                },
+            ]
+        );
+        assert_eq!(regions[1].end(), 0x11000);
+        assert!(regions[1].contains(0x10ffc, 4));
+        assert!(!regions[1].contains(0x10ffc, 8));
+        assert_eq!(region_alias(script, "REGION_DATA").as_deref(), Some("RAM"));
+        assert_eq!(region_alias(script, "REGION_STACK"), None);
+    }
+
+    #[test]
+    fn bad_regions() {
+        let region = |line: &str| memory_regions(&format!("MEMORY {{\n{line}\n}}"));
+        assert!(region("RAM : ORIGIN = 0")
+            .unwrap_err()
+            .contains("no LENGTH"));
+        assert!(region("RAM : ORIGIN = 0, LENGTH = lots").is_err());
+        assert!(region("RAM : ORIGIN = 0, SIZE = 4K")
+            .unwrap_err()
+            .contains("SIZE"));
+        assert!(memory_regions("SECTIONS {}").is_err());
+    }
+}
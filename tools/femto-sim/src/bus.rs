@@ -0,0 +1,259 @@
+//! The memory map of `RiscvFemto_tb`.
+//!
+//! Address bit 22 picks between the `RiscvMem` block RAM and the peripherals.
+//! Normally that's a single RAM mirrored through the whole RAM space, but for
+//! an execute-in-place layout the bus can instead hold a read-only ROM and a
+//! separate RAM at the addresses the linker script gave them, with everything
+//! around them reading as zero. See [`Bus::split`].
+//!
+//! The peripherals are decoded one address bit at a time, exactly like the
+//! testbench does it:
+//!
+//! | Address     | Bit | Access | Peripheral                       |
+//! | ----------- | --- | ------ | -------------------------------- |
+//! | `0x40_0004` | 2   | write  | LEDs, low byte of the write      |
+//! | `0x40_0008` | 3   | write  | UAT data, low byte of the write  |
+//! | `0x40_0010` | 4   | read   | UAT status, ready on bit 9       |
+//!
+//! Everything else in the peripheral space reads as zero and ignores writes.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::uat::Uat;
+
+/// Selects the peripheral space instead of RAM.
+pub const IO_BIT: u32 = 1 << 22;
+/// Address of the LED register.
+pub const LEDS: u32 = IO_BIT | 0x04;
+/// Address of the UAT data register.
+pub const UAT_DATA: u32 = IO_BIT | 0x08;
+/// Address of the UAT status register.
+pub const UAT_STATUS: u32 = IO_BIT | 0x10;
+/// Bit of the UAT status word that is set when the UAT can take a byte.
+pub const UAT_READY_BIT: u32 = 9;
+
+/// A block of memory sitting at `origin`.
+#[derive(Debug)]
+struct Memory {
+    origin: u32,
+    words: Vec<u32>,
+}
+
+impl Memory {
+    fn new(range: Range<u32>) -> Self {
+        let size = range.end.wrapping_sub(range.start);
+        Self {
+            origin: range.start,
+            words: vec![0; size as usize / 4],
+        }
+    }
+
+    fn size(&self) -> u32 {
+        (self.words.len() * 4) as u32
+    }
+
+    fn index(&self, addr: u32) -> Option<usize> {
+        let offset = addr.wrapping_sub(self.origin);
+        (offset < self.size()).then_some(offset as usize >> 2)
+    }
+}
+
+/// What sits in the RAM half of the address space.
+#[derive(Debug)]
+enum MemoryMap {
+    /// One `RiscvMem`, mirrored through the whole RAM space.
+    Mirrored(Vec<u32>),
+    /// Code and constants in a ROM that ignores writes, and a separate RAM.
+    Split { rom: Memory, ram: Memory },
+}
+
+impl fmt::Display for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryMap::Mirrored(ram) => write!(f, "{} byte RAM", ram.len() * 4),
+            MemoryMap::Split { rom, ram } => write!(
+                f,
+                "{} byte ROM at {:#010x} and {} byte RAM at {:#010x}",
+                rom.size(),
+                rom.origin,
+                ram.size(),
+                ram.origin
+            ),
+        }
+    }
+}
+
+/// `RiscvMem` plus the testbench's peripherals and clock.
+#[derive(Debug)]
+pub struct Bus {
+    memory: MemoryMap,
+    cycle: u64,
+    /// The LED latch.
+    pub leds: u8,
+    pub uat: Uat,
+}
+
+impl Bus {
+    /// Make a bus whose RAM is `depth` 32-bit words, like `RiscvMem`'s
+    /// `MEM_DEPTH`. The RAM is mirrored through the rest of the RAM space.
+    ///
+    /// # Panics
+    ///
+    /// If `depth` isn't a power of two, since `RiscvMem` can't be built that
+    /// way either.
+    pub fn new(depth: usize) -> Self {
+        assert!(
+            depth.is_power_of_two(),
+            "memory depth must be a power of two"
+        );
+        Self {
+            memory: MemoryMap::Mirrored(vec![0; depth]),
+            cycle: 0,
+            leds: 0,
+            uat: Uat::default(),
+        }
+    }
+
+    /// Replace the memory with a ROM covering `rom` and a RAM covering
+    /// `ram`, the way an execute-in-place linker script lays them out. The
+    /// ROM ignores writes once it has been [loaded](Self::load), and
+    /// addresses outside both read as zero.
+    ///
+    /// # Panics
+    ///
+    /// If either range isn't word-aligned, or they overlap.
+    pub fn split(&mut self, rom: Range<u32>, ram: Range<u32>) {
+        for range in [&rom, &ram] {
+            assert!(
+                (range.start | range.end) & 3 == 0,
+                "memory {range:#x?} isn't word-aligned"
+            );
+        }
+        assert!(
+            rom.end.wrapping_sub(1) < ram.start || ram.end.wrapping_sub(1) < rom.start,
+            "ROM {rom:#x?} overlaps RAM {ram:#x?}"
+        );
+        self.memory = MemoryMap::Split {
+            rom: Memory::new(rom),
+            ram: Memory::new(ram),
+        };
+    }
+
+    /// True if `size` bytes starting at `addr` are all backed by memory. In
+    /// the mirrored layout that means they fit in the RAM without wrapping.
+    pub fn contains(&self, addr: u32, size: u32) -> bool {
+        let end = addr as u64 + size as u64;
+        match &self.memory {
+            MemoryMap::Mirrored(ram) => end <= (ram.len() * 4) as u64,
+            MemoryMap::Split { rom, ram } => [rom, ram]
+                .iter()
+                .any(|m| addr >= m.origin && end <= m.origin as u64 + m.size() as u64),
+        }
+    }
+
+    /// A description of the memory, like "1024 byte RAM", for error messages.
+    pub fn describe_memory(&self) -> String {
+        self.memory.to_string()
+    }
+
+    /// The word holding `addr`, and whether the core may write it.
+    fn word_mut(&mut self, addr: u32) -> Option<(&mut u32, bool)> {
+        match &mut self.memory {
+            MemoryMap::Mirrored(ram) => {
+                let index = (addr as usize >> 2) & (ram.len() - 1);
+                Some((&mut ram[index], true))
+            }
+            MemoryMap::Split { rom, ram } => {
+                if let Some(index) = ram.index(addr) {
+                    Some((&mut ram.words[index], true))
+                } else {
+                    rom.index(addr).map(|index| (&mut rom.words[index], false))
+                }
+            }
+        }
+    }
+
+    /// Number of rising clock edges so far.
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Advance the clock by one cycle, clocking the peripherals with whatever
+    /// was written to them during it.
+    pub fn tick(&mut self) {
+        self.cycle += 1;
+        self.uat.tick();
+    }
+
+    /// Read the 32-bit word containing `addr`.
+    pub fn read(&mut self, addr: u32) -> u32 {
+        if addr & IO_BIT == 0 {
+            self.word_mut(addr).map_or(0, |(word, _)| *word)
+        } else if addr & 0x10 != 0 {
+            (self.uat.ready() as u32) << UAT_READY_BIT
+        } else {
+            0
+        }
+    }
+
+    /// The memory word containing `addr`, without clocking anything or
+    /// touching the peripherals, which read as zero here.
+    pub fn peek(&self, addr: u32) -> u32 {
+        if addr & IO_BIT != 0 {
+            return 0;
+        }
+        match &self.memory {
+            MemoryMap::Mirrored(ram) => ram[(addr as usize >> 2) & (ram.len() - 1)],
+            MemoryMap::Split { rom, ram } => ram
+                .index(addr)
+                .map(|index| ram.words[index])
+                .or_else(|| rom.index(addr).map(|index| rom.words[index]))
+                .unwrap_or(0),
+        }
+    }
+
+    /// Write the byte lanes of the word containing `addr` that are set in
+    /// `strobe`. `data` is already shifted into the right lanes.
+    pub fn write(&mut self, addr: u32, data: u32, strobe: u8) {
+        if addr & IO_BIT == 0 {
+            if let Some((word, true)) = self.word_mut(addr) {
+                merge_lanes(word, data, strobe);
+            }
+            return;
+        }
+        // The peripherals only look at the lowest byte lane.
+        if strobe & 1 == 0 {
+            return;
+        }
+        if addr & 0x04 != 0 {
+            self.leds = data as u8;
+        }
+        if addr & 0x08 != 0 {
+            self.uat.write(data as u8);
+        }
+    }
+
+    /// Copy bytes into memory, ROM included, as `$readmemh` would at time
+    /// zero. Bytes that land outside the memory are dropped.
+    pub fn load(&mut self, addr: u32, bytes: &[u8]) {
+        for (i, &b) in bytes.iter().enumerate() {
+            let a = addr.wrapping_add(i as u32);
+            let lane = a & 3;
+            if let Some((word, _)) = self.word_mut(a) {
+                merge_lanes(word, (b as u32) << (8 * lane), 1 << lane);
+            }
+        }
+    }
+}
+
+/// Replace the byte lanes of `word` that are set in `strobe` with those of
+/// `data`.
+fn merge_lanes(word: &mut u32, data: u32, strobe: u8) {
+    for lane in 0..4 {
+        if strobe & (1 << lane) != 0 {
+            let mask = 0xff << (8 * lane);
+            *word = (*word & !mask) | (data & mask);
+        }
+    }
+}
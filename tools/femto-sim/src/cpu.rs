@@ -0,0 +1,497 @@
+//! An RV32I hart.
+//!
+//! This follows the RV32I spec rather than any particular quirk of
+//! `RiscvFemto`, with two exceptions that match the hardware:
+//!
+//! - Every SYSTEM instruction (`ecall`, `ebreak`, and the CSR instructions)
+//!   halts the core, since `RiscvFemto` just stops advancing `pc` when it sees
+//!   one.
+//! - Misaligned accesses ignore the low address bits instead of trapping, as
+//!   there are no traps.
+//!
+//! Timing, on the other hand, is exactly `RiscvFemto`'s. Every instruction
+//! walks through the same states as the hardware state machine, clocking the
+//! bus once per state, so peripherals see reads and writes on the same cycles
+//! they would in `RiscvFemto_tb`:
+//!
+//! | Instruction | States                                                      | Cycles |
+//! | ----------- | ----------------------------------------------------------- | ------ |
+//! | Load        | `FETCH_INSTR` `WAIT_INSTR` `FETCH_REGS` `EXECUTE` `LOAD` `WAIT_DATA` | 6 |
+//! | Store       | `FETCH_INSTR` `WAIT_INSTR` `FETCH_REGS` `EXECUTE` `STORE`   | 5      |
+//! | Anything else | `FETCH_INSTR` `WAIT_INSTR` `FETCH_REGS` `EXECUTE`         | 4      |
+
+use crate::bus::Bus;
+
+/// Major opcodes, from bits 6:0 of the instruction. Grouped the same way as
+/// the decode in `RiscvFemto.sv`.
+#[allow(clippy::unusual_byte_groupings)]
+pub(crate) mod opcode {
+    pub const LOAD: u32 = 0b00_000_11;
+    pub const MISC_MEM: u32 = 0b00_011_11;
+    pub const OP_IMM: u32 = 0b00_100_11;
+    pub const AUIPC: u32 = 0b00_101_11;
+    pub const STORE: u32 = 0b01_000_11;
+    pub const OP: u32 = 0b01_100_11;
+    pub const LUI: u32 = 0b01_101_11;
+    pub const BRANCH: u32 = 0b11_000_11;
+    pub const JALR: u32 = 0b11_001_11;
+    pub const JAL: u32 = 0b11_011_11;
+    pub const SYSTEM: u32 = 0b11_100_11;
+}
+
+/// What kind of instruction was executed. This decides how many states the
+/// `RiscvFemto` state machine goes through for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Register/immediate arithmetic, `lui`, `auipc`, and `fence`.
+    Alu,
+    Branch,
+    Jump,
+    Load,
+    Store,
+    System,
+}
+
+impl Kind {
+    /// Clock cycles `RiscvFemto` spends on an instruction of this kind.
+    pub fn cycles(self) -> u32 {
+        match self {
+            Kind::Load => 6,
+            Kind::Store => 5,
+            _ => 4,
+        }
+    }
+}
+
+/// A single memory access made by a load or store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemAccess {
+    pub addr: u32,
+    /// For loads, the value written to `rd`. For stores, the data as it
+    /// appears on the bus, already shifted into the right byte lanes.
+    pub data: u32,
+    /// Byte lanes written by a store. Zero for loads.
+    pub strobe: u8,
+}
+
+/// The result of executing one instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retired {
+    pub pc: u32,
+    pub instr: u32,
+    pub kind: Kind,
+    pub next_pc: u32,
+    /// The register written and its new value, if any.
+    pub rd: Option<(u8, u32)>,
+    pub mem: Option<MemAccess>,
+}
+
+/// Why a step didn't retire an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The instruction at `pc` isn't part of RV32I.
+    IllegalInstruction { pc: u32, instr: u32 },
+}
+
+/// Architectural state of the hart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cpu {
+    pub regs: [u32; 32],
+    pub pc: u32,
+}
+
+impl Cpu {
+    /// A hart straight out of reset: everything zeroed, like the testbench's
+    /// `initial` blocks leave it.
+    pub fn new(pc: u32) -> Self {
+        Self { regs: [0; 32], pc }
+    }
+
+    fn set_reg(&mut self, rd: u32, value: u32) -> Option<(u8, u32)> {
+        if rd == 0 {
+            return None;
+        }
+        self.regs[rd as usize] = value;
+        Some((rd as u8, value))
+    }
+
+    /// Fetch and execute one instruction.
+    ///
+    /// SYSTEM instructions are reported with [`Kind::System`] and leave `pc`
+    /// where it is, so stepping again executes them again, just like the
+    /// hardware spins on them.
+    pub fn step(&mut self, bus: &mut Bus) -> Result<Retired, Fault> {
+        let pc = self.pc;
+        // FETCH_INSTR, WAIT_INSTR, FETCH_REGS, EXECUTE. Nothing in these
+        // states can affect a peripheral, so they're all clocked up front.
+        let instr = bus.read(pc);
+        for _ in 0..4 {
+            bus.tick();
+        }
+
+        let rd = (instr >> 7) & 0x1f;
+        let funct3 = (instr >> 12) & 0x7;
+        let rs1 = self.regs[((instr >> 15) & 0x1f) as usize];
+        let rs2 = self.regs[((instr >> 20) & 0x1f) as usize];
+        let funct7 = instr >> 25;
+
+        let imm_i = ((instr as i32) >> 20) as u32;
+        let imm_s = (((instr as i32) >> 25) << 5) as u32 | ((instr >> 7) & 0x1f);
+        let imm_b = (((instr as i32) >> 31) << 12) as u32
+            | ((instr << 4) & 0x800)
+            | ((instr >> 20) & 0x7e0)
+            | ((instr >> 7) & 0x1e);
+        let imm_u = instr & 0xffff_f000;
+        let imm_j = (((instr as i32) >> 31) << 20) as u32
+            | (instr & 0xf_f000)
+            | ((instr >> 9) & 0x800)
+            | ((instr >> 20) & 0x7fe);
+
+        let illegal = Fault::IllegalInstruction { pc, instr };
+        let pc4 = pc.wrapping_add(4);
+        let mut retired = Retired {
+            pc,
+            instr,
+            kind: Kind::Alu,
+            next_pc: pc4,
+            rd: None,
+            mem: None,
+        };
+
+        match instr & 0x7f {
+            opcode::LUI => retired.rd = self.set_reg(rd, imm_u),
+            opcode::AUIPC => retired.rd = self.set_reg(rd, pc.wrapping_add(imm_u)),
+            opcode::JAL => {
+                retired.kind = Kind::Jump;
+                retired.next_pc = pc.wrapping_add(imm_j);
+                retired.rd = self.set_reg(rd, pc4);
+            }
+            opcode::JALR => {
+                if funct3 != 0 {
+                    return Err(illegal);
+                }
+                retired.kind = Kind::Jump;
+                retired.next_pc = rs1.wrapping_add(imm_i) & !1;
+                retired.rd = self.set_reg(rd, pc4);
+            }
+            opcode::BRANCH => {
+                let taken = match funct3 {
+                    0b000 => rs1 == rs2,
+                    0b001 => rs1 != rs2,
+                    0b100 => (rs1 as i32) < (rs2 as i32),
+                    0b101 => (rs1 as i32) >= (rs2 as i32),
+                    0b110 => rs1 < rs2,
+                    0b111 => rs1 >= rs2,
+                    _ => return Err(illegal),
+                };
+                retired.kind = Kind::Branch;
+                if taken {
+                    retired.next_pc = pc.wrapping_add(imm_b);
+                }
+            }
+            opcode::LOAD => {
+                let addr = rs1.wrapping_add(imm_i);
+                // LOAD puts the address out, and the data comes back during
+                // WAIT_DATA.
+                bus.tick();
+                let word = bus.read(addr);
+                bus.tick();
+                let half = if addr & 2 != 0 {
+                    word >> 16
+                } else {
+                    word & 0xffff
+                };
+                let byte = if addr & 1 != 0 {
+                    half >> 8
+                } else {
+                    half & 0xff
+                };
+                let value = match funct3 {
+                    0b000 => byte as u8 as i8 as u32,
+                    0b001 => half as u16 as i16 as u32,
+                    0b010 => word,
+                    0b100 => byte,
+                    0b101 => half,
+                    _ => return Err(illegal),
+                };
+                retired.kind = Kind::Load;
+                retired.rd = self.set_reg(rd, value);
+                retired.mem = Some(MemAccess {
+                    addr,
+                    data: value,
+                    strobe: 0,
+                });
+            }
+            opcode::STORE => {
+                let addr = rs1.wrapping_add(imm_s);
+                let (data, strobe) = match funct3 {
+                    0b000 => ((rs2 & 0xff) * 0x0101_0101, 1 << (addr & 3)),
+                    0b001 => ((rs2 & 0xffff) * 0x0001_0001, 0b11 << (addr & 2)),
+                    0b010 => (rs2, 0b1111),
+                    _ => return Err(illegal),
+                };
+                // STORE
+                bus.write(addr, data, strobe);
+                bus.tick();
+                retired.kind = Kind::Store;
+                retired.mem = Some(MemAccess { addr, data, strobe });
+            }
+            opcode::OP_IMM => {
+                let shift_ok = match funct3 {
+                    0b001 => funct7 == 0,
+                    0b101 => funct7 & !0b010_0000 == 0,
+                    _ => true,
+                };
+                if !shift_ok {
+                    return Err(illegal);
+                }
+                // SRAI is told apart from SRLI by the same bit as SRA/SRL.
+                let value = alu(
+                    funct3,
+                    funct7 & 0b010_0000 != 0 && funct3 == 0b101,
+                    rs1,
+                    imm_i,
+                );
+                retired.rd = self.set_reg(rd, value);
+            }
+            opcode::OP => {
+                if funct7 & !0b010_0000 != 0 || (funct7 != 0 && funct3 != 0b000 && funct3 != 0b101)
+                {
+                    return Err(illegal);
+                }
+                let value = alu(funct3, funct7 != 0, rs1, rs2);
+                retired.rd = self.set_reg(rd, value);
+            }
+            // `fence` has nothing to order on a single in-order hart.
+            opcode::MISC_MEM => {}
+            opcode::SYSTEM => {
+                retired.kind = Kind::System;
+                retired.next_pc = pc;
+            }
+            _ => return Err(illegal),
+        }
+
+        self.pc = retired.next_pc;
+        Ok(retired)
+    }
+}
+
+/// The shared ALU for `OP` and `OP-IMM`. `alt` selects `sub` and `sra`.
+fn alu(funct3: u32, alt: bool, a: u32, b: u32) -> u32 {
+    match funct3 {
+        0b000 if alt => a.wrapping_sub(b),
+        0b000 => a.wrapping_add(b),
+        0b001 => a << (b & 0x1f),
+        0b010 => ((a as i32) < (b as i32)) as u32,
+        0b011 => (a < b) as u32,
+        0b100 => a ^ b,
+        0b101 if alt => ((a as i32) >> (b & 0x1f)) as u32,
+        0b101 => a >> (b & 0x1f),
+        0b110 => a | b,
+        _ => a & b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: u32 = 0x100;
+
+    fn i_type(opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: i32) -> u32 {
+        ((imm as u32 & 0xfff) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+    }
+
+    fn s_type(funct3: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+        let imm = imm as u32;
+        ((imm >> 5 & 0x7f) << 25)
+            | (rs2 << 20)
+            | (rs1 << 15)
+            | (funct3 << 12)
+            | ((imm & 0x1f) << 7)
+            | opcode::STORE
+    }
+
+    fn r_type(funct7: u32, funct3: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+        (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode::OP
+    }
+
+    /// A hart at 0 with `program` loaded there, and `data` at [`DATA`].
+    fn load(program: &[u32], data: u32) -> (Cpu, Bus) {
+        let mut bus = Bus::new(256);
+        let bytes: Vec<u8> = program.iter().flat_map(|w| w.to_le_bytes()).collect();
+        bus.load(0, &bytes);
+        bus.load(DATA, &data.to_le_bytes());
+        (Cpu::new(0), bus)
+    }
+
+    /// Run `program` to the end, returning the value every instruction wrote
+    /// to `rd`.
+    fn results(program: &[u32], data: u32, regs: &[(usize, u32)]) -> Vec<u32> {
+        let (mut cpu, mut bus) = load(program, data);
+        for &(reg, value) in regs {
+            cpu.regs[reg] = value;
+        }
+        program
+            .iter()
+            .map(|_| cpu.step(&mut bus).unwrap().rd.unwrap().1)
+            .collect()
+    }
+
+    #[test]
+    fn loads_sign_and_zero_extend() {
+        let load = |funct3, offset| i_type(opcode::LOAD, funct3, 1, 0, DATA as i32 + offset);
+        let program = [
+            load(0b000, 0), // lb
+            load(0b100, 0), // lbu
+            load(0b000, 1), // lb, positive
+            load(0b001, 0), // lh, positive
+            load(0b001, 2), // lh
+            load(0b101, 2), // lhu
+            load(0b100, 3), // lbu
+            load(0b010, 0), // lw
+        ];
+        assert_eq!(
+            results(&program, 0x80f0_7f81, &[]),
+            [
+                0xffff_ff81,
+                0x0000_0081,
+                0x0000_007f,
+                0x0000_7f81,
+                0xffff_80f0,
+                0x0000_80f0,
+                0x0000_0080,
+                0x80f0_7f81,
+            ]
+        );
+    }
+
+    #[test]
+    fn stores_only_write_their_byte_lanes() {
+        let program = [
+            s_type(0b000, 0, 1, DATA as i32 + 1), // sb
+            s_type(0b001, 0, 1, DATA as i32 + 2), // sh
+        ];
+        let (mut cpu, mut bus) = load(&program, 0xaaaa_aaaa);
+        cpu.regs[1] = 0x1122_3344;
+
+        let sb = cpu.step(&mut bus).unwrap().mem.unwrap();
+        assert_eq!(
+            (sb.addr, sb.data, sb.strobe),
+            (DATA + 1, 0x4444_4444, 0b0010)
+        );
+        assert_eq!(bus.peek(DATA), 0xaaaa_44aa);
+
+        let sh = cpu.step(&mut bus).unwrap().mem.unwrap();
+        assert_eq!(
+            (sh.addr, sh.data, sh.strobe),
+            (DATA + 2, 0x3344_3344, 0b1100)
+        );
+        assert_eq!(bus.peek(DATA), 0x3344_44aa);
+    }
+
+    #[test]
+    fn sra_keeps_the_sign_and_srl_doesnt() {
+        let program = [
+            r_type(0b010_0000, 0b101, 3, 1, 2),             // sra
+            r_type(0, 0b101, 3, 1, 2),                      // srl
+            i_type(opcode::OP_IMM, 0b101, 3, 1, 0x400 | 4), // srai
+            i_type(opcode::OP_IMM, 0b101, 3, 1, 4),         // srli
+            r_type(0b010_0000, 0b101, 3, 1, 4),             // sra by 36
+        ];
+        let regs = [(1, 0x8000_0010), (2, 4), (4, 36)];
+        assert_eq!(
+            results(&program, 0, &regs),
+            [
+                0xf800_0001,
+                0x0800_0001,
+                0xf800_0001,
+                0x0800_0001,
+                0xf800_0001
+            ]
+        );
+    }
+
+    #[test]
+    fn cycles_follow_the_state_machine() {
+        let program = [
+            i_type(opcode::LOAD, 0b010, 1, 0, DATA as i32), // lw
+            s_type(0b010, 0, 1, DATA as i32),               // sw
+            i_type(opcode::OP_IMM, 0b000, 1, 1, 1),         // addi
+            r_type(0, 0b000, 1, 1, 1),                      // add
+            0x0000_0037 | (1 << 7),                         // lui
+            0x0000_0463,                                    // beq x0, x0, +8
+            0x0000_0000,                                    // skipped
+            0x0040_006f,                                    // jal x0, +4
+            0x0000_0073,                                    // ecall
+        ];
+        let (mut cpu, mut bus) = load(&program, 0);
+        let expected = [
+            (Kind::Load, 6),
+            (Kind::Store, 5),
+            (Kind::Alu, 4),
+            (Kind::Alu, 4),
+            (Kind::Alu, 4),
+            (Kind::Branch, 4),
+            (Kind::Jump, 4),
+            (Kind::System, 4),
+        ];
+        for (kind, cycles) in expected {
+            let before = bus.cycle();
+            let retired = cpu.step(&mut bus).unwrap();
+            assert_eq!(retired.kind, kind, "at {:#x}", retired.pc);
+            assert_eq!(bus.cycle() - before, cycles, "{kind:?}");
+            assert_eq!(kind.cycles() as u64, cycles);
+        }
+    }
+
+    #[test]
+    fn system_instructions_halt_in_place() {
+        for instr in [
+            0x0000_0073, // ecall
+            0x0010_0073, // ebreak
+            0x3400_9073, // csrw mscratch, x1
+            0x3002_e073, // csrsi mstatus, 5
+        ] {
+            let (mut cpu, mut bus) = load(&[instr], 0);
+            for _ in 0..3 {
+                let retired = cpu.step(&mut bus).unwrap();
+                assert_eq!(retired.kind, Kind::System, "{instr:#010x}");
+                assert_eq!((retired.next_pc, cpu.pc), (0, 0));
+                assert_eq!(retired.rd, None);
+            }
+        }
+    }
+
+    #[test]
+    fn illegal_instructions_fault_without_moving() {
+        for instr in [
+            0x0000_0000,                                    // all zeros
+            0xffff_ffff,                                    // all ones
+            0x0000_0007,                                    // flw, no F extension
+            r_type(0b000_0001, 0b000, 1, 1, 1),             // mul, no M extension
+            r_type(0b010_0000, 0b001, 1, 1, 1),             // sll with sub's funct7
+            i_type(opcode::OP_IMM, 0b001, 1, 1, 0x400 | 4), // slli with srai's bit
+            i_type(opcode::LOAD, 0b011, 1, 0, 0),           // ld
+            s_type(0b011, 0, 1, 0),                         // sd
+            i_type(opcode::JALR, 0b001, 1, 1, 0),           // jalr, funct3 1
+            0x0000_2063,                                    // branch, funct3 2
+        ] {
+            let (mut cpu, mut bus) = load(&[instr], 0);
+            assert_eq!(
+                cpu.step(&mut bus),
+                Err(Fault::IllegalInstruction { pc: 0, instr }),
+                "{instr:#010x}"
+            );
+            assert_eq!(cpu.pc, 0);
+            assert_eq!(cpu.regs, [0; 32]);
+        }
+    }
+
+    #[test]
+    fn x0_stays_zero() {
+        let (mut cpu, mut bus) = load(&[i_type(opcode::OP_IMM, 0b000, 0, 0, 5)], 0);
+        assert_eq!(cpu.step(&mut bus).unwrap().rd, None);
+        assert_eq!(cpu.regs[0], 0);
+    }
+}
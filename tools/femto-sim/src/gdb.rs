@@ -0,0 +1,891 @@
+//! A GDB remote serial protocol stub.
+//!
+//! This lets `gdb-multiarch` or `riscv64-unknown-elf-gdb` debug firmware
+//! running in the simulator with `target remote`, the same way it would
+//! debug a board through OpenOCD:
+//!
+//! ```text
+//! $ femto-sim --gdb 3333 program.elf
+//! $ gdb-multiarch -ex 'target remote :3333' program.elf
+//! ```
+//!
+//! The stub supports reading and writing registers and memory, software
+//! breakpoints, single-stepping, continuing, and read, write and access
+//! watchpoints. Memory goes through the same [`Bus`](crate::bus::Bus) as the
+//! core does, so `x/x 0x400010` shows the UAT status the firmware would see
+//! at that point.
+//!
+//! Breakpoints and watchpoints are kept by the stub rather than patched into
+//! memory, and are checked before each instruction, like the triggers of the
+//! RISC-V debug spec: a watchpoint stops the core on the load or store that
+//! would touch it, before it does. The first instruction after a resume is
+//! never stopped on, so continuing from a breakpoint doesn't just hit it
+//! again.
+//!
+//! When the core halts on a SYSTEM instruction, GDB sees it stop with
+//! `SIGTRAP` there, so the registers and the backtrace of whatever called
+//! `exit` or panicked can still be looked at. Resuming after that reports
+//! that the program exited, with the code from `a0`, and ends the session.
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+use femto_disasm::Instr;
+
+use crate::bus::IO_BIT;
+use crate::{Exit, Sim};
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+/// GDB's register number for `pc`, after `x0` to `x31`.
+const PC: usize = 32;
+
+/// Largest packet GDB may send, and the most memory a single `m` packet
+/// reads.
+const PACKET_SIZE: usize = 0x1000;
+
+/// How many instructions go by between checks for a Ctrl-C from GDB.
+const INTERRUPT_CHECK: u64 = 1024;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+<architecture>riscv:rv32</architecture>
+<feature name="org.gnu.gdb.riscv.cpu">
+"#;
+
+/// The kind of access a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Watch {
+    Write,
+    Read,
+    Access,
+}
+
+impl Watch {
+    /// The `Z` packet type for this kind of watchpoint.
+    fn from_type(kind: u32) -> Option<Self> {
+        match kind {
+            2 => Some(Watch::Write),
+            3 => Some(Watch::Read),
+            4 => Some(Watch::Access),
+            _ => None,
+        }
+    }
+
+    /// The stop reason GDB expects when this kind of watchpoint triggers.
+    fn reason(self) -> &'static str {
+        match self {
+            Watch::Write => "watch",
+            Watch::Read => "rwatch",
+            Watch::Access => "awatch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Watchpoint {
+    kind: Watch,
+    addr: u32,
+    len: u32,
+}
+
+/// Why a resume stopped.
+enum Stop {
+    Signal(u8),
+    Breakpoint,
+    Watchpoint(Watch, u32),
+    /// The core was resumed while halted, so the program is over.
+    Exited {
+        code: u32,
+    },
+}
+
+/// Serve GDB on `stream` until the program exits, or GDB detaches or kills
+/// it. `on_step` is called after every instruction, to pass on UAT output
+/// and the like.
+///
+/// Returns the reason the program ended, or `None` if GDB detached and left
+/// it to run on its own. A dropped connection counts as [`Exit::Killed`].
+pub fn serve(
+    sim: &mut Sim,
+    stream: TcpStream,
+    on_step: impl FnMut(&mut Sim),
+) -> io::Result<Option<Exit>> {
+    let mut stub = Stub {
+        sim,
+        conn: Connection {
+            stream,
+            input: Vec::new(),
+            ack: true,
+        },
+        on_step,
+        breakpoints: BTreeSet::new(),
+        watchpoints: Vec::new(),
+        halted: false,
+        swbreak: false,
+        last_stop: format!("S{SIGTRAP:02x}"),
+    };
+    match stub.serve() {
+        Err(e) if disconnected(&e) => Ok(Some(Exit::Killed)),
+        result => result,
+    }
+}
+
+fn disconnected(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+    )
+}
+
+struct Stub<'a, F> {
+    sim: &'a mut Sim,
+    conn: Connection,
+    on_step: F,
+    breakpoints: BTreeSet<u32>,
+    watchpoints: Vec<Watchpoint>,
+    /// The core stopped on a SYSTEM instruction, and will spin on it forever.
+    halted: bool,
+    /// GDB understands the `swbreak` stop reason.
+    swbreak: bool,
+    last_stop: String,
+}
+
+impl<F: FnMut(&mut Sim)> Stub<'_, F> {
+    fn serve(&mut self) -> io::Result<Option<Exit>> {
+        loop {
+            let packet = self.conn.receive()?;
+            let (command, args) = packet.split_at(packet.len().min(1));
+            let reply = match command {
+                "?" => self.last_stop.clone(),
+                "g" => self.read_registers(),
+                "G" => self.write_registers(args),
+                "p" => self.read_register(args),
+                "P" => self.write_register(args),
+                "m" => self.read_memory(args),
+                "M" => self.write_memory(args),
+                "Z" | "z" => self.breakpoint(command == "Z", args),
+                "c" | "s" | "C" | "S" => {
+                    // `c addr` resumes at addr. The signal in `C sig` has
+                    // nowhere to go, so it's dropped.
+                    let addr = match command {
+                        "c" | "s" => args,
+                        _ => args.split_once(';').map_or("", |(_, addr)| addr),
+                    };
+                    if let Some(pc) = parse_hex(addr) {
+                        self.set_pc(pc);
+                    }
+                    match self.resume(matches!(command, "s" | "S"))? {
+                        Stop::Exited { code } => {
+                            self.conn.send(&format!("W{:02x}", code as u8))?;
+                            let pc = self.sim.cpu.pc;
+                            return Ok(Some(Exit::Halted { pc, code }));
+                        }
+                        stop => {
+                            self.last_stop = self.stop_reply(stop);
+                            self.last_stop.clone()
+                        }
+                    }
+                }
+                "D" => {
+                    self.conn.send("OK")?;
+                    return Ok(None);
+                }
+                "k" => return Ok(Some(Exit::Killed)),
+                "H" => "OK".to_string(),
+                "q" | "Q" | "v" => match self.query(&packet) {
+                    Some(reply) => reply,
+                    None => {
+                        self.conn.send("OK")?;
+                        return Ok(Some(Exit::Killed));
+                    }
+                },
+                _ => String::new(),
+            };
+            self.conn.send(&reply)?;
+            // GDB acknowledges the OK, and nothing after it.
+            if packet == "QStartNoAckMode" {
+                self.conn.ack = false;
+            }
+        }
+    }
+
+    /// Answer a `q`, `Q` or `v` packet. Returns `None` for `vKill`.
+    fn query(&mut self, packet: &str) -> Option<String> {
+        let (name, args) = packet.split_once([':', ';']).unwrap_or((packet, ""));
+        let reply = match name {
+            "qSupported" => {
+                self.swbreak = args.split(';').any(|feature| feature == "swbreak+");
+                format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+;swbreak+;QStartNoAckMode+")
+            }
+            "QStartNoAckMode" => "OK".to_string(),
+            "qAttached" => "1".to_string(),
+            "qXfer" => match args.strip_prefix("features:read:target.xml:") {
+                Some(range) => read_part(&target_xml(), range),
+                None => String::new(),
+            },
+            "vKill" => return None,
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn stop_reply(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Signal(signal) => format!("S{signal:02x}"),
+            Stop::Breakpoint if self.swbreak => format!("T{SIGTRAP:02x}swbreak:;"),
+            Stop::Breakpoint => format!("S{SIGTRAP:02x}"),
+            Stop::Watchpoint(kind, addr) => {
+                format!("T{SIGTRAP:02x}{}:{addr:x};", kind.reason())
+            }
+            Stop::Exited { .. } => unreachable!("exits aren't stops"),
+        }
+    }
+
+    /// Run until something stops the core, or for one instruction if
+    /// `step` is set.
+    fn resume(&mut self, step: bool) -> io::Result<Stop> {
+        if self.halted {
+            return Ok(Stop::Exited {
+                code: self.sim.cpu.regs[10],
+            });
+        }
+        let mut first = true;
+        loop {
+            if !first {
+                if self.breakpoints.contains(&self.sim.cpu.pc) {
+                    return Ok(Stop::Breakpoint);
+                }
+                if let Some((kind, addr)) = self.watchpoint_hit() {
+                    return Ok(Stop::Watchpoint(kind, addr));
+                }
+                if step {
+                    return Ok(Stop::Signal(SIGTRAP));
+                }
+                if self.sim.instructions().is_multiple_of(INTERRUPT_CHECK)
+                    && self.conn.interrupted()?
+                {
+                    return Ok(Stop::Signal(SIGINT));
+                }
+            }
+            first = false;
+            let exit = self.sim.step();
+            (self.on_step)(self.sim);
+            match exit {
+                Some(Exit::Halted { .. }) => {
+                    self.halted = true;
+                    return Ok(Stop::Signal(SIGTRAP));
+                }
+                Some(Exit::IllegalInstruction { .. }) => return Ok(Stop::Signal(SIGILL)),
+                Some(_) => return Ok(Stop::Signal(SIGTRAP)),
+                None => {}
+            }
+        }
+    }
+
+    /// The watchpoint the load or store at `pc` is about to touch, if any,
+    /// and the address it touches.
+    fn watchpoint_hit(&self) -> Option<(Watch, u32)> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+        let cpu = &self.sim.cpu;
+        let word = self.sim.bus.peek(cpu.pc);
+        let instr = Instr::decode(word)?;
+        let kind = match instr.op {
+            op if op.is_load() => Watch::Read,
+            op if op.is_store() => Watch::Write,
+            _ => return None,
+        };
+        let addr = cpu.regs[instr.rs1 as usize].wrapping_add(instr.imm as u32);
+        let len = 1 << ((word >> 12) & 3);
+        self.watchpoints.iter().find_map(|w| {
+            let overlaps = addr < w.addr.wrapping_add(w.len) && w.addr < addr.wrapping_add(len);
+            (overlaps && (w.kind == kind || w.kind == Watch::Access))
+                .then_some((w.kind, addr.max(w.addr)))
+        })
+    }
+
+    fn set_pc(&mut self, pc: u32) {
+        if pc != self.sim.cpu.pc {
+            self.halted = false;
+        }
+        self.sim.cpu.pc = pc;
+    }
+
+    fn register(&self, n: usize) -> Option<u32> {
+        match n {
+            0..=31 => Some(self.sim.cpu.regs[n]),
+            PC => Some(self.sim.cpu.pc),
+            _ => None,
+        }
+    }
+
+    fn set_register(&mut self, n: usize, value: u32) -> bool {
+        match n {
+            // x0 is hardwired, so writes to it quietly go nowhere.
+            0 => {}
+            1..=31 => self.sim.cpu.regs[n] = value,
+            PC => self.set_pc(value),
+            _ => return false,
+        }
+        true
+    }
+
+    fn read_registers(&self) -> String {
+        (0..=PC)
+            .filter_map(|n| self.register(n))
+            .fold(String::new(), |mut out, value| {
+                push_hex(&mut out, &value.to_le_bytes());
+                out
+            })
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let Some(bytes) = parse_bytes(args).filter(|b| b.len() >= 4 * (PC + 1)) else {
+            return "E01".to_string();
+        };
+        for (n, value) in bytes.chunks_exact(4).take(PC + 1).enumerate() {
+            self.set_register(n, u32::from_le_bytes(value.try_into().unwrap()));
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        match parse_hex(args).and_then(|n| self.register(n as usize)) {
+            Some(value) => {
+                let mut out = String::new();
+                push_hex(&mut out, &value.to_le_bytes());
+                out
+            }
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let written = args.split_once('=').and_then(|(n, value)| {
+            let n = parse_hex(n)? as usize;
+            let value: [u8; 4] = parse_bytes(value)?.try_into().ok()?;
+            Some(self.set_register(n, u32::from_le_bytes(value)))
+        });
+        match written {
+            Some(true) => "OK".to_string(),
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// `m addr,len`. Peripherals read the same as they do for a load.
+    fn read_memory(&mut self, args: &str) -> String {
+        let Some((addr, len)) = parse_range(args) else {
+            return "E01".to_string();
+        };
+        let len = len.min(PACKET_SIZE as u32 / 2);
+        let bytes: Vec<u8> = (0..len)
+            .map(|i| {
+                let addr = addr.wrapping_add(i);
+                (self.sim.bus.read(addr) >> (8 * (addr & 3))) as u8
+            })
+            .collect();
+        let mut out = String::new();
+        push_hex(&mut out, &bytes);
+        out
+    }
+
+    /// `M addr,len:bytes`. Memory writes go in even if it's ROM, like a flash
+    /// programmer would do it, and peripheral writes act like a store.
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| {
+            let (addr, len) = parse_range(range)?;
+            let bytes = parse_bytes(data)?;
+            (bytes.len() == len as usize).then_some((addr, bytes))
+        });
+        let Some((addr, bytes)) = parsed else {
+            return "E01".to_string();
+        };
+        for (i, &byte) in bytes.iter().enumerate() {
+            let addr = addr.wrapping_add(i as u32);
+            if addr & IO_BIT != 0 {
+                let lane = addr & 3;
+                self.sim
+                    .bus
+                    .write(addr, (byte as u32) << (8 * lane), 1 << lane);
+            } else {
+                self.sim.bus.load(addr, &[byte]);
+            }
+        }
+        "OK".to_string()
+    }
+
+    /// `Z type,addr,kind` and `z type,addr,kind`. Hardware breakpoints are
+    /// the same thing as software ones here.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',').map(parse_hex);
+        let (Some(Some(kind)), Some(Some(addr)), Some(Some(len))) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return "E01".to_string();
+        };
+        match (kind, Watch::from_type(kind)) {
+            (0 | 1, _) if insert => {
+                self.breakpoints.insert(addr);
+            }
+            (0 | 1, _) => {
+                self.breakpoints.remove(&addr);
+            }
+            (_, Some(kind)) => {
+                let watchpoint = Watchpoint { kind, addr, len };
+                if insert {
+                    self.watchpoints.push(watchpoint);
+                } else if let Some(i) = self.watchpoints.iter().position(|w| *w == watchpoint) {
+                    self.watchpoints.remove(i);
+                }
+            }
+            _ => return String::new(),
+        }
+        "OK".to_string()
+    }
+}
+
+/// The target description, which tells GDB it's talking to an RV32 core
+/// with `x0` to `x31` and `pc`, and nothing else.
+fn target_xml() -> String {
+    let mut xml = TARGET_XML.to_string();
+    for (n, name) in femto_disasm::REGISTER_NAMES.iter().enumerate() {
+        let kind = match n {
+            1 => "code_ptr",
+            2 => "data_ptr",
+            _ => "int",
+        };
+        writeln!(
+            xml,
+            r#"<reg name="{name}" bitsize="32" type="{kind}" regnum="{n}"/>"#
+        )
+        .unwrap();
+    }
+    writeln!(
+        xml,
+        r#"<reg name="pc" bitsize="32" type="code_ptr" regnum="{PC}"/>"#
+    )
+    .unwrap();
+    xml + "</feature>\n</target>\n"
+}
+
+/// The part of `data` a `qXfer` read asks for with `offset,length`.
+fn read_part(data: &str, range: &str) -> String {
+    let Some((offset, len)) = parse_range(range) else {
+        return "E01".to_string();
+    };
+    let start = (offset as usize).min(data.len());
+    let end = start.saturating_add(len as usize).min(data.len());
+    let marker = if end == data.len() { 'l' } else { 'm' };
+    format!("{marker}{}", &data[start..end])
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    u32::from_str_radix(s, 16).ok()
+}
+
+/// `addr,len`, both in hex.
+fn parse_range(s: &str) -> Option<(u32, u32)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+fn parse_bytes(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn push_hex(out: &mut String, bytes: &[u8]) {
+    for b in bytes {
+        write!(out, "{b:02x}").unwrap();
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+/// The packet layer: `$data#checksum` framing, and `+`/`-` acknowledgements
+/// until GDB turns them off.
+struct Connection {
+    stream: TcpStream,
+    /// Bytes received but not used yet.
+    input: Vec<u8>,
+    ack: bool,
+}
+
+impl Connection {
+    /// Read whatever has arrived into `input`, waiting for something if
+    /// `wait` is set.
+    fn fill(&mut self, wait: bool) -> io::Result<()> {
+        let mut buf = [0; PACKET_SIZE];
+        if !wait {
+            self.stream.set_nonblocking(true)?;
+        }
+        let result = self.stream.read(&mut buf);
+        if !wait {
+            self.stream.set_nonblocking(false)?;
+        }
+        match result {
+            Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                self.input.extend_from_slice(&buf[..n]);
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether GDB has sent a Ctrl-C, without waiting for one.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.fill(false)?;
+        match self.input.iter().position(|&b| b == 0x03) {
+            Some(i) => {
+                self.input.remove(i);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// The next packet, with its framing taken off. Anything between
+    /// packets, like a Ctrl-C that came too late to matter, is dropped.
+    fn receive(&mut self) -> io::Result<String> {
+        loop {
+            match self.input.iter().position(|&b| b == b'$') {
+                Some(start) => {
+                    self.input.drain(..start);
+                }
+                None => {
+                    self.input.clear();
+                    self.fill(true)?;
+                    continue;
+                }
+            }
+            let Some(end) = self.input.iter().position(|&b| b == b'#') else {
+                self.fill(true)?;
+                continue;
+            };
+            if self.input.len() < end + 3 {
+                self.fill(true)?;
+                continue;
+            }
+            let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+            let data = &packet[1..end];
+            let sum = std::str::from_utf8(&packet[end + 1..])
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            if self.ack {
+                let good = sum == Some(checksum(data));
+                self.stream.write_all(if good { b"+" } else { b"-" })?;
+                if !good {
+                    continue;
+                }
+            }
+            return Ok(String::from_utf8_lossy(data).into_owned());
+        }
+    }
+
+    /// Send a packet, and wait for GDB to acknowledge it if it's doing that.
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${data}#{:02x}", checksum(data.as_bytes()));
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            if !self.ack {
+                return Ok(());
+            }
+            loop {
+                if self.input.is_empty() {
+                    self.fill(true)?;
+                }
+                match self.input[0] {
+                    b'+' => {
+                        self.input.remove(0);
+                        return Ok(());
+                    }
+                    b'-' => {
+                        self.input.remove(0);
+                        break;
+                    }
+                    // GDB has moved on to the next packet.
+                    b'$' => return Ok(()),
+                    _ => {
+                        self.input.remove(0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Stores 5 at 0x100, loads it back, then counts up in `a3` forever.
+    const LOOP: [u32; 6] = [
+        0x0050_0513, // 0x00: li   a0, 5
+        0x1000_0593, // 0x04: li   a1, 0x100
+        0x00a5_a023, // 0x08: sw   a0, 0(a1)
+        0x0005_a603, // 0x0c: lw   a2, 0(a1)
+        0x0016_8693, // 0x10: addi a3, a3, 1
+        0xffdf_f06f, // 0x14: j    0x10
+    ];
+
+    /// Exits with 7.
+    const EXIT: [u32; 2] = [
+        0x0070_0513, // li    a0, 7
+        0x0000_0073, // ecall
+    ];
+
+    /// The GDB end of a session, which checks the stub's side of the framing
+    /// as it goes.
+    struct Gdb {
+        stream: TcpStream,
+        ack: bool,
+    }
+
+    impl Gdb {
+        fn connect(addr: SocketAddr) -> Self {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_nodelay(true).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            Self { stream, ack: true }
+        }
+
+        fn byte(&mut self) -> u8 {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+
+        fn send(&mut self, data: &str) {
+            let packet = format!("${data}#{:02x}", checksum(data.as_bytes()));
+            self.stream.write_all(packet.as_bytes()).unwrap();
+            if self.ack {
+                assert_eq!(self.byte(), b'+', "`{data}` wasn't acknowledged");
+            }
+        }
+
+        fn reply(&mut self) -> String {
+            assert_eq!(self.byte(), b'$', "expected a packet");
+            let mut data = Vec::new();
+            loop {
+                match self.byte() {
+                    b'#' => break,
+                    b => data.push(b),
+                }
+            }
+            let sum = [self.byte(), self.byte()];
+            let sum = u8::from_str_radix(std::str::from_utf8(&sum).unwrap(), 16).unwrap();
+            assert_eq!(sum, checksum(&data), "bad checksum");
+            if self.ack {
+                self.stream.write_all(b"+").unwrap();
+            }
+            String::from_utf8(data).unwrap()
+        }
+
+        fn ask(&mut self, data: &str) -> String {
+            self.send(data);
+            self.reply()
+        }
+
+        /// Register `n` from a `g` reply, as GDB sends it.
+        fn register(&mut self, n: usize) -> String {
+            self.ask("g")[8 * n..8 * (n + 1)].to_string()
+        }
+    }
+
+    /// Serve `program` to `client` over a localhost connection, and return
+    /// what [`serve`] did. A failed check in `client` fails the test.
+    fn session(program: &[u32], client: impl FnOnce(Gdb) + Send + 'static) -> Option<Exit> {
+        let mut sim = Sim::new(256);
+        let bytes: Vec<u8> = program.iter().flat_map(|w| w.to_le_bytes()).collect();
+        sim.bus.load(0, &bytes);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let gdb = thread::spawn(move || client(Gdb::connect(addr)));
+        let (stream, _) = listener.accept().unwrap();
+        // Without this, every small packet waits on a delayed ACK.
+        stream.set_nodelay(true).unwrap();
+        let exit = serve(&mut sim, stream, |_| {}).unwrap();
+        if let Err(panic) = gdb.join() {
+            std::panic::resume_unwind(panic);
+        }
+        exit
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let exit = session(&LOOP, |mut gdb| {
+            assert_eq!(
+                gdb.ask("qSupported:multiprocess+;swbreak+"),
+                "PacketSize=1000;qXfer:features:read+;swbreak+;QStartNoAckMode+"
+            );
+            assert_eq!(gdb.ask("?"), "S05");
+            assert_eq!(gdb.ask("g"), "0".repeat(8 * 33));
+            assert_eq!(gdb.ask("m0,8"), "1305500093050010");
+            assert_eq!(gdb.ask("M200,4:deadbeef"), "OK");
+            assert_eq!(gdb.ask("m200,4"), "deadbeef");
+            assert_eq!(gdb.ask("M200,4:dead"), "E01");
+            assert_eq!(gdb.ask("Pb=78563412"), "OK");
+            assert_eq!(gdb.ask("pb"), "78563412");
+            assert_eq!(gdb.ask("P0=78563412"), "OK");
+            assert_eq!(gdb.ask("p0"), "00000000");
+            assert_eq!(gdb.ask("p21"), "E01");
+            assert_eq!(gdb.ask("D"), "OK");
+        });
+        assert_eq!(exit, None);
+    }
+
+    #[test]
+    fn breakpoints_steps_and_watchpoints() {
+        let exit = session(&LOOP, |mut gdb| {
+            gdb.ask("qSupported:swbreak+");
+            assert_eq!(gdb.ask("Z0,c,4"), "OK");
+            assert_eq!(gdb.ask("c"), "T05swbreak:;");
+            assert_eq!(gdb.register(10), "05000000");
+            assert_eq!(gdb.register(PC), "0c000000");
+            assert_eq!(gdb.ask("z0,c,4"), "OK");
+
+            assert_eq!(gdb.ask("s"), "S05");
+            assert_eq!(gdb.register(12), "05000000");
+            assert_eq!(gdb.register(PC), "10000000");
+
+            // Stops before the store, not after it.
+            assert_eq!(gdb.ask("M100,4:00000000"), "OK");
+            assert_eq!(gdb.ask("Z2,100,4"), "OK");
+            assert_eq!(gdb.ask("c4"), "T05watch:100;");
+            assert_eq!(gdb.register(PC), "08000000");
+            assert_eq!(gdb.ask("m100,4"), "00000000");
+            assert_eq!(gdb.ask("z2,100,4"), "OK");
+
+            // Resuming goes past the store it stopped on.
+            assert_eq!(gdb.ask("Z3,102,1"), "OK");
+            assert_eq!(gdb.ask("c"), "T05rwatch:102;");
+            assert_eq!(gdb.register(PC), "0c000000");
+            assert_eq!(gdb.ask("m100,4"), "05000000");
+            gdb.send("k");
+        });
+        assert_eq!(exit, Some(Exit::Killed));
+    }
+
+    #[test]
+    fn resuming_after_a_halt_exits() {
+        let exit = session(&EXIT, |mut gdb| {
+            assert_eq!(gdb.ask("c"), "S05");
+            assert_eq!(gdb.register(PC), "04000000");
+            assert_eq!(gdb.ask("c"), "W07");
+        });
+        assert_eq!(exit, Some(Exit::Halted { pc: 4, code: 7 }));
+    }
+
+    #[test]
+    fn ctrl_c_stops_a_run() {
+        let exit = session(&LOOP, |mut gdb| {
+            gdb.send("c");
+            gdb.stream.write_all(&[0x03]).unwrap();
+            assert_eq!(gdb.reply(), "S02");
+            assert!(gdb.register(13) != "00000000");
+            gdb.send("k");
+        });
+        assert_eq!(exit, Some(Exit::Killed));
+    }
+
+    #[test]
+    fn bad_checksums_are_refused() {
+        let exit = session(&LOOP, |mut gdb| {
+            gdb.stream.write_all(b"$?#00").unwrap();
+            assert_eq!(gdb.byte(), b'-');
+            assert_eq!(gdb.ask("?"), "S05");
+            gdb.send("vKill;1");
+            assert_eq!(gdb.reply(), "OK");
+        });
+        assert_eq!(exit, Some(Exit::Killed));
+    }
+
+    #[test]
+    fn no_ack_mode() {
+        let exit = session(&LOOP, |mut gdb| {
+            assert_eq!(gdb.ask("QStartNoAckMode"), "OK");
+            gdb.ack = false;
+            // `reply` would see a `+` here if the stub still sent one.
+            assert_eq!(gdb.ask("?"), "S05");
+            assert_eq!(gdb.ask("m4,4"), "93050010");
+            assert_eq!(gdb.ask("D"), "OK");
+        });
+        assert_eq!(exit, None);
+    }
+
+    #[test]
+    fn dropping_the_connection_kills_the_program() {
+        let exit = session(&LOOP, |mut gdb| {
+            assert_eq!(gdb.ask("?"), "S05");
+        });
+        assert_eq!(exit, Some(Exit::Killed));
+    }
+
+    #[test]
+    fn target_description() {
+        let xml = target_xml();
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.ends_with("</target>\n"));
+        assert!(xml.contains(r#"<reg name="sp" bitsize="32" type="data_ptr" regnum="2"/>"#));
+        assert!(xml.contains(r#"<reg name="pc" bitsize="32" type="code_ptr" regnum="32"/>"#));
+
+        let exit = session(&LOOP, move |mut gdb| {
+            let first = gdb.ask("qXfer:features:read:target.xml:0,10");
+            assert_eq!(first, format!("m{}", &xml[..0x10]));
+            let rest = gdb.ask(&format!(
+                "qXfer:features:read:target.xml:10,{:x}",
+                xml.len()
+            ));
+            assert_eq!(rest, format!("l{}", &xml[0x10..]));
+            assert_eq!(gdb.ask("qXfer:features:read:other.xml:0,10"), "");
+            assert_eq!(gdb.ask("D"), "OK");
+        });
+        assert_eq!(exit, None);
+    }
+
+    #[test]
+    fn read_part_marks_the_last_part() {
+        assert_eq!(read_part("abcdef", "0,4"), "mabcd");
+        assert_eq!(read_part("abcdef", "4,4"), "lef");
+        assert_eq!(read_part("abcdef", "2,4"), "lcdef");
+        assert_eq!(read_part("abcdef", "9,4"), "l");
+        assert_eq!(read_part("abcdef", "zz"), "E01");
+    }
+
+    #[test]
+    fn hex_fields() {
+        assert_eq!(parse_bytes("00ff7a"), Some(vec![0x00, 0xff, 0x7a]));
+        assert_eq!(parse_bytes(""), Some(vec![]));
+        assert_eq!(parse_bytes("abc"), None);
+        assert_eq!(parse_bytes("zz"), None);
+        assert_eq!(parse_range("400010,4"), Some((0x40_0010, 4)));
+        assert_eq!(parse_range("400010"), None);
+        assert_eq!(parse_range("x,4"), None);
+        assert_eq!(parse_range("1,ffffffff0"), None);
+    }
+}
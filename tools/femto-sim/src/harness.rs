@@ -0,0 +1,224 @@
+//! The host side of `femto-test`.
+//!
+//! A test binary built with `femto-test` reports how its tests went over the
+//! UAT, in lines starting with `femto-test: `. [`Harness`] picks those out of
+//! the output, and prints what the standard test harness would have for the
+//! same tests, so `cargo test` looks the way it always does:
+//!
+//! ```text
+//! running 2 tests
+//! test tests::adds ... ok
+//! test tests::fails ... FAILED
+//!
+//! failures:
+//!
+//! ---- tests::fails stdout ----
+//! panicked at tests/hal.rs:20:9
+//!
+//! failures:
+//!     tests::fails
+//!
+//! test result: FAILED. 1 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.01s
+//! ```
+//!
+//! Whatever else a test prints is held back, and only shown if it fails.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::time::Duration;
+
+use femto_elf::Elf;
+
+use crate::Exit;
+
+/// Starts every line from the harness on the target.
+pub const PREFIX: &[u8] = b"femto-test: ";
+
+/// The symbol `femto-test` keeps its state in, which marks a test binary.
+pub const STATE_SYMBOL: &str = "__femto_test_state";
+
+/// True if `elf` was built with `femto-test`.
+pub fn is_test_binary(elf: &Elf) -> bool {
+    elf.symbol(STATE_SYMBOL).is_some()
+}
+
+struct Failure {
+    name: String,
+    output: Vec<u8>,
+}
+
+/// Turns the UAT output of a test binary into a test report.
+pub struct Harness {
+    out: Box<dyn Write>,
+    /// The line being received.
+    line: Vec<u8>,
+    /// The test that's running, and what it's printed so far.
+    running: Option<String>,
+    output: Vec<u8>,
+    total: Option<u32>,
+    passed: u32,
+    ignored: u32,
+    failures: Vec<Failure>,
+    done: bool,
+}
+
+impl fmt::Debug for Harness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Harness")
+            .field("running", &self.running)
+            .field("total", &self.total)
+            .field("passed", &self.passed)
+            .field("failed", &self.failures.len())
+            .field("ignored", &self.ignored)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Harness {
+    /// Write the report to `out`.
+    pub fn new(out: impl Write + 'static) -> Self {
+        Self {
+            out: Box::new(out),
+            line: Vec::new(),
+            running: None,
+            output: Vec::new(),
+            total: None,
+            passed: 0,
+            ignored: 0,
+            failures: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Take in bytes the UAT sent.
+    pub fn feed(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for &b in bytes {
+            if b == b'\n' {
+                let line = mem::take(&mut self.line);
+                self.line_received(&line)?;
+            } else {
+                self.line.push(b);
+            }
+        }
+        Ok(())
+    }
+
+    fn line_received(&mut self, line: &[u8]) -> io::Result<()> {
+        // A test that printed something without a newline leaves the
+        // harness's line on the end of its own.
+        let Some(start) = line.windows(PREFIX.len()).position(|w| w == PREFIX) else {
+            return self.test_output(line);
+        };
+        if start > 0 {
+            self.test_output(&line[..start])?;
+        }
+        let message = String::from_utf8_lossy(&line[start + PREFIX.len()..]).into_owned();
+        let (verb, rest) = message.split_once(' ').unwrap_or((&message, ""));
+        match verb {
+            "start" => {
+                let total = rest.parse().unwrap_or(0);
+                self.total = Some(total);
+                let plural = if total == 1 { "" } else { "s" };
+                writeln!(self.out, "\nrunning {total} test{plural}")?;
+            }
+            "run" => {
+                self.output.clear();
+                self.running = Some(rest.to_string());
+                write!(self.out, "test {rest} ... ")?;
+            }
+            "ok" => {
+                self.running = None;
+                self.passed += 1;
+                writeln!(self.out, "ok")?;
+            }
+            "ignored" => {
+                self.ignored += 1;
+                writeln!(self.out, "test {rest} ... ignored")?;
+            }
+            "fail" => {
+                let (name, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+                let reason = match reason {
+                    "did not panic" => "note: test did not panic as expected".to_string(),
+                    reason => reason.to_string(),
+                };
+                self.fail(name.to_string(), &reason)?;
+            }
+            "done" => self.done = true,
+            // Anything else is from a newer femto-test, or just a test
+            // printing something that looks like it.
+            _ => self.test_output(line)?,
+        }
+        self.out.flush()
+    }
+
+    fn test_output(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.running.is_some() {
+            self.output.extend_from_slice(bytes);
+            self.output.push(b'\n');
+            Ok(())
+        } else {
+            // Between tests, there's nothing to hold it back for.
+            self.out.write_all(bytes)?;
+            self.out.write_all(b"\n")
+        }
+    }
+
+    fn fail(&mut self, name: String, reason: &str) -> io::Result<()> {
+        self.running = None;
+        writeln!(self.out, "FAILED")?;
+        let mut output = mem::take(&mut self.output);
+        writeln!(output, "{reason}")?;
+        self.failures.push(Failure { name, output });
+        Ok(())
+    }
+
+    /// Print the summary, once the simulation has stopped with `exit` after
+    /// `elapsed`. Returns true if every test ran and passed.
+    ///
+    /// If the core stopped in the middle of a test, that test fails with
+    /// the reason it stopped, and the rest don't run.
+    pub fn finish(&mut self, exit: &Exit, elapsed: Duration) -> io::Result<bool> {
+        if !self.line.is_empty() {
+            let line = mem::take(&mut self.line);
+            self.line_received(&line)?;
+        }
+        if self.total.is_none() {
+            writeln!(self.out, "error: the test harness never started: {exit}")?;
+            self.out.flush()?;
+            return Ok(false);
+        }
+        if let Some(name) = self.running.take() {
+            self.fail(name, &format!("femto-sim: {exit}"))?;
+        } else if !self.done {
+            writeln!(self.out, "error: the test harness stopped early: {exit}")?;
+        }
+
+        if !self.failures.is_empty() {
+            writeln!(self.out, "\nfailures:")?;
+            for failure in &self.failures {
+                writeln!(self.out, "\n---- {} stdout ----", failure.name)?;
+                self.out.write_all(&failure.output)?;
+            }
+            writeln!(self.out, "\nfailures:")?;
+            for failure in &self.failures {
+                writeln!(self.out, "    {}", failure.name)?;
+            }
+        }
+
+        let ok = self.done && self.failures.is_empty();
+        writeln!(
+            self.out,
+            "\ntest result: {}. {} passed; {} failed; {} ignored; 0 measured; 0 filtered out; \
+             finished in {:.2}s\n",
+            if ok { "ok" } else { "FAILED" },
+            self.passed,
+            self.failures.len(),
+            self.ignored,
+            elapsed.as_secs_f64()
+        )?;
+        self.out.flush()?;
+        Ok(ok)
+    }
+}
@@ -0,0 +1,279 @@
+//! Host-side simulator for the RiscvFemto core.
+//!
+//! This runs the firmware ELF produced by `cargo build --release` on an RV32I
+//! interpreter wired up to the same memory map as `RiscvFemto_tb`, so programs
+//! can be run without the Vivado flow in `verilog/riscv.sh`.
+//!
+//! The core is clocked exactly like `RiscvFemto`, so [`Sim::cycles`] is the
+//! number of clock cycles the real thing would have taken, and UAT polling
+//! loops spin for the same number of iterations they do in the testbench.
+
+pub mod bus;
+pub mod cpu;
+pub mod gdb;
+pub mod harness;
+pub mod profile;
+pub mod testing;
+pub mod trace;
+pub mod uat;
+pub mod vcd;
+
+use std::fmt;
+use std::ops::Range;
+
+use femto_elf::Elf;
+
+use crate::bus::Bus;
+use crate::cpu::{opcode, Cpu, Fault, Kind};
+use crate::profile::Profile;
+use crate::trace::Trace;
+use crate::vcd::Vcd;
+
+/// `MEM_DEPTH` the testbench gives `RiscvMem`: 256 words, the 1 KiB `BRAM`
+/// region in `linker.ld`.
+pub const DEFAULT_MEM_DEPTH: usize = 256;
+
+/// What femto-sim exits with when the program gets stuck in a loop, which is
+/// how a `loop {}` panic handler ends up.
+pub const STUCK_EXIT_CODE: u8 = 3;
+
+/// The ROM and RAM regions from the `_rom_start`/`_rom_end` and
+/// `_ram_start`/`_ram_end` symbols femto-rt's linker script defines, if they
+/// are different regions.
+fn split_layout(elf: &Elf) -> Option<(Range<u32>, Range<u32>)> {
+    let symbol = |name| elf.symbol(name).map(|s| s.value);
+    let rom = symbol("_rom_start")?..symbol("_rom_end")?;
+    let ram = symbol("_ram_start")?..symbol("_ram_end")?;
+    (rom != ram).then_some((rom, ram))
+}
+
+/// The `MEM_DEPTH` an ELF was linked for: the size of its one memory region,
+/// from the `_ram_start`/`_ram_end` symbols, in words. `None` if the ELF has
+/// separate ROM and RAM, or a region `RiscvMem` can't be built with.
+pub fn linked_mem_depth(elf: &Elf) -> Option<usize> {
+    if split_layout(elf).is_some() {
+        return None;
+    }
+    let start = elf.symbol("_ram_start")?.value;
+    let end = elf.symbol("_ram_end")?.value;
+    let depth = end.checked_sub(start)? as usize / 4;
+    depth.is_power_of_two().then_some(depth)
+}
+
+/// Why a simulation stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// The core hit a SYSTEM instruction and stopped advancing `pc`. `code`
+    /// is what was in `a0` at the time, which is where `femto_rt::exit` puts
+    /// the exit code.
+    Halted { pc: u32, code: u32 },
+    /// The core is in a loop that jumps to itself, like the one in a
+    /// `loop {}` panic handler, and will never do anything else.
+    Stuck { pc: u32 },
+    /// The core fetched something that isn't an RV32I instruction.
+    IllegalInstruction { pc: u32, instr: u32 },
+    /// The instruction limit given to [`Sim::run`] ran out.
+    InstructionLimit,
+    /// The cycle limit given to [`Sim::run`] ran out.
+    CycleLimit,
+    /// The debugger killed the program, or went away. See [`gdb`].
+    Killed,
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exit::Halted { pc, code } => {
+                write!(f, "halted at {pc:#010x} with exit code {}", *code as i32)
+            }
+            Exit::Stuck { pc } => write!(f, "stuck in a loop at {pc:#010x}"),
+            Exit::IllegalInstruction { pc, instr } => {
+                write!(f, "illegal instruction {instr:#010x} at {pc:#010x}")
+            }
+            Exit::InstructionLimit => write!(f, "instruction limit reached"),
+            Exit::CycleLimit => write!(f, "cycle limit reached"),
+            Exit::Killed => write!(f, "killed by the debugger"),
+        }
+    }
+}
+
+/// Errors from [`Sim::load`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// A loadable segment doesn't fit in the simulated memory, which is
+    /// described by `memory`.
+    OutOfMemory {
+        addr: u32,
+        size: u32,
+        memory: String,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::OutOfMemory { addr, size, memory } => write!(
+                f,
+                "segment at {addr:#010x} ({size} bytes) doesn't fit in the {memory}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// The core, its memory, and its peripherals.
+#[derive(Debug)]
+pub struct Sim {
+    pub cpu: Cpu,
+    pub bus: Bus,
+    instructions: u64,
+    profile: Option<Profile>,
+    trace: Option<Trace>,
+    vcd: Option<Vcd>,
+    min_sp: Option<u32>,
+}
+
+impl Sim {
+    /// Make a simulator with `mem_depth` words of RAM. See [`Bus::new`].
+    pub fn new(mem_depth: usize) -> Self {
+        Self {
+            cpu: Cpu::new(0),
+            bus: Bus::new(mem_depth),
+            instructions: 0,
+            profile: None,
+            trace: None,
+            vcd: None,
+            min_sp: None,
+        }
+    }
+
+    /// Start counting cycles per instruction address. See
+    /// [`Profile::by_function`] for the per-function breakdown.
+    pub fn enable_profile(&mut self) {
+        self.profile.get_or_insert_with(Profile::new);
+    }
+
+    /// The profile, if [`enable_profile`](Self::enable_profile) was called.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Write a line to `trace` for every instruction from now on.
+    pub fn enable_trace(&mut self, trace: Trace) {
+        self.trace = Some(trace);
+    }
+
+    /// Stop tracing, and hand the trace back so it can be
+    /// [finished](Trace::finish).
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+
+    /// Write a waveform of every cycle from now on to `out`, in the layout
+    /// of `RiscvFemto_tb.vcd`. See [`Vcd`].
+    pub fn enable_vcd(&mut self, out: impl std::io::Write + 'static) {
+        self.vcd = Some(Vcd::new(out, &self.cpu, &self.bus));
+    }
+
+    /// Stop writing the waveform, and hand it back so it can be
+    /// [finished](Vcd::finish).
+    pub fn take_vcd(&mut self) -> Option<Vcd> {
+        self.vcd.take()
+    }
+
+    /// Copy an ELF file's loadable segments into memory at their load
+    /// addresses, the same layout `objcopy -O binary` gives `program.mem`,
+    /// and point the core at the entry point.
+    ///
+    /// If the ELF was linked with separate ROM and RAM regions, the bus is
+    /// [split](Bus::split) to match first, so `.data` gets copied out of ROM
+    /// by `_start` just like it would on an execute-in-place system.
+    pub fn load(&mut self, elf: &Elf) -> Result<(), LoadError> {
+        if let Some((rom, ram)) = split_layout(elf) {
+            self.bus.split(rom, ram);
+        }
+        for segment in elf.load_segments().filter(|s| s.filesz > 0) {
+            if !self.bus.contains(segment.paddr, segment.filesz) {
+                return Err(LoadError::OutOfMemory {
+                    addr: segment.paddr,
+                    size: segment.filesz,
+                    memory: self.bus.describe_memory(),
+                });
+            }
+            self.bus.load(segment.paddr, elf.segment_data(segment));
+        }
+        self.cpu.pc = elf.entry();
+        Ok(())
+    }
+
+    /// The lowest `sp` has been so far, or `None` if nothing has written
+    /// it yet.
+    pub fn min_sp(&self) -> Option<u32> {
+        self.min_sp
+    }
+
+    /// Number of instructions executed so far.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Number of clock cycles so far.
+    pub fn cycles(&self) -> u64 {
+        self.bus.cycle()
+    }
+
+    /// Execute one instruction. Returns why the simulation should stop, if it
+    /// should.
+    pub fn step(&mut self) -> Option<Exit> {
+        let cycle = self.cycles();
+        let retired = match self.cpu.step(&mut self.bus) {
+            Ok(retired) => retired,
+            Err(Fault::IllegalInstruction { pc, instr }) => {
+                return Some(Exit::IllegalInstruction { pc, instr })
+            }
+        };
+        self.instructions += 1;
+        if let Some(profile) = &mut self.profile {
+            profile.record(&retired);
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.record(cycle, &retired);
+        }
+        if let Some(vcd) = &mut self.vcd {
+            vcd.record(&retired, &self.bus);
+        }
+        // `la sp, ...` goes through a half-built address from `auipc` (or
+        // `lui`) first, which isn't a real stack pointer.
+        let upper_immediate = matches!(retired.instr & 0x7f, opcode::AUIPC | opcode::LUI);
+        if let Some((2, sp)) = retired.rd.filter(|_| !upper_immediate) {
+            self.min_sp = Some(self.min_sp.map_or(sp, |min| min.min(sp)));
+        }
+        match retired.kind {
+            Kind::System => Some(Exit::Halted {
+                pc: retired.pc,
+                code: self.cpu.regs[10],
+            }),
+            Kind::Jump | Kind::Branch if retired.next_pc == retired.pc => {
+                Some(Exit::Stuck { pc: retired.pc })
+            }
+            _ => None,
+        }
+    }
+
+    /// Run until the core stops, or until `max_instructions` instructions or
+    /// `max_cycles` cycles have gone by since the simulation started.
+    pub fn run(&mut self, max_instructions: u64, max_cycles: u64) -> Exit {
+        loop {
+            if self.instructions >= max_instructions {
+                return Exit::InstructionLimit;
+            }
+            if self.cycles() >= max_cycles {
+                return Exit::CycleLimit;
+            }
+            if let Some(exit) = self.step() {
+                return exit;
+            }
+        }
+    }
+}
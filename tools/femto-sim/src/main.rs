@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{Ipv4Addr, TcpListener};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use femto_disasm::Symbols;
+use femto_elf::{Elf, SymbolMap};
+use femto_sim::gdb;
+use femto_sim::harness::{self, Harness};
+use femto_sim::profile::Profile;
+use femto_sim::trace::Trace;
+use femto_sim::vcd::Vcd;
+use femto_sim::{linked_mem_depth, Exit, Sim, DEFAULT_MEM_DEPTH, STUCK_EXIT_CODE};
+
+const USAGE: &str = "\
+Usage: femto-sim [OPTIONS] <ELF>
+
+Run a RISC-V firmware image on a simulated RiscvFemto_tb. Bytes sent to the
+UAT are written to stdout. When the program halts with a SYSTEM instruction,
+femto-sim exits with the code the program left in a0. If it gets stuck in a
+loop that jumps to itself instead, like a `loop {}` panic handler, femto-sim
+exits with 3, and on an illegal instruction or a limit running out, with 1.
+
+A test binary built with femto-test has its results printed the way cargo
+test prints them, and femto-sim exits with 0 if they all passed, or 101 if
+not.
+
+Options:
+      --max-instructions <N>  Stop after N instructions [default: 1000000]
+      --max-cycles <N>        Stop after N clock cycles [default: no limit]
+      --cycles                Print how many clock cycles the run took,
+                              broken down by function
+      --mem-depth <WORDS>     RAM size in 32-bit words, like RiscvMem's
+                              MEM_DEPTH [default: the size of the ELF's
+                              memory region, or 256]. Ignored for ELFs
+                              linked with separate ROM and RAM, which get
+                              the sizes from the linker script
+      --leds                  Print LED changes to stderr
+      --stack                 Print the lowest sp reached, and how far that is
+                              below _stack_start
+      --trace <FILE>          Write a Spike-style commit log of every
+                              instruction to FILE
+      --vcd <FILE>            Write a waveform to FILE, with the signals and
+                              hierarchy of RiscvFemto_tb.vcd
+      --gdb <PORT>            Wait for GDB to connect on localhost:PORT, and
+                              run under its control. The limits only count
+                              once GDB detaches
+  -h, --help                  Print this help
+";
+
+struct Args {
+    elf: String,
+    max_instructions: u64,
+    max_cycles: u64,
+    mem_depth: Option<usize>,
+    leds: bool,
+    cycles: bool,
+    stack: bool,
+    trace: Option<String>,
+    vcd: Option<String>,
+    gdb: Option<u16>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut elf = None;
+    let mut max_instructions = 1_000_000;
+    let mut max_cycles = u64::MAX;
+    let mut mem_depth: Option<usize> = None;
+    let mut leds = false;
+    let mut cycles = false;
+    let mut stack = false;
+    let mut trace = None;
+    let mut vcd = None;
+    let mut gdb = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                std::process::exit(0);
+            }
+            "--max-instructions" => max_instructions = parse_value(&arg, args.next())?,
+            "--max-cycles" => max_cycles = parse_value(&arg, args.next())?,
+            "--mem-depth" => mem_depth = Some(parse_value(&arg, args.next())?),
+            "--leds" => leds = true,
+            "--cycles" => cycles = true,
+            "--stack" => stack = true,
+            "--trace" => trace = Some(args.next().ok_or("`--trace` needs a value")?),
+            "--vcd" => vcd = Some(args.next().ok_or("`--vcd` needs a value")?),
+            "--gdb" => gdb = Some(parse_value(&arg, args.next())?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ if elf.is_none() => elf = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+
+    if let Some(depth) = mem_depth.filter(|depth| !depth.is_power_of_two()) {
+        return Err(format!("memory depth {depth} isn't a power of two"));
+    }
+    Ok(Args {
+        elf: elf.ok_or("no ELF file given")?,
+        max_instructions,
+        max_cycles,
+        mem_depth,
+        leds,
+        cycles,
+        stack,
+        trace,
+        vcd,
+        gdb,
+    })
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("`{option}` needs a value"))?;
+    value
+        .replace('_', "")
+        .parse()
+        .map_err(|_| format!("invalid value `{value}` for `{option}`"))
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let data = match std::fs::read(&args.elf) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("error: couldn't read {}: {e}", args.elf);
+            return ExitCode::FAILURE;
+        }
+    };
+    let elf = match Elf::parse(&data) {
+        Ok(elf) => elf,
+        Err(e) => {
+            eprintln!("error: {}: {e}", args.elf);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mem_depth = args.mem_depth.or_else(|| linked_mem_depth(&elf));
+    let mut sim = Sim::new(mem_depth.unwrap_or(DEFAULT_MEM_DEPTH));
+    if let Err(e) = sim.load(&elf) {
+        eprintln!("error: {}: {e}", args.elf);
+        return ExitCode::FAILURE;
+    }
+    if args.cycles {
+        sim.enable_profile();
+    }
+    if let Some(path) = &args.trace {
+        match File::create(path) {
+            Ok(file) => sim.enable_trace(Trace::new(BufWriter::new(file), Symbols::new(&elf))),
+            Err(e) => {
+                eprintln!("error: couldn't create {path}: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if let Some(path) = &args.vcd {
+        match File::create(path) {
+            Ok(file) => sim.enable_vcd(BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("error: couldn't create {path}: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let mut harness = harness::is_test_binary(&elf).then(|| Harness::new(io::stdout()));
+    let mut stdout = io::stdout();
+    let mut leds = sim.bus.leds;
+    let started = Instant::now();
+    let mut on_step = |sim: &mut Sim| {
+        let output = sim.bus.uat.take_output();
+        if !output.is_empty() {
+            match &mut harness {
+                Some(harness) => harness.feed(&output).ok(),
+                None => stdout.write_all(&output).and_then(|_| stdout.flush()).ok(),
+            };
+        }
+        if args.leds && sim.bus.leds != leds {
+            leds = sim.bus.leds;
+            eprintln!("leds: {leds:#010b}");
+        }
+    };
+
+    let mut exit = None;
+    if let Some(port) = args.gdb {
+        match debug(&mut sim, port, &mut on_step) {
+            Ok(None) => eprintln!("femto-sim: GDB detached, running on"),
+            Ok(stop) => exit = stop,
+            Err(e) => {
+                eprintln!("error: GDB connection: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    // After GDB detaches, the limits count from there.
+    let (instructions, cycles) = (sim.instructions(), sim.cycles());
+    let exit = exit.unwrap_or_else(|| loop {
+        if sim.instructions() - instructions >= args.max_instructions {
+            break Exit::InstructionLimit;
+        }
+        if sim.cycles() - cycles >= args.max_cycles {
+            break Exit::CycleLimit;
+        }
+        let stop = sim.step();
+        on_step(&mut sim);
+        if let Some(stop) = stop {
+            break stop;
+        }
+    });
+
+    // The harness reports how a test run ended itself.
+    let passed = harness
+        .as_mut()
+        .map(|h| h.finish(&exit, started.elapsed()).unwrap_or(false));
+    if passed.is_none() {
+        eprintln!(
+            "femto-sim: {exit} after {} instructions ({} cycles), leds = {:#04x}",
+            sim.instructions(),
+            sim.cycles(),
+            sim.bus.leds
+        );
+    }
+    if let Some(Err(e)) = sim.take_trace().map(Trace::finish) {
+        eprintln!("femto-sim: error writing the trace: {e}");
+    }
+    if let Some(Err(e)) = sim.take_vcd().map(Vcd::finish) {
+        eprintln!("femto-sim: error writing the waveform: {e}");
+    }
+    if args.stack {
+        print_stack(&sim, &elf);
+    }
+    if let Some(profile) = sim.profile() {
+        print_profile(profile, &SymbolMap::new(&elf));
+    }
+    match (passed, exit) {
+        // What the standard test harness exits with.
+        (Some(passed), _) => ExitCode::from(if passed { 0 } else { 101 }),
+        (None, Exit::Halted { code, .. }) => exit_code(code),
+        (None, Exit::Stuck { .. }) => ExitCode::from(STUCK_EXIT_CODE),
+        (
+            None,
+            Exit::IllegalInstruction { .. }
+            | Exit::InstructionLimit
+            | Exit::CycleLimit
+            | Exit::Killed,
+        ) => ExitCode::FAILURE,
+    }
+}
+
+/// The process only gets the low 8 bits of the code, so make sure a non-zero
+/// code doesn't get cut down to 0.
+fn exit_code(code: u32) -> ExitCode {
+    match code as u8 {
+        0 if code != 0 => ExitCode::FAILURE,
+        status => ExitCode::from(status),
+    }
+}
+
+/// Wait for GDB on localhost:`port`, and let it drive the simulation. See
+/// [`gdb::serve`].
+fn debug(sim: &mut Sim, port: u16, on_step: impl FnMut(&mut Sim)) -> io::Result<Option<Exit>> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    eprintln!("femto-sim: waiting for GDB on {}", listener.local_addr()?);
+    let (stream, _) = listener.accept()?;
+    gdb::serve(sim, stream, on_step)
+}
+
+/// Report how deep the stack got, against the `_stack_size` the linker script
+/// reserved for it.
+fn print_stack(sim: &Sim, elf: &Elf) {
+    let Some(min_sp) = sim.min_sp() else {
+        eprintln!("femto-sim: sp was never set");
+        return;
+    };
+    let symbol = |name| elf.symbol(name).map(|s| s.value);
+    match (symbol("_stack_start"), symbol("_stack_size")) {
+        (Some(start), Some(size)) => {
+            let used = start.wrapping_sub(min_sp);
+            eprintln!(
+                "femto-sim: lowest sp {min_sp:#010x}, {used} bytes below _stack_start \
+                 ({size} reserved)"
+            );
+            if used > size {
+                eprintln!("femto-sim: warning: the stack overflowed its {size} bytes");
+            }
+        }
+        _ => eprintln!("femto-sim: lowest sp {min_sp:#010x}"),
+    }
+}
+
+fn print_profile(profile: &Profile, symbols: &SymbolMap) {
+    eprintln!("{:>12} {:>8}  function", "cycles", "calls");
+    for f in profile.by_function(symbols) {
+        let name = f
+            .name
+            .unwrap_or_else(|| format!("<unknown> at {:#010x}", f.addr));
+        eprintln!("{:>12} {:>8}  {name}", f.cycles, f.calls);
+    }
+    eprintln!("{:>12} {:>8}  total", profile.total(), "");
+}
@@ -0,0 +1,80 @@
+//! Where the cycles went.
+
+use std::collections::HashMap;
+
+use femto_elf::{demangle, SymbolMap};
+
+use crate::cpu::{Kind, Retired};
+
+/// Cycles spent at each instruction address, and how often each address was
+/// called.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    cycles: HashMap<u32, u64>,
+    calls: HashMap<u32, u64>,
+}
+
+/// Cycles spent inside one function, not counting the functions it calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCycles {
+    /// Demangled name, or `None` for code no symbol covers.
+    pub name: Option<String>,
+    pub addr: u32,
+    pub cycles: u64,
+    pub calls: u64,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for one executed instruction.
+    pub fn record(&mut self, retired: &Retired) {
+        *self.cycles.entry(retired.pc).or_default() += retired.kind.cycles() as u64;
+        // A jump that saves a return address is a call.
+        if retired.kind == Kind::Jump && retired.rd.is_some() {
+            *self.calls.entry(retired.next_pc).or_default() += 1;
+        }
+    }
+
+    /// Total cycles recorded.
+    pub fn total(&self) -> u64 {
+        self.cycles.values().sum()
+    }
+
+    /// Cycles spent at a single instruction address.
+    pub fn cycles_at(&self, pc: u32) -> u64 {
+        self.cycles.get(&pc).copied().unwrap_or(0)
+    }
+
+    /// Roll the per-address counts up into functions, most expensive first.
+    pub fn by_function(&self, symbols: &SymbolMap) -> Vec<FunctionCycles> {
+        let mut functions: HashMap<Option<u32>, FunctionCycles> = HashMap::new();
+        for (&pc, &cycles) in &self.cycles {
+            let sym = symbols.lookup(pc).map(|(sym, _)| sym);
+            let f = functions
+                .entry(sym.map(|s| s.value))
+                .or_insert_with(|| FunctionCycles {
+                    name: sym.map(|s| demangle(s.name)),
+                    addr: sym.map_or(pc, |s| s.value),
+                    cycles: 0,
+                    calls: 0,
+                });
+            f.cycles += cycles;
+            // Everything without a symbol is lumped together at its lowest
+            // address.
+            f.addr = f.addr.min(pc);
+        }
+        for (&target, &calls) in &self.calls {
+            if let Some((sym, 0)) = symbols.lookup(target) {
+                if let Some(f) = functions.get_mut(&Some(sym.value)) {
+                    f.calls += calls;
+                }
+            }
+        }
+        let mut functions: Vec<_> = functions.into_values().collect();
+        functions.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.addr.cmp(&b.addr)));
+        functions
+    }
+}
@@ -0,0 +1,398 @@
+//! Running firmware images from host-side tests.
+//!
+//! [`FemtoTest`] loads an ELF, runs it to the end, and hands back an
+//! [`Outcome`] with everything a test might want to check: what came out of
+//! the UAT, the LEDs, the exit code, how long it took, and the values of
+//! global variables, looked up by name.
+//!
+//! ```no_run
+//! use femto_sim::testing::{firmware, FemtoTest};
+//!
+//! let run = FemtoTest::load(firmware("femto-riscv-demo")).max_cycles(100_000).run();
+//! run.assert_exit_code(0);
+//! run.assert_output_matches_file("tests/golden/femto-riscv-demo.uat");
+//! assert_eq!(run.global("UAT_VAL"), 5);
+//! ```
+//!
+//! Everything here panics instead of returning errors, since it's meant to be
+//! called straight from a `#[test]`.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use femto_elf::{demangle, Elf, SymbolKind};
+
+use crate::{linked_mem_depth, Exit, Sim, DEFAULT_MEM_DEPTH};
+
+/// Set to anything but `0` to have
+/// [`assert_output_matches_file`](Outcome::assert_output_matches_file) write
+/// the output to the file instead of comparing against it.
+pub const BLESS_VAR: &str = "FEMTO_BLESS";
+
+/// The top of the repository, which holds the firmware workspace.
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .ancestors()
+        .nth(2)
+        .unwrap()
+        .to_path_buf()
+}
+
+/// Where `cargo build --release` in `root`, the firmware workspace, puts the
+/// firmware: under `CARGO_TARGET_DIR` or `CARGO_BUILD_TARGET_DIR` if either
+/// is set, since a build started from here sees them too, or else under
+/// `target`.
+pub fn release_dir(root: &Path) -> PathBuf {
+    let target = ["CARGO_TARGET_DIR", "CARGO_BUILD_TARGET_DIR"]
+        .into_iter()
+        .filter_map(std::env::var_os)
+        .find(|dir| !dir.is_empty())
+        .map_or_else(|| root.join("target"), |dir| root.join(dir));
+    target.join("riscv32i-unknown-none-elf/release")
+}
+
+/// Build the firmware binary `bin`, from whichever package in the firmware
+/// workspace has it, with `cargo build --release`, and return the path of its
+/// ELF.
+///
+/// Each binary is only built once per test process, however many tests ask
+/// for it.
+///
+/// # Panics
+///
+/// If the build fails.
+pub fn firmware(bin: &str) -> PathBuf {
+    static BUILT: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+    let root = root();
+    let mut built = BUILT.lock().unwrap_or_else(|e| e.into_inner());
+    if built
+        .get_or_insert_with(HashSet::new)
+        .insert(bin.to_string())
+    {
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+        let output = Command::new(cargo)
+            .current_dir(&root)
+            .args(["build", "--release", "--workspace", "--bin", bin])
+            .output()
+            .unwrap_or_else(|e| panic!("couldn't run cargo: {e}"));
+        assert!(
+            output.status.success(),
+            "`cargo build --release --workspace --bin {bin}` failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    release_dir(&root).join(bin)
+}
+
+/// A firmware image, and the limits to run it with.
+#[derive(Debug, Clone)]
+pub struct FemtoTest {
+    path: PathBuf,
+    data: Vec<u8>,
+    max_instructions: u64,
+    max_cycles: u64,
+    mem_depth: Option<usize>,
+    setup: Option<fn(&mut Sim, &Elf)>,
+}
+
+impl FemtoTest {
+    /// Read the ELF at `path`.
+    ///
+    /// # Panics
+    ///
+    /// If it can't be read.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let data = std::fs::read(&path)
+            .unwrap_or_else(|e| panic!("couldn't read {}: {e}", path.display()));
+        Self {
+            path,
+            data,
+            max_instructions: 1_000_000,
+            max_cycles: u64::MAX,
+            mem_depth: None,
+            setup: None,
+        }
+    }
+
+    /// Stop after `n` instructions. The default is 1,000,000, same as
+    /// `femto-sim`.
+    pub fn max_instructions(mut self, n: u64) -> Self {
+        self.max_instructions = n;
+        self
+    }
+
+    /// Stop after `n` clock cycles. There's no limit by default.
+    pub fn max_cycles(mut self, n: u64) -> Self {
+        self.max_cycles = n;
+        self
+    }
+
+    /// Give the core `words` words of RAM, instead of what the ELF was
+    /// linked for. See [`Sim::new`].
+    pub fn mem_depth(mut self, words: usize) -> Self {
+        self.mem_depth = Some(words);
+        self
+    }
+
+    /// Call `setup` once the ELF is loaded, just before the program starts.
+    /// The simulator starts out with everything zeroed, which can hide a
+    /// missing bit of initialization that real hardware would show up, so
+    /// this is the place to fill registers or memory with junk.
+    pub fn setup(mut self, setup: fn(&mut Sim, &Elf)) -> Self {
+        self.setup = Some(setup);
+        self
+    }
+
+    /// Run the program until it stops or hits a limit.
+    ///
+    /// # Panics
+    ///
+    /// If the ELF can't be parsed or doesn't fit in memory. Anything that
+    /// goes wrong once it's running is in the [`Outcome`].
+    pub fn run(self) -> Outcome {
+        let name = self.path.display();
+        let elf = Elf::parse(&self.data).unwrap_or_else(|e| panic!("{name}: {e}"));
+        let mem_depth = self.mem_depth.or_else(|| linked_mem_depth(&elf));
+        let mut sim = Sim::new(mem_depth.unwrap_or(DEFAULT_MEM_DEPTH));
+        sim.load(&elf).unwrap_or_else(|e| panic!("{name}: {e}"));
+        if let Some(setup) = self.setup {
+            setup(&mut sim, &elf);
+        }
+
+        let exit = sim.run(self.max_instructions, self.max_cycles);
+        let symbols = elf
+            .symbols()
+            .iter()
+            .filter(|s| !s.is_internal() && s.kind != SymbolKind::File)
+            .map(|s| Global {
+                name: s.name.to_string(),
+                demangled: demangle(s.name),
+                addr: s.value,
+                size: s.size,
+                variable: s.kind == SymbolKind::Object,
+            })
+            .collect();
+        Outcome {
+            output: sim.bus.uat.take_output(),
+            exit,
+            sim,
+            symbols,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Global {
+    name: String,
+    demangled: String,
+    addr: u32,
+    size: u32,
+    /// False for functions and for labels like `_stack_start`.
+    variable: bool,
+}
+
+impl Global {
+    /// True if `name` is this global's symbol, its demangled path, or the
+    /// end of that path.
+    fn is_called(&self, name: &str) -> bool {
+        self.name == name
+            || self.demangled == name
+            || self
+                .demangled
+                .strip_suffix(name)
+                .is_some_and(|prefix| prefix.ends_with("::"))
+    }
+}
+
+/// How a [`FemtoTest`] run went.
+#[derive(Debug)]
+pub struct Outcome {
+    output: Vec<u8>,
+    exit: Exit,
+    sim: Sim,
+    symbols: Vec<Global>,
+}
+
+impl Outcome {
+    /// Every byte the program sent to the UAT.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// The output as text, with anything that isn't UTF-8 replaced.
+    pub fn output_text(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+
+    /// Why the run stopped.
+    pub fn exit(&self) -> Exit {
+        self.exit
+    }
+
+    /// The code the program exited with, if it halted.
+    pub fn exit_code(&self) -> Option<u32> {
+        match self.exit {
+            Exit::Halted { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+
+    /// The LEDs at the end of the run.
+    pub fn leds(&self) -> u8 {
+        self.sim.bus.leds
+    }
+
+    /// Number of instructions the run took.
+    pub fn instructions(&self) -> u64 {
+        self.sim.instructions()
+    }
+
+    /// Number of clock cycles the run took.
+    pub fn cycles(&self) -> u64 {
+        self.sim.cycles()
+    }
+
+    /// The simulator, as the run left it, for anything not covered here.
+    pub fn sim(&self) -> &Sim {
+        &self.sim
+    }
+
+    /// The bytes of the global variable called `name`, as the run left them.
+    /// `name` can be the symbol, its demangled path like
+    /// `femto_riscv_demo::UAT_VAL`, or just the end of that path, like
+    /// `UAT_VAL`.
+    ///
+    /// The compiler is free to fold away a variable it can see the whole
+    /// life of, so mark the ones tests look at `#[used]`.
+    ///
+    /// # Panics
+    ///
+    /// If there's no such global, or more than one.
+    pub fn global_bytes(&self, name: &str) -> Vec<u8> {
+        let global = self.find(name, true);
+        (global.addr..global.addr + global.size)
+            .map(|addr| (self.sim.bus.peek(addr) >> (8 * (addr & 3))) as u8)
+            .collect()
+    }
+
+    /// The value of the global variable called `name`, zero-extended from
+    /// however many bytes it has. See [`global_bytes`](Self::global_bytes)
+    /// for how it's looked up.
+    ///
+    /// # Panics
+    ///
+    /// If there's no such global, or it's bigger than 4 bytes.
+    pub fn global(&self, name: &str) -> u32 {
+        let bytes = self.global_bytes(name);
+        assert!(
+            bytes.len() <= 4,
+            "`{name}` is {} bytes; use `global_bytes` for it",
+            bytes.len()
+        );
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |value, &b| (value << 8) | b as u32)
+    }
+
+    /// The address of the symbol called `name`, which can be any symbol in
+    /// the ELF, like a function or `_stack_start` from the linker script. See
+    /// [`global_bytes`](Self::global_bytes) for how it's looked up.
+    ///
+    /// # Panics
+    ///
+    /// If there's no such symbol, or more than one.
+    pub fn address(&self, name: &str) -> u32 {
+        self.find(name, false).addr
+    }
+
+    fn find(&self, name: &str, variable: bool) -> &Global {
+        let what = if variable { "global" } else { "symbol" };
+        let mut found = self
+            .symbols
+            .iter()
+            .filter(|g| (g.variable || !variable) && g.is_called(name));
+        let global = found
+            .next()
+            .unwrap_or_else(|| panic!("no {what} called `{name}` in the ELF"));
+        if let Some(other) = found.next() {
+            panic!(
+                "`{name}` could be `{}` or `{}`; give more of the path",
+                global.demangled, other.demangled
+            );
+        }
+        global
+    }
+
+    /// Check that the program halted with exit code `code`.
+    pub fn assert_exit_code(&self, code: u32) {
+        assert_eq!(
+            self.exit_code(),
+            Some(code),
+            "expected exit code {code}, but the program {}\noutput: \"{}\"",
+            self.exit,
+            self.output_text().escape_debug()
+        );
+    }
+
+    /// Check that the UAT output is exactly `expected`.
+    pub fn assert_output(&self, expected: impl AsRef<[u8]>) {
+        if let Some(message) = mismatch(expected.as_ref(), &self.output) {
+            panic!("UAT output doesn't match\n{message}");
+        }
+    }
+
+    /// Check that the UAT output is exactly what's in the file at `path`, a
+    /// golden file, relative to the package being tested.
+    ///
+    /// With [`FEMTO_BLESS`](BLESS_VAR) set, the file is written with the
+    /// output instead, to create it or to accept a change.
+    pub fn assert_output_matches_file(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        if std::env::var_os(BLESS_VAR).is_some_and(|v| v != "0") {
+            if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)
+                    .unwrap_or_else(|e| panic!("couldn't create {}: {e}", dir.display()));
+            }
+            std::fs::write(path, &self.output)
+                .unwrap_or_else(|e| panic!("couldn't write {}: {e}", path.display()));
+            return;
+        }
+        let expected = std::fs::read(path).unwrap_or_else(|e| {
+            panic!(
+                "couldn't read {}: {e}; run with {BLESS_VAR}=1 to create it",
+                path.display()
+            )
+        });
+        if let Some(message) = mismatch(&expected, &self.output) {
+            panic!(
+                "UAT output doesn't match {}\n{message}\nrun with {BLESS_VAR}=1 if the new \
+                 output is right",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Describe how `found` differs from `expected`, if it does.
+fn mismatch(expected: &[u8], found: &[u8]) -> Option<String> {
+    if expected == found {
+        return None;
+    }
+    let at = expected
+        .iter()
+        .zip(found)
+        .position(|(a, b)| a != b)
+        .unwrap_or(expected.len().min(found.len()));
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).escape_debug().to_string();
+    let mut message = String::new();
+    writeln!(message, "expected: \"{}\"", text(expected)).unwrap();
+    writeln!(message, "   found: \"{}\"", text(found)).unwrap();
+    write!(message, "first difference at byte {at}").unwrap();
+    Some(message)
+}
@@ -0,0 +1,113 @@
+//! A per-instruction trace in the format of Spike's commit log.
+//!
+//! Each retired instruction gets one line (padded out so the columns line up,
+//! which is left out here):
+//!
+//! ```text
+//!  4 core   0: 3 0x00000004 (0xa0418193) x3  0x00000a04  ; addi    gp, gp, -1532  <_start+0x4>
+//! 68 core   0: 3 0x0000005c (0x00032023) mem 0x00000204 0x00000000  ; sw      zero, 0(t1)  <_start+0x5c>
+//! ```
+//!
+//! The first column is the cycle the instruction was fetched on. Between that
+//! and the `;` is exactly what `spike --log-commits` prints for an RV32 hart in
+//! machine mode: the `pc`, the instruction word, then the register written
+//! and its new value, and the address of a load or the address and data of a
+//! store. Strip the other two columns off and the result can be diffed
+//! against a commit log from Spike, or from a waveform of `RiscvFemto_tb`.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use femto_disasm::{disassemble_word, Symbols};
+
+use crate::cpu::{MemAccess, Retired};
+
+/// Width of the Spike part of a line, enough for the longest, a load with a
+/// register write.
+const COMMIT_WIDTH: usize = 65;
+const TEXT_WIDTH: usize = 32;
+
+/// Writes a line per retired instruction. Symbols name the functions in the
+/// last column, and branch targets in the disassembly.
+pub struct Trace {
+    out: Box<dyn Write>,
+    symbols: Symbols,
+    error: Option<io::Error>,
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Trace")
+            .field("symbols", &self.symbols)
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Trace {
+    pub fn new(out: impl Write + 'static, symbols: Symbols) -> Self {
+        Self {
+            out: Box::new(out),
+            symbols,
+            error: None,
+        }
+    }
+
+    /// Write the line for an instruction fetched on `cycle`. After a write
+    /// fails, nothing more is written, and [`finish`](Self::finish) returns
+    /// the error.
+    pub fn record(&mut self, cycle: u64, retired: &Retired) {
+        if self.error.is_some() {
+            return;
+        }
+        let line = self.line(cycle, retired);
+        if let Err(e) = writeln!(self.out, "{line}") {
+            self.error = Some(e);
+        }
+    }
+
+    /// Flush the output, and report the first error writing it, if any.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.out.flush(),
+        }
+    }
+
+    fn line(&self, cycle: u64, retired: &Retired) -> String {
+        let text = disassemble_word(retired.instr, retired.pc, &self.symbols);
+        let function = match self.symbols.lookup(retired.pc) {
+            Some((name, 0)) => format!("<{name}>"),
+            Some((name, offset)) => format!("<{name}+{offset:#x}>"),
+            None => String::new(),
+        };
+        let line = format!(
+            "{cycle:>8} {:COMMIT_WIDTH$} ; {text:TEXT_WIDTH$} {function}",
+            commit(retired)
+        );
+        line.trim_end().to_string()
+    }
+}
+
+/// The line `spike --log-commits` prints for an instruction.
+pub fn commit(retired: &Retired) -> String {
+    let mut line = format!("core   0: 3 {:#010x} ({:#010x})", retired.pc, retired.instr);
+    if let Some((rd, value)) = retired.rd {
+        line += &format!(" x{rd:<2} {value:#010x}");
+    }
+    match retired.mem {
+        Some(MemAccess {
+            addr, strobe: 0, ..
+        }) => line += &format!(" mem {addr:#010x}"),
+        Some(MemAccess { addr, data, strobe }) => {
+            // Take the stored value back out of its byte lanes, and print it
+            // as wide as the store was.
+            let bytes = strobe.count_ones() as usize;
+            let value = data >> (8 * strobe.trailing_zeros());
+            let value = value & (u32::MAX >> (32 - 8 * bytes));
+            line += &format!(" mem {addr:#010x} {value:#0width$x}", width = 2 + 2 * bytes);
+        }
+        None => {}
+    }
+    line
+}
@@ -0,0 +1,194 @@
+//! The `RiscvUAT` transmitter.
+//!
+//! This is a register-for-register copy of `RiscvUAT.sv`, clocked once per
+//! core cycle, so the ready bit firmware polls goes high and low on exactly
+//! the same cycles as it does in `RiscvFemto_tb`.
+
+/// `CLK_FREQ` the testbench gives `RiscvUAT`.
+pub const TB_CLK_FREQ: u32 = 500_000_000;
+/// `BAUD` the testbench gives `RiscvUAT`.
+pub const TB_BAUD: u32 = 50_000_000;
+
+#[derive(Debug, Clone)]
+pub struct Uat {
+    /// `COUNT`: clock cycles per bit, minus one.
+    count: u32,
+    /// `CNT_W`: width of the clock divider.
+    width: u32,
+    clk_div: u32,
+    shift: u32,
+    tx: bool,
+    ready: bool,
+    /// `dIn` for the current cycle, if `dInValid` is high.
+    d_in: Option<u8>,
+    output: Vec<u8>,
+}
+
+impl Default for Uat {
+    fn default() -> Self {
+        Self::new(TB_CLK_FREQ, TB_BAUD)
+    }
+}
+
+impl Uat {
+    pub fn new(clk_freq: u32, baud: u32) -> Self {
+        let count = clk_freq / baud;
+        // $clog2(COUNT)+1
+        let width = (32 - count.saturating_sub(1).leading_zeros()) + 1;
+        Self {
+            count,
+            width,
+            clk_div: (1 << width) - 1,
+            shift: 0,
+            tx: true,
+            ready: false,
+            d_in: None,
+            output: Vec::new(),
+        }
+    }
+
+    /// The `dInReady` line, which firmware sees on bit 9 of the status word.
+    pub fn ready(&self) -> bool {
+        self.ready
+    }
+
+    /// The serial output line.
+    pub fn tx(&self) -> bool {
+        self.tx
+    }
+
+    /// The `clkDiv` register.
+    pub fn clk_div(&self) -> u32 {
+        self.clk_div
+    }
+
+    /// `CNT_W`, the width of `clkDiv`.
+    pub fn clk_div_width(&self) -> u32 {
+        self.width
+    }
+
+    /// The `shift` register: the bits still to go out after `tx`.
+    pub fn shift(&self) -> u32 {
+        self.shift
+    }
+
+    /// Present a byte on `dIn` with `dInValid` high for the current cycle.
+    /// Whether it's taken depends on [`ready`](Self::ready) at the next
+    /// [`tick`](Self::tick), just like in hardware.
+    pub fn write(&mut self, byte: u8) {
+        self.d_in = Some(byte);
+    }
+
+    /// Advance one clock cycle.
+    pub fn tick(&mut self) {
+        let mask = (1 << self.width) - 1;
+        let div_done = self.clk_div & (1 << (self.width - 1)) != 0;
+        let d_in = self.d_in.take();
+
+        if self.shift != 0 {
+            self.ready = false;
+            if div_done {
+                self.clk_div = self.count;
+                self.tx = self.shift & 1 != 0;
+                self.shift >>= 1;
+            } else {
+                self.clk_div = self.clk_div.wrapping_sub(1) & mask;
+            }
+        } else if let (true, Some(byte), true) = (div_done, d_in, self.ready) {
+            // Start bit goes out now, then the byte, then the stop bit.
+            self.ready = false;
+            self.clk_div = self.count;
+            self.tx = false;
+            self.shift = 0x100 | byte as u32;
+            self.output.push(byte);
+        } else if div_done {
+            self.ready = true;
+            self.tx = true;
+        } else {
+            self.ready = false;
+            self.clk_div = self.clk_div.wrapping_sub(1) & mask;
+        }
+    }
+
+    /// Take every byte the UAT has started sending since the last call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cycles per bit with the testbench's `COUNT` of 10: `clkDiv` is loaded
+    /// with `COUNT`, counts down through zero, and the bit moves on once it
+    /// wraps around to a value with its top bit set.
+    const BIT: usize = 12;
+
+    /// A UAT that has come out of reset and is ready for a byte.
+    fn ready_uat() -> Uat {
+        let mut uat = Uat::default();
+        uat.tick();
+        assert!(uat.ready());
+        uat
+    }
+
+    /// Tick until `ready` comes back, recording `tx` on each cycle.
+    fn send(uat: &mut Uat, byte: u8) -> Vec<bool> {
+        uat.write(byte);
+        let mut tx = Vec::new();
+        loop {
+            uat.tick();
+            if uat.ready() {
+                return tx;
+            }
+            tx.push(uat.tx());
+            assert!(tx.len() < 1000, "the UAT never came back ready");
+        }
+    }
+
+    #[test]
+    fn divider_matches_the_testbench() {
+        let uat = Uat::default();
+        assert_eq!(uat.count, 10);
+        assert_eq!(uat.clk_div_width(), 5);
+        assert_eq!(uat.clk_div(), 0b11111);
+    }
+
+    #[test]
+    fn busy_for_ten_bit_times_after_a_write() {
+        let mut uat = ready_uat();
+        let tx = send(&mut uat, 0x55);
+        // Start bit, 8 data bits and the stop bit.
+        assert_eq!(tx.len(), 10 * BIT);
+        assert_eq!(uat.take_output(), [0x55]);
+    }
+
+    #[test]
+    fn sends_start_bit_data_lsb_first_then_stop_bit() {
+        let mut uat = ready_uat();
+        let tx = send(&mut uat, 0b1100_1010);
+        let bits: Vec<bool> = tx.chunks(BIT).map(|bit| bit[0]).collect();
+        for (i, bit) in tx.chunks(BIT).enumerate() {
+            assert!(bit.iter().all(|&b| b == bit[0]), "bit {i} changed mid-bit");
+        }
+        let expected = [
+            false, false, true, false, true, false, false, true, true, true,
+        ];
+        assert_eq!(bits, expected);
+    }
+
+    #[test]
+    fn ignores_a_write_while_busy() {
+        let mut uat = ready_uat();
+        uat.write(b'a');
+        uat.tick();
+        assert!(!uat.ready());
+        uat.write(b'b');
+        uat.tick();
+        while !uat.ready() {
+            uat.tick();
+        }
+        assert_eq!(uat.take_output(), b"a");
+    }
+}
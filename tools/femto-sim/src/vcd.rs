@@ -0,0 +1,521 @@
+//! A VCD waveform laid out like the one `RiscvFemto_tb` dumps.
+//!
+//! The testbench's `$dumpvars` covers `uut`, `uut.pc`, `leds`, `tx`, `uat0`,
+//! and the `regFile0` to `regFile31` wires, so that's the hierarchy here,
+//! with the same names and widths, and the clock at the testbench's 2 time
+//! units per cycle. A GTKWave save file made for the RTL waveform opens this
+//! one too.
+//!
+//! The simulator works an instruction at a time, so each instruction's
+//! cycles are played back afterwards through a register-level copy of the
+//! core, the memory port, the LED latch and the UAT. That's enough for the
+//! registers and ports of `uut` (`pc`, `instr`, `rs1`, `rs2`, `state`, and
+//! the `mem*` signals) and everything in `uat0`. The decode and ALU wires
+//! inside `uut` aren't reproduced.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::bus::{Bus, IO_BIT, UAT_READY_BIT};
+use crate::cpu::{opcode, Cpu, Kind, Retired};
+use crate::uat::Uat;
+
+/// States of the `RiscvFemto` state machine, numbered like its `enum`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    FetchInstr = 0,
+    WaitInstr = 1,
+    FetchRegs = 2,
+    Execute = 3,
+    Load = 4,
+    WaitData = 5,
+    Store = 6,
+}
+
+impl State {
+    /// The states the core goes through for an instruction.
+    fn sequence(kind: Kind) -> &'static [State] {
+        use State::*;
+        match kind {
+            Kind::Load => &[FetchInstr, WaitInstr, FetchRegs, Execute, Load, WaitData],
+            Kind::Store => &[FetchInstr, WaitInstr, FetchRegs, Execute, Store],
+            _ => &[FetchInstr, WaitInstr, FetchRegs, Execute],
+        }
+    }
+}
+
+/// A signal in the dump.
+struct Var {
+    scope: Scope,
+    kind: &'static str,
+    name: String,
+    width: u32,
+    /// Index into the identifier codes. Signals that are the same net in
+    /// different scopes share one.
+    code: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Tb,
+    Uut,
+    Uat,
+}
+
+impl Scope {
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Tb => "RiscvFemto_tb",
+            Scope::Uut => "uut",
+            Scope::Uat => "uat0",
+        }
+    }
+}
+
+// Identifier codes of the nets.
+const CLK: usize = 0;
+const RSTN: usize = 1;
+const TX: usize = 2;
+const LEDS: usize = 3;
+const MEM_IN: usize = 4;
+const MEM_ADDR: usize = 5;
+const MEM_READ: usize = 6;
+const MEM_WSTRB: usize = 7;
+const MEM_WRITE: usize = 8;
+const INSTR: usize = 9;
+const PC: usize = 10;
+const RS1: usize = 11;
+const RS2: usize = 12;
+const STATE: usize = 13;
+const D_IN: usize = 14;
+const D_IN_VALID: usize = 15;
+const D_IN_READY: usize = 16;
+const CLK_DIV: usize = 17;
+const SHIFT: usize = 18;
+const REG_FILE: usize = 19;
+const NETS: usize = REG_FILE + 32;
+
+/// The registers of the testbench, as they stand during one cycle.
+#[derive(Debug, Clone)]
+struct Model {
+    pc: u32,
+    instr: u32,
+    rs1: u32,
+    rs2: u32,
+    regs: [u32; 32],
+    /// `RiscvMem`'s output register, already byte-swapped back.
+    mem_r_data: u32,
+    leds: u8,
+    uat: Uat,
+}
+
+/// The wires worked out from the registers for one cycle.
+struct Wires {
+    mem_addr: u32,
+    mem_read: bool,
+    mem_wstrb: u32,
+    mem_write: u32,
+    mem_in: u32,
+    is_mem: bool,
+    uat_write: bool,
+}
+
+impl Model {
+    fn wires(&self, state: State) -> Wires {
+        let instr = self.instr;
+        let funct3 = (instr >> 12) & 0x7;
+        let is_store = instr & 0x7f == opcode::STORE;
+        let imm_i = ((instr as i32) >> 20) as u32;
+        let imm_s = (((instr as i32) >> 25) << 5) as u32 | ((instr >> 7) & 0x1f);
+        let addr = self.rs1.wrapping_add(if is_store { imm_s } else { imm_i });
+
+        let mem_addr = match state {
+            State::FetchInstr | State::WaitInstr => self.pc,
+            _ => addr,
+        };
+        // Bytes and halfwords go out in every lane they could land in.
+        let lane = |n: u32| (self.rs2 >> (8 * n)) & 0xff;
+        let lane1 = if addr & 1 != 0 { lane(0) } else { lane(1) };
+        let lane2 = if addr & 2 != 0 { lane(0) } else { lane(2) };
+        let lane3 = match addr & 3 {
+            1 | 3 => lane(0),
+            2 => lane(1),
+            _ => lane(3),
+        };
+        let mem_write = lane(0) | lane1 << 8 | lane2 << 16 | lane3 << 24;
+        let store_mask = match funct3 & 0b11 {
+            0b00 => 1 << (addr & 3),
+            0b01 => 0b11 << (addr & 2),
+            _ => 0b1111,
+        };
+        let mem_wstrb = if state == State::Store { store_mask } else { 0 };
+        let is_mem = mem_addr & IO_BIT == 0;
+        let mem_in = if is_mem {
+            self.mem_r_data
+        } else if mem_addr & 0x10 != 0 {
+            (self.uat.ready() as u32) << UAT_READY_BIT
+        } else {
+            0
+        };
+        Wires {
+            mem_addr,
+            mem_read: matches!(state, State::FetchInstr | State::Load),
+            mem_wstrb,
+            mem_write,
+            mem_in,
+            is_mem,
+            uat_write: !is_mem && mem_addr & 0x08 != 0 && mem_wstrb & 1 != 0,
+        }
+    }
+
+    /// The value of every net during a cycle in `state`, by identifier code.
+    fn values(&self, state: State, wires: &Wires) -> [u32; NETS] {
+        let mut values = [0; NETS];
+        values[CLK] = 0;
+        values[RSTN] = 1;
+        values[TX] = self.uat.tx() as u32;
+        values[LEDS] = self.leds as u32;
+        values[MEM_IN] = wires.mem_in;
+        values[MEM_ADDR] = wires.mem_addr;
+        values[MEM_READ] = wires.mem_read as u32;
+        values[MEM_WSTRB] = wires.mem_wstrb;
+        values[MEM_WRITE] = wires.mem_write;
+        values[INSTR] = self.instr;
+        values[PC] = self.pc;
+        values[RS1] = self.rs1;
+        values[RS2] = self.rs2;
+        values[STATE] = state as u32;
+        values[D_IN] = wires.mem_write & 0xff;
+        values[D_IN_VALID] = wires.uat_write as u32;
+        values[D_IN_READY] = self.uat.ready() as u32;
+        values[CLK_DIV] = self.uat.clk_div();
+        values[SHIFT] = self.uat.shift();
+        values[REG_FILE..].copy_from_slice(&self.regs);
+        values
+    }
+
+    /// Clock the registers at the end of a cycle in `state`.
+    fn posedge(&mut self, state: State, wires: &Wires, retired: &Retired, bus: &Bus) {
+        if wires.mem_read && wires.is_mem {
+            self.mem_r_data = match state {
+                State::FetchInstr => retired.instr,
+                _ => bus.peek(wires.mem_addr),
+            };
+        }
+        if !wires.is_mem && wires.mem_addr & 0x04 != 0 && wires.mem_wstrb & 1 != 0 {
+            self.leds = wires.mem_write as u8;
+        }
+        if wires.uat_write {
+            self.uat.write(wires.mem_write as u8);
+        }
+        self.uat.tick();
+        self.uat.take_output();
+
+        let write_back = |regs: &mut [u32; 32]| {
+            if let Some((rd, value)) = retired.rd {
+                regs[rd as usize] = value;
+            }
+        };
+        match state {
+            State::WaitInstr => self.instr = retired.instr,
+            State::FetchRegs => {
+                self.rs1 = self.regs[((self.instr >> 15) & 0x1f) as usize];
+                self.rs2 = self.regs[((self.instr >> 20) & 0x1f) as usize];
+            }
+            State::Execute => {
+                self.pc = retired.next_pc;
+                if !matches!(retired.kind, Kind::Load) {
+                    write_back(&mut self.regs);
+                }
+            }
+            State::WaitData => write_back(&mut self.regs),
+            _ => {}
+        }
+    }
+}
+
+/// Writes the testbench's waveform, a cycle at a time.
+pub struct Vcd {
+    out: Box<dyn Write>,
+    vars: Vec<Var>,
+    model: Model,
+    /// What was last written for each net.
+    last: Option<[u32; NETS]>,
+    cycle: u64,
+    error: Option<io::Error>,
+}
+
+impl fmt::Debug for Vcd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Vcd")
+            .field("model", &self.model)
+            .field("cycle", &self.cycle)
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Vcd {
+    /// Start a waveform for a core and bus that are about to run their first
+    /// instruction.
+    pub fn new(out: impl Write + 'static, cpu: &Cpu, bus: &Bus) -> Self {
+        let model = Model {
+            pc: cpu.pc,
+            instr: 0,
+            rs1: 0,
+            rs2: 0,
+            regs: cpu.regs,
+            mem_r_data: 0,
+            leds: bus.leds,
+            uat: bus.uat.clone(),
+        };
+        let mut vcd = Self {
+            out: Box::new(out),
+            vars: vars(model.uat.clk_div_width()),
+            model,
+            last: None,
+            cycle: 0,
+            error: None,
+        };
+        vcd.write(Self::header);
+        vcd
+    }
+
+    /// Add the cycles of an instruction that has just been executed. `bus`
+    /// is only used to look up what loads read, so it must not have been
+    /// stepped any further.
+    pub fn record(&mut self, retired: &Retired, bus: &Bus) {
+        for &state in State::sequence(retired.kind) {
+            let wires = self.model.wires(state);
+            let values = self.model.values(state, &wires);
+            let cycle = self.cycle;
+            self.write(|vcd, out| vcd.cycle(out, cycle, &values));
+            self.model.posedge(state, &wires, retired, bus);
+            self.cycle += 1;
+        }
+    }
+
+    /// Finish off the last cycle, flush the output, and report the first
+    /// error writing it, if any.
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.cycle > 0 {
+            let end = 2 * self.cycle - 1;
+            self.write(|_, out| writeln!(out, "#{end}\n1{}", code(CLK)));
+        }
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.out.flush(),
+        }
+    }
+
+    /// Run `f` unless a write has already failed, and remember it if it
+    /// fails now.
+    fn write(&mut self, f: impl FnOnce(&mut Self, &mut dyn Write) -> io::Result<()>) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut out = std::mem::replace(&mut self.out, Box::new(io::sink()));
+        if let Err(e) = f(self, &mut *out) {
+            self.error = Some(e);
+        }
+        self.out = out;
+    }
+
+    fn header(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "$version femto-sim $end")?;
+        writeln!(out, "$timescale 1ns $end")?;
+        writeln!(out, "$scope module {} $end", Scope::Tb.name())?;
+        let mut scope = Scope::Tb;
+        for var in &self.vars {
+            if var.scope != scope {
+                if scope != Scope::Tb {
+                    writeln!(out, "$upscope $end")?;
+                }
+                writeln!(out, "$scope module {} $end", var.scope.name())?;
+                scope = var.scope;
+            }
+            let range = match var.width {
+                1 => String::new(),
+                w => format!(" [{}:0]", w - 1),
+            };
+            writeln!(
+                out,
+                "$var {} {} {} {}{range} $end",
+                var.kind,
+                var.width,
+                code(var.code),
+                var.name
+            )?;
+        }
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")
+    }
+
+    /// Write the changes for one cycle. Registers change on the rising edge
+    /// that ends the cycle before, at time `2 * cycle - 1`, and the clock
+    /// falls again halfway through.
+    fn cycle(&mut self, out: &mut dyn Write, cycle: u64, values: &[u32; NETS]) -> io::Result<()> {
+        let last = self.last.replace(*values);
+        if cycle == 0 {
+            writeln!(out, "#0\n$dumpvars")?;
+        } else {
+            writeln!(out, "#{}\n1{}", 2 * cycle - 1, code(CLK))?;
+        }
+        let mut written = [false; NETS];
+        for var in &self.vars {
+            let value = values[var.code];
+            let changed = last.is_none_or(|last| last[var.code] != value);
+            if var.code == CLK || written[var.code] || !changed {
+                continue;
+            }
+            written[var.code] = true;
+            match var.width {
+                1 => writeln!(out, "{value}{}", code(var.code))?,
+                w => writeln!(out, "b{value:0w$b} {}", code(var.code), w = w as usize)?,
+            }
+        }
+        if cycle == 0 {
+            writeln!(out, "0{}\n$end", code(CLK))
+        } else {
+            writeln!(out, "#{}\n0{}", 2 * cycle, code(CLK))
+        }
+    }
+}
+
+/// The signals `RiscvFemto_tb` dumps, in the order it declares them.
+fn vars(clk_div_width: u32) -> Vec<Var> {
+    let var = |scope, kind, name: &str, width, code| Var {
+        scope,
+        kind,
+        name: name.to_string(),
+        width,
+        code,
+    };
+    let mut vars = vec![
+        var(Scope::Tb, "reg", "tx", 1, TX),
+        var(Scope::Tb, "reg", "leds", 8, LEDS),
+    ];
+    for i in 0..32 {
+        vars.push(var(
+            Scope::Tb,
+            "wire",
+            &format!("regFile{i}"),
+            32,
+            REG_FILE + i,
+        ));
+    }
+    vars.extend([
+        var(Scope::Uut, "wire", "clk", 1, CLK),
+        var(Scope::Uut, "wire", "rstn", 1, RSTN),
+        var(Scope::Uut, "wire", "memIn", 32, MEM_IN),
+        var(Scope::Uut, "reg", "memAddr", 32, MEM_ADDR),
+        var(Scope::Uut, "reg", "memRead", 1, MEM_READ),
+        var(Scope::Uut, "reg", "memWstrb", 4, MEM_WSTRB),
+        var(Scope::Uut, "reg", "memWrite", 32, MEM_WRITE),
+        var(Scope::Uut, "reg", "instr", 32, INSTR),
+        var(Scope::Uut, "reg", "pc", 32, PC),
+        var(Scope::Uut, "reg", "rs1", 32, RS1),
+        var(Scope::Uut, "reg", "rs2", 32, RS2),
+        var(Scope::Uut, "reg", "state", 32, STATE),
+        var(Scope::Uat, "wire", "clk", 1, CLK),
+        var(Scope::Uat, "wire", "rstn", 1, RSTN),
+        var(Scope::Uat, "wire", "dIn", 8, D_IN),
+        var(Scope::Uat, "wire", "dInValid", 1, D_IN_VALID),
+        var(Scope::Uat, "reg", "dInReady", 1, D_IN_READY),
+        var(Scope::Uat, "reg", "tx", 1, TX),
+        var(Scope::Uat, "reg", "clkDiv", clk_div_width, CLK_DIV),
+        var(Scope::Uat, "reg", "shift", 9, SHIFT),
+    ]);
+    vars
+}
+
+/// The identifier code of a net: base 94 in the printable characters.
+fn code(mut index: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return code;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::testing::BLESS_VAR;
+    use crate::Sim;
+
+    const GOLDEN: &str = "tests/golden/load-store-addi.vcd";
+
+    /// A writer the test can read back after the [`Vcd`] has taken it.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn load_store_and_alu_match_the_golden_waveform() {
+        let program: [u32; 3] = [
+            0x1000_2583, // lw   a1, 256(zero)
+            0x10b0_2223, // sw   a1, 260(zero)
+            0x0015_8613, // addi a2, a1, 1
+        ];
+        let mut sim = Sim::new(256);
+        let bytes: Vec<u8> = program.iter().flat_map(|w| w.to_le_bytes()).collect();
+        sim.bus.load(0, &bytes);
+        sim.bus.load(0x100, &0x1234_5678u32.to_le_bytes());
+        let out = Shared::default();
+        sim.enable_vcd(out.clone());
+
+        // 6, 5 and 4 cycles.
+        for cycles in [6, 11, 15] {
+            assert_eq!(sim.step(), None);
+            assert_eq!(sim.cycles(), cycles);
+        }
+        assert_eq!(sim.cpu.regs[12], 0x1234_5679);
+        sim.take_vcd().unwrap().finish().unwrap();
+
+        let vcd = String::from_utf8(out.0.take()).unwrap();
+        // Each instruction starts with `state` going back to FETCH_INSTR, on
+        // the rising edge at `2 * cycle - 1`, and the last edge ends cycle 15.
+        let fetch = format!("b{:032b} {}", State::FetchInstr as u32, code(STATE));
+        let mut time = 0;
+        let mut fetches = Vec::new();
+        for line in vcd.lines() {
+            if let Some(t) = line.strip_prefix('#') {
+                time = t.parse().unwrap();
+            } else if line == fetch {
+                fetches.push(time);
+            }
+        }
+        assert_eq!(fetches, [0, 2 * 6 - 1, 2 * 11 - 1]);
+        assert!(vcd.ends_with(&format!("#{}\n1!\n", 2 * 15 - 1)), "{vcd}");
+        if std::env::var_os(BLESS_VAR).is_some_and(|v| v != "0") {
+            std::fs::write(GOLDEN, &vcd).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(GOLDEN).unwrap_or_else(|e| {
+            panic!("couldn't read {GOLDEN}: {e}; run with {BLESS_VAR}=1 to create it")
+        });
+        assert!(
+            vcd == expected,
+            "the waveform doesn't match {GOLDEN}; run with {BLESS_VAR}=1 if the new one is \
+             right\n{vcd}"
+        );
+    }
+}
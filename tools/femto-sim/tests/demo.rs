@@ -0,0 +1,24 @@
+//! The demo firmware in `src/main.rs`, run on the simulator.
+
+use femto_sim::testing::{firmware, FemtoTest};
+
+#[test]
+fn says_hello() {
+    let run = FemtoTest::load(firmware("femto-riscv-demo"))
+        .max_cycles(100_000)
+        .run();
+    run.assert_exit_code(0);
+    run.assert_output_matches_file("tests/golden/femto-riscv-demo.uat");
+    assert_eq!(run.leds(), 0);
+}
+
+#[test]
+fn sets_up_data_and_bss() {
+    let run = FemtoTest::load(firmware("femto-riscv-demo"))
+        .max_cycles(100_000)
+        .run();
+    // `.data` came up with its initial value, and `.bss` zeroed and then
+    // written with the UAT status, which is ready before anything's sent.
+    assert_eq!(run.global("UAT_VAL"), 5);
+    assert_eq!(run.global("femto_riscv_demo::UAT_STAT"), 1 << 9);
+}
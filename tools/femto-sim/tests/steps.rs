@@ -0,0 +1,137 @@
+//! The tutorial's programs from `steps/`, each checked for what the README
+//! says it does.
+
+use femto_elf::Elf;
+use femto_sim::testing::{firmware, FemtoTest, Outcome};
+use femto_sim::{Exit, Sim};
+
+/// What uninitialized registers and RAM hold, near enough.
+const JUNK: u32 = 0xdead_beef;
+
+fn run(bin: &str) -> Outcome {
+    FemtoTest::load(firmware(bin)).max_cycles(10_000).run()
+}
+
+/// Fill every register but `zero` with junk.
+fn scribble_on_registers(sim: &mut Sim, _: &Elf) {
+    sim.cpu.regs[1..].fill(JUNK);
+}
+
+/// Fill `.bss` with junk.
+fn scribble_on_bss(sim: &mut Sim, elf: &Elf) {
+    let start = elf.symbol("_sbss").unwrap().value;
+    let end = elf.symbol("_ebss").unwrap().value;
+    for addr in (start..end).step_by(4) {
+        sim.bus.load(addr, &JUNK.to_le_bytes());
+    }
+}
+
+#[track_caller]
+fn assert_stuck(run: &Outcome) {
+    assert!(
+        matches!(run.exit(), Exit::Stuck { .. }),
+        "expected the program to park in a loop, but it {}",
+        run.exit()
+    );
+}
+
+#[test]
+fn step2_has_no_code() {
+    let data = std::fs::read(firmware("step2")).unwrap();
+    let elf = Elf::parse(&data).unwrap();
+    let code: Vec<_> = elf
+        .sections()
+        .iter()
+        .filter(|s| s.is_code() && s.size > 0)
+        .map(|s| s.name)
+        .collect();
+    assert!(code.is_empty(), "step 2 has code in {code:?}");
+}
+
+#[test]
+fn step3_writes_the_leds_without_a_stack() {
+    let run = run("step3");
+    assert_stuck(&run);
+    // `sp` starts at 0, so the panic handler's first push wraps around to
+    // the top of the address space, which is I/O.
+    assert!(run.sim().min_sp().unwrap() > 0x8000_0000);
+    assert_ne!(run.leds(), 0);
+}
+
+#[test]
+fn step6_has_a_stack() {
+    let run = run("step6");
+    assert_stuck(&run);
+    let top = run.address("_stack_start");
+    assert_eq!(top, 0x400);
+    let min_sp = run.sim().min_sp().unwrap();
+    assert!(
+        (top - 64..top).contains(&min_sp),
+        "sp went down to {min_sp:#x}"
+    );
+    assert_eq!(run.leds(), 0);
+}
+
+#[test]
+fn step7_clears_the_registers() {
+    let run = FemtoTest::load(firmware("step7"))
+        .max_cycles(10_000)
+        .setup(scribble_on_registers)
+        .run();
+    assert_stuck(&run);
+    let regs = &run.sim().cpu.regs;
+    // tp, t0-t2, s1, a0-a7, s2-s11 and t3-t6: everything but ra, sp, gp and
+    // fp, which the code after `_start` is free to use.
+    for (i, &reg) in regs.iter().enumerate().skip(4) {
+        if i != 8 {
+            assert_eq!(reg, 0, "x{i} wasn't cleared");
+        }
+    }
+    // Nothing sets up `gp` until step 9.
+    assert_eq!(regs[3], JUNK);
+}
+
+#[test]
+fn step8_has_data_and_bss() {
+    let run = FemtoTest::load(firmware("step8"))
+        .max_cycles(10_000)
+        .setup(scribble_on_bss)
+        .run();
+    assert_stuck(&run);
+    let data = run.address("_sdata")..run.address("_edata");
+    let bss = run.address("_sbss")..run.address("_ebss");
+    assert!(data.contains(&run.address("UAT_VAL")));
+    assert!(bss.contains(&run.address("UAT_STAT")));
+    assert!(bss.end <= run.address("_stack_start") - 64);
+    // It's only right because the loader put it there.
+    assert_eq!(run.global("UAT_VAL"), 5);
+    // Nothing clears `.bss` yet.
+    assert_eq!(run.global("UAT_STAT"), JUNK & 0xffff);
+}
+
+#[test]
+fn step9_sets_up_globals() {
+    let run = FemtoTest::load(firmware("step9"))
+        .max_cycles(10_000)
+        .setup(|sim, elf| {
+            scribble_on_registers(sim, elf);
+            scribble_on_bss(sim, elf);
+        })
+        .run();
+    assert_stuck(&run);
+    assert_eq!(run.sim().cpu.regs[3], run.address("__global_pointer$"));
+    assert_eq!(run.global("UAT_VAL"), 5);
+    assert_eq!(run.global("UAT_STAT"), 0);
+}
+
+#[test]
+fn step10_runs_main_from_femto_rt() {
+    let run = run("step10");
+    assert_stuck(&run);
+    // femto-rt's `_start` did the setup, and `main` got as far as its loop
+    // without touching the stack.
+    let regs = &run.sim().cpu.regs;
+    assert_eq!(regs[2], run.address("_stack_start"));
+    assert_eq!(regs[3], run.address("__global_pointer$"));
+    assert_eq!(run.leds(), 0);
+}
@@ -0,0 +1,405 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::{Error, Sizes};
+
+/// A symbol's size in the old and new builds.
+type OldNew = (Option<u32>, Option<u32>);
+
+/// How a symbol changed between two builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    Added,
+    Removed,
+    Grown,
+    Shrunk,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Grown => "grown",
+            Change::Shrunk => "shrunk",
+        })
+    }
+}
+
+/// The size of one part of the firmware before and after.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartDelta {
+    /// A part name from [`Part`](crate::Part), or `ROM`, `RAM` or `total`.
+    pub name: String,
+    pub old: u32,
+    pub new: u32,
+}
+
+impl PartDelta {
+    pub fn delta(&self) -> i64 {
+        self.new as i64 - self.old as i64
+    }
+}
+
+/// A symbol that was added, removed, or changed size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolDelta {
+    pub name: String,
+    pub section: String,
+    /// Size in the old build, or `None` if it wasn't there.
+    pub old: Option<u32>,
+    /// Size in the new build, or `None` if it's gone.
+    pub new: Option<u32>,
+}
+
+impl SymbolDelta {
+    pub fn delta(&self) -> i64 {
+        self.new.unwrap_or(0) as i64 - self.old.unwrap_or(0) as i64
+    }
+
+    pub fn change(&self) -> Change {
+        match (self.old, self.new) {
+            (None, _) => Change::Added,
+            (_, None) => Change::Removed,
+            (Some(old), Some(new)) if new > old => Change::Grown,
+            _ => Change::Shrunk,
+        }
+    }
+}
+
+/// What changed in size between two builds of the firmware.
+///
+/// Symbols are matched up by demangled name and section, which leaves out
+/// the hash `rustc` puts on the end, so a function that only moved doesn't
+/// show up. Both lists are sorted by how much they changed, biggest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeDiff {
+    /// Every part and total, changed or not.
+    pub parts: Vec<PartDelta>,
+    /// Only the symbols that changed.
+    pub symbols: Vec<SymbolDelta>,
+}
+
+impl SizeDiff {
+    pub fn new(old: &Sizes, new: &Sizes) -> Self {
+        let mut parts: Vec<PartDelta> = new
+            .parts
+            .iter()
+            .map(|part| PartDelta {
+                name: part.name.to_string(),
+                old: old.get(part.name).unwrap_or(0),
+                new: part.size,
+            })
+            .collect();
+        // Parts only the old build had, like a heap that's been turned off.
+        for part in old.parts.iter().filter(|p| new.get(p.name).is_none()) {
+            parts.push(PartDelta {
+                name: part.name.to_string(),
+                old: part.size,
+                new: 0,
+            });
+        }
+        for (name, old, new) in [
+            ("ROM", old.rom(), new.rom()),
+            ("RAM", old.ram(), new.ram()),
+            ("total", old.total(), new.total()),
+        ] {
+            parts.push(PartDelta {
+                name: name.to_string(),
+                old,
+                new,
+            });
+        }
+
+        let mut sizes: BTreeMap<(&str, &str), OldNew> = BTreeMap::new();
+        for sym in &old.symbols {
+            let entry = sizes
+                .entry((diff_name(&sym.name), &sym.section))
+                .or_default();
+            entry.0 = Some(entry.0.unwrap_or(0) + sym.size);
+        }
+        for sym in &new.symbols {
+            let entry = sizes
+                .entry((diff_name(&sym.name), &sym.section))
+                .or_default();
+            entry.1 = Some(entry.1.unwrap_or(0) + sym.size);
+        }
+        let mut symbols: Vec<SymbolDelta> = sizes
+            .into_iter()
+            .filter(|(_, (old, new))| old != new)
+            .map(|((name, section), (old, new))| SymbolDelta {
+                name: name.to_string(),
+                section: section.to_string(),
+                old,
+                new,
+            })
+            .collect();
+        symbols.sort_by_key(|s| std::cmp::Reverse(s.delta().abs()));
+
+        Self { parts, symbols }
+    }
+
+    /// Compare two ELF files.
+    pub fn between_files(old: impl AsRef<Path>, new: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(&Sizes::from_file(old)?, &Sizes::from_file(new)?))
+    }
+
+    /// The change in a part, or in `ROM`, `RAM` or `total`.
+    pub fn delta(&self, name: &str) -> i64 {
+        self.parts
+            .iter()
+            .find(|p| p.name == name)
+            .map_or(0, PartDelta::delta)
+    }
+
+    /// How many more bytes the ROM image takes.
+    pub fn rom_delta(&self) -> i64 {
+        self.delta("ROM")
+    }
+
+    /// How many more bytes of RAM are used, stack and heap included.
+    pub fn ram_delta(&self) -> i64 {
+        self.delta("RAM")
+    }
+
+    /// True if nothing changed at all.
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty() && self.parts.iter().all(|p| p.delta() == 0)
+    }
+}
+
+/// The name to match a symbol up by. Constants the compiler made up, like
+/// string literals, are called `.Lanon.<hash>.<n>` with numbers that change
+/// from build to build, so they're all lumped together.
+fn diff_name(name: &str) -> &str {
+    if name.starts_with(".Lanon.") {
+        "<anonymous constants>"
+    } else {
+        name
+    }
+}
+
+/// The changed parts and symbols, as a table.
+impl fmt::Display for SizeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no size changes");
+        }
+        let mut parts: Vec<&PartDelta> = self.parts.iter().filter(|p| p.delta() != 0).collect();
+        // Changed sections first, biggest change first, then the totals.
+        parts.sort_by_key(|p| {
+            let total = matches!(p.name.as_str(), "ROM" | "RAM" | "total");
+            (total, std::cmp::Reverse(p.delta().abs()))
+        });
+        writeln!(
+            f,
+            "{:<10} {:>8} {:>8} {:>8}",
+            "section", "old", "new", "delta"
+        )?;
+        for p in parts {
+            writeln!(
+                f,
+                "{:<10} {:>8} {:>8} {:>+8}",
+                p.name,
+                p.old,
+                p.new,
+                p.delta()
+            )?;
+        }
+        if !self.symbols.is_empty() {
+            writeln!(f, "\nsymbols:")?;
+            for s in &self.symbols {
+                let sizes = match (s.old, s.new) {
+                    (Some(old), Some(new)) => format!("  ({old} -> {new})"),
+                    _ => String::new(),
+                };
+                writeln!(
+                    f,
+                    "{:>+8}  {:<8} {:<8} {}{sizes}",
+                    s.delta(),
+                    s.change(),
+                    s.section,
+                    s.name
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Part, SymbolSize};
+
+    fn part(name: &'static str, size: u32, rom: bool, ram: bool) -> Part {
+        Part {
+            name,
+            size,
+            rom,
+            ram,
+        }
+    }
+
+    fn sizes(parts: Vec<Part>, symbols: &[(&str, &str, u32)]) -> Sizes {
+        Sizes {
+            parts,
+            rom_length: None,
+            ram_length: None,
+            shared: true,
+            symbols: symbols
+                .iter()
+                .map(|&(name, section, size)| SymbolSize {
+                    name: name.to_string(),
+                    section: section.to_string(),
+                    size,
+                })
+                .collect(),
+        }
+    }
+
+    fn text(size: u32) -> Vec<Part> {
+        vec![part(".text", size, true, false)]
+    }
+
+    #[test]
+    fn symbols_are_sorted_by_how_much_they_changed() {
+        let old = sizes(
+            text(168),
+            &[
+                ("foo", ".text", 100),
+                ("bar", ".text", 40),
+                ("same", ".text", 20),
+                ("gone", ".rodata", 8),
+            ],
+        );
+        let new = sizes(
+            text(234),
+            &[
+                ("foo", ".text", 120),
+                ("bar", ".text", 30),
+                ("same", ".text", 20),
+                ("new", ".text", 64),
+                // Same name, but not the same symbol.
+                ("gone", ".text", 8),
+            ],
+        );
+        let diff = SizeDiff::new(&old, &new);
+        let changes: Vec<_> = diff
+            .symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.section.as_str(), s.change(), s.delta()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("new", ".text", Change::Added, 64),
+                ("foo", ".text", Change::Grown, 20),
+                ("bar", ".text", Change::Shrunk, -10),
+                ("gone", ".rodata", Change::Removed, -8),
+                ("gone", ".text", Change::Added, 8),
+            ]
+        );
+        assert_eq!(diff.symbols[1].old, Some(100));
+        assert_eq!(diff.symbols[1].new, Some(120));
+        assert_eq!(diff.symbols[3].new, None);
+        assert_eq!(diff.rom_delta(), 66);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn anonymous_constants_are_lumped_together() {
+        let old = sizes(
+            text(0),
+            &[
+                (".Lanon.abc.0", ".rodata", 12),
+                (".Lanon.abc.1", ".rodata", 4),
+            ],
+        );
+        let renumbered = sizes(
+            text(0),
+            &[
+                (".Lanon.def.0", ".rodata", 4),
+                (".Lanon.def.7", ".rodata", 12),
+            ],
+        );
+        assert!(SizeDiff::new(&old, &renumbered).symbols.is_empty());
+
+        let grown = sizes(
+            text(0),
+            &[
+                (".Lanon.def.0", ".rodata", 16),
+                (".Lanon.def.1", ".rodata", 8),
+            ],
+        );
+        let diff = SizeDiff::new(&old, &grown);
+        assert_eq!(
+            diff.symbols,
+            [SymbolDelta {
+                name: "<anonymous constants>".to_string(),
+                section: ".rodata".to_string(),
+                old: Some(16),
+                new: Some(24),
+            }]
+        );
+    }
+
+    #[test]
+    fn parts_only_one_build_has_count_as_zero_in_the_other() {
+        let old = sizes(
+            vec![
+                part(".text", 100, true, false),
+                part(".bss", 16, false, true),
+                part("heap", 1024, false, true),
+            ],
+            &[],
+        );
+        let new = sizes(
+            vec![
+                part(".text", 120, true, false),
+                part(".data", 8, true, true),
+                part(".bss", 16, false, true),
+            ],
+            &[],
+        );
+        let diff = SizeDiff::new(&old, &new);
+        let parts: Vec<_> = diff
+            .parts
+            .iter()
+            .map(|p| (p.name.as_str(), p.old, p.new))
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                (".text", 100, 120),
+                (".data", 0, 8),
+                (".bss", 16, 16),
+                ("heap", 1024, 0),
+                ("ROM", 100, 128),
+                ("RAM", 1040, 24),
+                ("total", 1140, 144),
+            ]
+        );
+        assert_eq!(diff.rom_delta(), 28);
+        assert_eq!(diff.ram_delta(), -1016);
+        assert_eq!(diff.delta("heap"), -1024);
+        assert_eq!(diff.delta("nothing"), 0);
+    }
+
+    #[test]
+    fn the_same_build_is_empty() {
+        let build = sizes(
+            vec![
+                part(".text", 100, true, false),
+                part("stack", 64, false, true),
+            ],
+            &[("main", ".text", 100), (".Lanon.abc.0", ".rodata", 4)],
+        );
+        let diff = SizeDiff::new(&build, &build.clone());
+        assert!(diff.is_empty());
+        assert!(diff.symbols.is_empty());
+        assert_eq!(diff.rom_delta(), 0);
+        assert_eq!(diff.to_string(), "no size changes\n");
+    }
+}
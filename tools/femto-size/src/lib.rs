@@ -0,0 +1,52 @@
+//! Section and symbol sizes of femto-riscv firmware, and how they change
+//! between two builds.
+//!
+//! [`Sizes`] measures one ELF: the `.init`, `.text`, `.rodata`, `.data` and
+//! `.bss` sections, the stack and heap reservations, and every symbol with a
+//! size. [`SizeDiff`] lines two of those up, so a test can check that a
+//! change didn't make the firmware any bigger:
+//!
+//! ```no_run
+//! let diff = femto_size::SizeDiff::between_files("old.elf", "new.elf")?;
+//! assert!(diff.rom_delta() <= 0, "{diff}");
+//! # Ok::<(), femto_size::Error>(())
+//! ```
+
+mod diff;
+mod sizes;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use femto_elf::Elf;
+
+pub use diff::{Change, PartDelta, SizeDiff, SymbolDelta};
+pub use sizes::{Part, Sizes, SymbolSize};
+
+/// Errors from reading an ELF file to measure.
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Elf(PathBuf, femto_elf::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "couldn't read {}: {e}", path.display()),
+            Error::Elf(path, e) => write!(f, "{}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Sizes {
+    /// Measure the ELF file at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|e| Error::Io(path.into(), e))?;
+        let elf = Elf::parse(&data).map_err(|e| Error::Elf(path.into(), e))?;
+        Ok(Self::new(&elf))
+    }
+}
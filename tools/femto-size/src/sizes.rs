@@ -0,0 +1,191 @@
+use femto_elf::{demangle, Elf, SymbolKind};
+
+/// One of the things that takes up room in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    /// `.init`, `.text`, `.rodata`, `.data`, `.bss`, `stack` or `heap`.
+    pub name: &'static str,
+    pub size: u32,
+    /// Takes room in the ROM image.
+    pub rom: bool,
+    /// Takes room in RAM while running.
+    pub ram: bool,
+}
+
+/// A symbol with a size, and the section it's in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolSize {
+    pub name: String,
+    pub section: String,
+    pub size: u32,
+}
+
+/// Sizes of everything in a firmware ELF, and of the memory it goes in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sizes {
+    pub parts: Vec<Part>,
+    /// Length of the ROM and RAM regions, from the `_rom_*` and `_ram_*`
+    /// symbols. `None` if the ELF doesn't have them.
+    pub rom_length: Option<u32>,
+    pub ram_length: Option<u32>,
+    /// True if ROM and RAM are the same region, like the default `BRAM`.
+    pub shared: bool,
+    /// Every symbol with a size in an allocated section, biggest first.
+    pub symbols: Vec<SymbolSize>,
+}
+
+impl Sizes {
+    pub fn new(elf: &Elf) -> Self {
+        let symbol = |name| elf.symbol(name).map(|s| s.value);
+        let section = |name| elf.section(name).map_or(0, |s| s.size);
+
+        // `.init` is inside the `.text` output section, between these two.
+        let init = match (symbol("_sinit"), symbol("_einit")) {
+            (Some(start), Some(end)) => end - start,
+            _ => 0,
+        };
+        let mut parts = vec![
+            Part {
+                name: ".init",
+                size: init,
+                rom: true,
+                ram: false,
+            },
+            Part {
+                name: ".text",
+                size: section(".text").saturating_sub(init),
+                rom: true,
+                ram: false,
+            },
+            Part {
+                name: ".rodata",
+                size: section(".rodata"),
+                rom: true,
+                ram: false,
+            },
+            Part {
+                name: ".data",
+                size: section(".data"),
+                rom: true,
+                ram: true,
+            },
+            Part {
+                name: ".bss",
+                size: section(".bss"),
+                rom: false,
+                ram: true,
+            },
+            Part {
+                name: "stack",
+                size: symbol("_stack_size").unwrap_or(0),
+                rom: false,
+                ram: true,
+            },
+        ];
+        if let Some(heap) = symbol("_heap_size").filter(|&size| size > 0) {
+            parts.push(Part {
+                name: "heap",
+                size: heap,
+                rom: false,
+                ram: true,
+            });
+        }
+
+        let region = |start, end| Some(symbol(end)?.wrapping_sub(symbol(start)?));
+        let shared = symbol("_rom_start") == symbol("_ram_start")
+            && symbol("_rom_end") == symbol("_ram_end");
+
+        let mut symbols: Vec<SymbolSize> = elf
+            .symbols()
+            .iter()
+            .filter(|s| s.size > 0 && matches!(s.kind, SymbolKind::Func | SymbolKind::Object))
+            .filter_map(|s| {
+                let section = elf.sections().get(s.section? as usize)?;
+                section.is_alloc().then(|| SymbolSize {
+                    name: demangle(s.name),
+                    section: section.name.to_string(),
+                    size: s.size,
+                })
+            })
+            .collect();
+        symbols.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        symbols.dedup();
+
+        Self {
+            parts,
+            rom_length: region("_rom_start", "_rom_end"),
+            ram_length: region("_ram_start", "_ram_end"),
+            shared,
+            symbols,
+        }
+    }
+
+    /// Bytes of ROM used: the code, constants and the `.data` image.
+    pub fn rom(&self) -> u32 {
+        self.parts.iter().filter(|p| p.rom).map(|p| p.size).sum()
+    }
+
+    /// Bytes of RAM used, counting the whole stack and heap reservation.
+    pub fn ram(&self) -> u32 {
+        self.parts.iter().filter(|p| p.ram).map(|p| p.size).sum()
+    }
+
+    /// Bytes used of a shared ROM and RAM, with `.data` only counted once.
+    pub fn total(&self) -> u32 {
+        self.parts.iter().map(|p| p.size).sum()
+    }
+
+    /// The size of a part, or of `rom`, `ram` or `total`.
+    pub fn get(&self, name: &str) -> Option<u32> {
+        match name {
+            "rom" => Some(self.rom()),
+            "ram" => Some(self.ram()),
+            "total" => Some(self.total()),
+            _ => self
+                .parts
+                .iter()
+                .find(|p| p.name.trim_start_matches('.') == name.trim_start_matches('.'))
+                .map(|p| p.size),
+        }
+    }
+
+    /// The budgets that apply when none are given: everything has to fit in
+    /// the regions it was linked for.
+    pub fn default_budgets(&self) -> Vec<(String, u32)> {
+        match (self.rom_length, self.ram_length) {
+            (Some(length), _) if self.shared => vec![("total".into(), length)],
+            (rom, ram) => [("rom", rom), ("ram", ram)]
+                .into_iter()
+                .filter_map(|(name, length)| Some((name.to_string(), length?)))
+                .collect(),
+        }
+    }
+
+    /// Print the sizes, followed by the `top` biggest symbols.
+    pub fn print(&self, top: usize) {
+        println!("{:<10} {:>8}", "section", "bytes");
+        for part in &self.parts {
+            println!("{:<10} {:>8}", part.name, part.size);
+        }
+        let used = |name, used, length: Option<u32>| match length {
+            Some(length) if length > 0 => println!(
+                "{name:<10} {used:>8} of {length} ({}%)",
+                used as u64 * 100 / length as u64
+            ),
+            _ => println!("{name:<10} {used:>8}"),
+        };
+        if self.shared {
+            used("total", self.total(), self.rom_length);
+        } else {
+            used("ROM", self.rom(), self.rom_length);
+            used("RAM", self.ram(), self.ram_length);
+        }
+
+        if top > 0 && !self.symbols.is_empty() {
+            println!("\nlargest symbols:");
+            for sym in self.symbols.iter().take(top) {
+                println!("{:>8}  {:<8} {}", sym.size, sym.section, sym.name);
+            }
+        }
+    }
+}
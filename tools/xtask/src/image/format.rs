@@ -0,0 +1,360 @@
+//! The file formats an [`Image`] can be written out as.
+//!
+//! Most FPGA flows want the memory as a list of words. The word width and the
+//! order bytes are packed into each word are both configurable, since
+//! `RiscvMem` byte-swaps its words while a vendor block RAM wired straight to
+//! the core won't.
+
+use std::fmt::Write;
+
+use super::Image;
+
+/// An output format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Hex words for `$readmemh`, one per line.
+    ReadmemhWords,
+    /// Hex bytes for `$readmemh` into a byte-wide memory, one per line.
+    ReadmemhBytes,
+    /// Xilinx coefficient file, for Block Memory Generator cores.
+    Coe,
+    /// Intel/Altera memory initialization file.
+    Mif,
+    /// Intel HEX, with byte addresses, for device programmers.
+    IntelHex,
+    /// The raw bytes.
+    Bin,
+}
+
+/// How bytes are packed into words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    /// The first byte in memory is the most significant byte of the word.
+    /// This is what `xxd -g 4 -ps` prints and what `RiscvMem` expects.
+    Big,
+    /// The first byte in memory is the least significant byte of the word,
+    /// i.e. the word the core would load from that address.
+    Little,
+}
+
+/// Word shape for the word-oriented formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WordLayout {
+    /// Word width in bits. Must be a non-zero multiple of 8.
+    pub width: u32,
+    pub endian: Endian,
+}
+
+impl Default for WordLayout {
+    fn default() -> Self {
+        Self {
+            width: 32,
+            endian: Endian::Big,
+        }
+    }
+}
+
+impl WordLayout {
+    /// Bytes per word.
+    pub fn bytes(&self) -> usize {
+        self.width as usize / 8
+    }
+
+    fn words<'a>(&self, image: &'a Image) -> impl Iterator<Item = String> + 'a {
+        let endian = self.endian;
+        image.bytes.chunks(self.bytes()).map(move |word| {
+            let mut hex = String::with_capacity(word.len() * 2);
+            let mut push = |b: &u8| write!(hex, "{b:02x}").unwrap();
+            match endian {
+                Endian::Big => word.iter().for_each(&mut push),
+                Endian::Little => word.iter().rev().for_each(&mut push),
+            }
+            hex
+        })
+    }
+}
+
+impl Format {
+    pub const ALL: [Format; 6] = [
+        Format::ReadmemhWords,
+        Format::ReadmemhBytes,
+        Format::Coe,
+        Format::Mif,
+        Format::IntelHex,
+        Format::Bin,
+    ];
+
+    /// The name used to pick the format on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::ReadmemhWords => "mem",
+            Format::ReadmemhBytes => "mem-bytes",
+            Format::Coe => "coe",
+            Format::Mif => "mif",
+            Format::IntelHex => "hex",
+            Format::Bin => "bin",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    /// File name the format is written to, next to the `program` ELF.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Format::ReadmemhWords => "program.mem",
+            Format::ReadmemhBytes => "program-bytes.mem",
+            Format::Coe => "program.coe",
+            Format::Mif => "program.mif",
+            Format::IntelHex => "program.hex",
+            Format::Bin => "program.bin",
+        }
+    }
+
+    /// Render an image. The image should already be padded to a whole number
+    /// of words.
+    pub fn render(self, image: &Image, layout: &WordLayout) -> Vec<u8> {
+        match self {
+            Format::ReadmemhWords => readmemh_words(image, layout).into_bytes(),
+            Format::ReadmemhBytes => readmemh_bytes(image).into_bytes(),
+            Format::Coe => coe(image, layout).into_bytes(),
+            Format::Mif => mif(image, layout).into_bytes(),
+            Format::IntelHex => intel_hex(image).into_bytes(),
+            Format::Bin => image.bytes.clone(),
+        }
+    }
+}
+
+fn readmemh_words(image: &Image, layout: &WordLayout) -> String {
+    let mut out = String::new();
+    for word in layout.words(image) {
+        out.push_str(&word);
+        out.push('\n');
+    }
+    out
+}
+
+fn readmemh_bytes(image: &Image) -> String {
+    let mut out = String::with_capacity(image.bytes.len() * 3);
+    for b in &image.bytes {
+        writeln!(out, "{b:02x}").unwrap();
+    }
+    out
+}
+
+fn coe(image: &Image, layout: &WordLayout) -> String {
+    let words: Vec<String> = layout.words(image).collect();
+    let mut out = String::new();
+    out.push_str("memory_initialization_radix=16;\n");
+    out.push_str("memory_initialization_vector=\n");
+    out.push_str(&words.join(",\n"));
+    out.push_str(";\n");
+    out
+}
+
+fn mif(image: &Image, layout: &WordLayout) -> String {
+    let words: Vec<String> = layout.words(image).collect();
+    let addr_digits = format!("{:x}", words.len().saturating_sub(1)).len();
+    let mut out = String::new();
+    writeln!(out, "WIDTH={};", layout.width).unwrap();
+    writeln!(out, "DEPTH={};", words.len()).unwrap();
+    out.push_str("\nADDRESS_RADIX=HEX;\nDATA_RADIX=HEX;\n\nCONTENT BEGIN\n");
+    // Collapse runs of the same word, mostly so the zero padding at the end
+    // is a single line.
+    let mut start = 0;
+    while start < words.len() {
+        let end = start
+            + words[start..]
+                .iter()
+                .take_while(|w| **w == words[start])
+                .count();
+        if end - start == 1 {
+            writeln!(out, "\t{start:0addr_digits$X} : {};", words[start]).unwrap();
+        } else {
+            writeln!(
+                out,
+                "\t[{start:0addr_digits$X}..{:0addr_digits$X}] : {};",
+                end - 1,
+                words[start]
+            )
+            .unwrap();
+        }
+        start = end;
+    }
+    out.push_str("END;\n");
+    out
+}
+
+fn intel_hex(image: &Image) -> String {
+    fn record(out: &mut String, kind: u8, addr: u16, data: &[u8]) {
+        let mut sum = data.len() as u8;
+        sum = sum.wrapping_add((addr >> 8) as u8).wrapping_add(addr as u8);
+        sum = sum.wrapping_add(kind);
+        write!(out, ":{:02X}{addr:04X}{kind:02X}", data.len()).unwrap();
+        for &b in data {
+            write!(out, "{b:02X}").unwrap();
+            sum = sum.wrapping_add(b);
+        }
+        writeln!(out, "{:02X}", sum.wrapping_neg()).unwrap();
+    }
+
+    let mut out = String::new();
+    let mut upper = None;
+    let mut offset = 0;
+    while offset < image.bytes.len() {
+        let addr = image.origin.wrapping_add(offset as u32);
+        // Records can't cross a 64 KiB boundary, so cut them short there.
+        let len = (image.bytes.len() - offset)
+            .min(16)
+            .min(0x1_0000 - (addr & 0xffff) as usize);
+        if upper != Some(addr >> 16) {
+            upper = Some(addr >> 16);
+            record(&mut out, 0x04, 0, &((addr >> 16) as u16).to_be_bytes());
+        }
+        record(
+            &mut out,
+            0x00,
+            addr as u16,
+            &image.bytes[offset..offset + len],
+        );
+        offset += len;
+    }
+    record(&mut out, 0x01, 0, &[]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `li a0, 1` and `j .`, padded to four words.
+    fn image() -> Image {
+        let mut bytes = vec![0x13, 0x05, 0x10, 0x00, 0x6f, 0x00, 0x00, 0x00];
+        bytes.resize(16, 0);
+        Image { origin: 0, bytes }
+    }
+
+    fn render(format: Format, image: &Image, width: u32, endian: Endian) -> String {
+        let bytes = format.render(image, &WordLayout { width, endian });
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn readmemh_words_width_and_endianness() {
+        let image = image();
+        assert_eq!(
+            render(Format::ReadmemhWords, &image, 32, Endian::Big),
+            "13051000\n6f000000\n00000000\n00000000\n"
+        );
+        assert_eq!(
+            render(Format::ReadmemhWords, &image, 32, Endian::Little),
+            "00100513\n0000006f\n00000000\n00000000\n"
+        );
+        assert_eq!(
+            render(Format::ReadmemhWords, &image, 16, Endian::Big),
+            "1305\n1000\n6f00\n0000\n0000\n0000\n0000\n0000\n"
+        );
+        assert_eq!(
+            render(Format::ReadmemhWords, &image, 64, Endian::Little),
+            "0000006f00100513\n0000000000000000\n"
+        );
+    }
+
+    #[test]
+    fn readmemh_bytes_ignores_the_word_layout() {
+        let image = image();
+        let expected = "13\n05\n10\n00\n6f\n00\n00\n00\n00\n00\n00\n00\n00\n00\n00\n00\n";
+        assert_eq!(
+            render(Format::ReadmemhBytes, &image, 32, Endian::Big),
+            expected
+        );
+        assert_eq!(
+            render(Format::ReadmemhBytes, &image, 16, Endian::Little),
+            expected
+        );
+    }
+
+    #[test]
+    fn coe_has_the_radix_and_one_word_per_line() {
+        assert_eq!(
+            render(Format::Coe, &image(), 32, Endian::Big),
+            "memory_initialization_radix=16;\n\
+             memory_initialization_vector=\n\
+             13051000,\n\
+             6f000000,\n\
+             00000000,\n\
+             00000000;\n"
+        );
+    }
+
+    #[test]
+    fn mif_collapses_runs_into_address_ranges() {
+        assert_eq!(
+            render(Format::Mif, &image(), 32, Endian::Little),
+            "WIDTH=32;\n\
+             DEPTH=4;\n\
+             \n\
+             ADDRESS_RADIX=HEX;\n\
+             DATA_RADIX=HEX;\n\
+             \n\
+             CONTENT BEGIN\n\
+             \t0 : 00100513;\n\
+             \t1 : 0000006f;\n\
+             \t[2..3] : 00000000;\n\
+             END;\n"
+        );
+    }
+
+    #[test]
+    fn mif_addresses_are_as_wide_as_the_last_one() {
+        let mut image = image();
+        image.bytes.resize(17 * 4, 0);
+        image.bytes[16 * 4] = 0xff;
+        let mif = render(Format::Mif, &image, 32, Endian::Big);
+        assert!(mif.contains("DEPTH=17;\n"));
+        assert!(mif.contains("\t00 : 13051000;\n\t01 : 6f000000;\n"));
+        assert!(mif.contains("\t[02..0F] : 00000000;\n\t10 : ff000000;\nEND;\n"));
+    }
+
+    #[test]
+    fn intel_hex_records_and_checksums() {
+        assert_eq!(
+            render(Format::IntelHex, &image(), 32, Endian::Big),
+            ":020000040000FA\n\
+             :10000000130510006F000000000000000000000059\n\
+             :00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn intel_hex_starts_a_new_segment_at_64k() {
+        let image = Image {
+            origin: 0xfff8,
+            bytes: (1..=16).collect(),
+        };
+        assert_eq!(
+            render(Format::IntelHex, &image, 32, Endian::Big),
+            ":020000040000FA\n\
+             :08FFF8000102030405060708DD\n\
+             :020000040001F9\n\
+             :08000000090A0B0C0D0E0F1094\n\
+             :00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn bin_is_the_bytes() {
+        let image = image();
+        let layout = WordLayout::default();
+        assert_eq!(Format::Bin.render(&image, &layout), image.bytes);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for format in Format::ALL {
+            assert_eq!(Format::from_name(format.name()), Some(format));
+        }
+        assert_eq!(Format::from_name("srec"), None);
+    }
+}
@@ -0,0 +1,85 @@
+//! Turning a firmware ELF into the memory contents `RiscvMem` loads.
+
+pub mod format;
+
+use femto_elf::{Elf, Segment, SHT_NOBITS};
+use femto_linker_script::Region;
+
+/// The initial contents of a memory, starting at `origin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub origin: u32,
+    pub bytes: Vec<u8>,
+}
+
+impl Image {
+    /// Place every loadable segment of `elf` at its load address, which must
+    /// fall inside `region`. Gaps between segments are zero-filled.
+    ///
+    /// Segments with nothing in the file, like `.bss` and the stack, are
+    /// skipped, so with separate ROM and RAM regions the image only covers
+    /// ROM: the `.data` image lives there too, and `_start` copies it out.
+    pub fn from_elf(elf: &Elf, region: &Region) -> Result<Self, String> {
+        let mut bytes = Vec::new();
+        for segment in elf.load_segments().filter(|s| s.filesz > 0) {
+            if !region.contains(segment.paddr, segment.filesz) {
+                return Err(format!(
+                    "segment at {:#010x}..{:#010x}{} falls outside {} ({:#010x}..{:#010x})",
+                    segment.paddr,
+                    segment.paddr as u64 + segment.filesz as u64,
+                    section_names(elf, segment),
+                    region.name,
+                    region.origin,
+                    region.end(),
+                ));
+            }
+            let start = (segment.paddr - region.origin) as usize;
+            let end = start + segment.filesz as usize;
+            if bytes.len() < end {
+                bytes.resize(end, 0);
+            }
+            bytes[start..end].copy_from_slice(elf.segment_data(segment));
+        }
+        Ok(Self {
+            origin: region.origin,
+            bytes,
+        })
+    }
+
+    /// Zero-fill the image out to a whole number of `word_bytes`-byte words.
+    pub fn pad_to_word(&mut self, word_bytes: usize) {
+        let size = self.bytes.len().div_ceil(word_bytes) * word_bytes;
+        self.bytes.resize(size, 0);
+    }
+
+    /// Zero-fill the image out to `depth` words of `word_bytes` bytes each,
+    /// like a memory declared with that `MEM_DEPTH`.
+    pub fn pad_to_depth(&mut self, depth: usize, word_bytes: usize) -> Result<(), String> {
+        let size = depth * word_bytes;
+        if self.bytes.len() > size {
+            return Err(format!(
+                "image is {} bytes, which doesn't fit in a memory depth of {depth} words",
+                self.bytes.len()
+            ));
+        }
+        self.bytes.resize(size, 0);
+        Ok(())
+    }
+}
+
+/// The allocated sections a segment was built from, for error messages.
+fn section_names(elf: &Elf, segment: &Segment) -> String {
+    let end = segment.offset as u64 + segment.filesz as u64;
+    let names: Vec<&str> = elf
+        .sections()
+        .iter()
+        .filter(|s| s.is_alloc() && s.sh_type != SHT_NOBITS && s.size > 0)
+        .filter(|s| s.offset >= segment.offset && (s.offset as u64) < end)
+        .map(|s| s.name)
+        .collect();
+    if names.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", names.join(", "))
+    }
+}
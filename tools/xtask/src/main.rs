@@ -0,0 +1,542 @@
+//! Build tasks for the firmware. Run with `cargo xtask <TASK>` from anywhere
+//! in the repository.
+
+mod image;
+mod stack;
+mod steps;
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use femto_elf::Elf;
+use femto_linker_script::{parse_number, Region};
+use femto_sim::testing::release_dir;
+use femto_size::{SizeDiff, Sizes};
+
+use crate::image::format::{Endian, Format, WordLayout};
+use crate::image::Image;
+use crate::stack::Analysis;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+const USAGE: &str = "\
+Usage: cargo xtask <TASK> [OPTIONS]
+
+Tasks:
+  image    Build the firmware and write it out as memory images
+  size     Show how much memory each part of the firmware takes
+  size-diff
+           Show what got bigger or smaller between two builds
+  stack    Work out the most stack the firmware can need
+  steps    List the tutorial's steps, and the program that goes with each
+  step     Build the program from one of the tutorial's steps and run it on
+           femto-sim
+
+Options for `image`:
+      --elf <PATH>       Use an already-built ELF instead of running
+                         `cargo build --release`
+      --format <FMT>     Image formats to write, comma-separated or repeated
+                         [default: bin,mem]
+                           mem        $readmemh words     (program.mem)
+                           mem-bytes  $readmemh bytes     (program-bytes.mem)
+                           coe        Xilinx COE          (program.coe)
+                           mif        Intel MIF           (program.mif)
+                           hex        Intel HEX           (program.hex)
+                           bin        raw binary          (program.bin)
+      --width <BITS>     Word width for mem, coe and mif [default: 32]
+      --endian <ORDER>   How bytes are packed into words: `big` puts the first
+                         byte on the left like xxd does, which is what
+                         RiscvMem expects; `little` gives the word the core
+                         would load [default: big]
+      --region <NAME>    Memory region of the linker script the image is for
+                         [default: the region holding .text]
+      --linker-script <PATH>
+                         Linker script to take the regions from [default:
+                         the ROM recorded in the ELF, or else the script
+                         femto-rt's build script generated]
+      --depth <WORDS>    Pad the image out to this many words, like a
+                         MEM_DEPTH [default: the length of the region]
+      --no-pad           Don't pad the image past the end of the program
+      --out-dir <DIR>    Where to write the files [default: the repository root]
+      --no-stack-check   Write the images even if the firmware can need more
+                         stack than it has. The check only runs on ELFs with
+                         `_start_rust` and `_stack_size` symbols
+
+Options for `size`:
+      --elf <PATH>       Measure an already-built ELF instead of running
+                         `cargo build --release`
+      --top <N>          How many of the largest symbols to list [default: 10]
+      --budget <NAME>=<BYTES>
+                         Fail if NAME takes more than BYTES. NAME is a section
+                         (init, text, rodata, data, bss), stack, heap, rom,
+                         ram, or total. Can be given more than once [default:
+                         everything fits in the memory it was linked for]
+
+Arguments for `size-diff`:
+      <OLD>              The ELF to compare against
+      [NEW]              The ELF to compare [default: the result of running
+                         `cargo build --release`]
+
+Options for `stack`:
+      --elf <PATH>       Check an already-built ELF instead of running
+                         `cargo build --release`
+      --root <NAME>      Function to start from [default: _start_rust]
+      --limit <BYTES>    Fail if the worst case is deeper than this
+                         [default: the _stack_size the ELF was linked with]
+
+Arguments for `step`:
+      <N>                The step's number, from `cargo xtask steps`
+      --build-only       Build the program without running it
+      -- <ARGS>...       Pass the rest on to femto-sim
+";
+
+/// Where the stack analysis starts: the function `_start` hands off to.
+const STACK_ROOT: &str = "_start_rust";
+
+/// The firmware binary, in [`release_dir`].
+const FIRMWARE: &str = "femto-riscv-demo";
+/// Where the build scripts' output goes for a release build, in
+/// [`release_dir`].
+const BUILD_OUT: &str = "build";
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let result = match args.next().as_deref() {
+        Some("image") => image(args),
+        Some("size") => size(args),
+        Some("size-diff") => size_diff(args),
+        Some("stack") => stack(args),
+        Some("steps") => {
+            steps::print_list();
+            Ok(())
+        }
+        Some("step") => step(args),
+        Some("-h" | "--help") => {
+            print!("{USAGE}");
+            Ok(())
+        }
+        Some(task) => Err(format!("unknown task `{task}`\n\n{USAGE}").into()),
+        None => Err(USAGE.into()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The top of the repository.
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .ancestors()
+        .nth(2)
+        .unwrap()
+        .to_path_buf()
+}
+
+fn option_value(option: &str, value: Option<String>) -> Result<String> {
+    value.ok_or_else(|| format!("`{option}` needs a value").into())
+}
+
+/// The linker script femto-rt's build script generated for the last release
+/// build. Cargo keeps one output directory per build script configuration,
+/// so take the newest.
+fn generated_linker_script(root: &Path) -> Result<PathBuf> {
+    let build = release_dir(root).join(BUILD_OUT);
+    let entries = std::fs::read_dir(&build).map_err(|e| {
+        format!(
+            "couldn't read {}: {e}; build the firmware with `cargo build --release` first",
+            build.display()
+        )
+    })?;
+    let mut newest = None;
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with("femto-rt-") {
+            continue;
+        }
+        let script = entry.path().join("out/linker.ld");
+        if let Ok(modified) = script.metadata().and_then(|m| m.modified()) {
+            if newest.as_ref().is_none_or(|(t, _)| modified > *t) {
+                newest = Some((modified, script));
+            }
+        }
+    }
+    newest.map(|(_, script)| script).ok_or_else(|| {
+        format!(
+            "no linker script generated by femto-rt in {}",
+            build.display()
+        )
+        .into()
+    })
+}
+
+/// The ROM region from the `_rom_start` and `_rom_end` symbols femto-rt's
+/// linker script puts in the ELF, which describe exactly the layout it was
+/// linked for.
+fn rom_region(elf: &Elf) -> Option<Region> {
+    let start = elf.symbol("_rom_start")?.value;
+    let end = elf.symbol("_rom_end")?.value;
+    Some(Region {
+        name: "REGION_TEXT".into(),
+        origin: start,
+        length: end.wrapping_sub(start),
+    })
+}
+
+/// The region called `name` in the linker script at `path`, or the one
+/// holding `.text` if no name is given.
+fn script_region(path: &Path, name: Option<String>) -> Result<Region> {
+    let script_name = path.display();
+    let script =
+        std::fs::read_to_string(path).map_err(|e| format!("couldn't read {script_name}: {e}"))?;
+    let name = match name {
+        Some(name) => name,
+        None => femto_linker_script::region_alias(&script, "REGION_TEXT")
+            .ok_or_else(|| format!("{script_name} doesn't say which region holds .text"))?,
+    };
+    let region = femto_linker_script::memory_regions(&script)
+        .map_err(|e| format!("{script_name}: {e}"))?
+        .into_iter()
+        .find(|r| r.name == name)
+        .ok_or_else(|| format!("{script_name} doesn't declare a {name} region"))?;
+    Ok(region)
+}
+
+fn cargo_build_release() -> Result<()> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let status = Command::new(cargo)
+        .current_dir(root())
+        .args(["build", "--release"])
+        .status()?;
+    if !status.success() {
+        return Err("`cargo build --release` failed".into());
+    }
+    Ok(())
+}
+
+fn image(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let root = root();
+    let mut elf_path = None;
+    let mut formats = Vec::new();
+    let mut layout = WordLayout::default();
+    let mut region_name = None;
+    let mut script_path = None;
+    let mut depth = None;
+    let mut pad = true;
+    let mut stack_check = true;
+    let mut out_dir = root.clone();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--elf" => elf_path = Some(PathBuf::from(option_value(&arg, args.next())?)),
+            "--format" => {
+                for name in option_value(&arg, args.next())?.split(',') {
+                    let format = Format::from_name(name)
+                        .ok_or_else(|| format!("unknown image format `{name}`"))?;
+                    formats.push(format);
+                }
+            }
+            "--width" => {
+                let value = option_value(&arg, args.next())?;
+                layout.width = match value.parse() {
+                    Ok(width) if width > 0 && width % 8 == 0 => width,
+                    _ => return Err(format!("invalid word width `{value}`").into()),
+                };
+            }
+            "--endian" => {
+                layout.endian = match option_value(&arg, args.next())?.as_str() {
+                    "big" => Endian::Big,
+                    "little" => Endian::Little,
+                    other => return Err(format!("invalid byte order `{other}`").into()),
+                };
+            }
+            "--region" => region_name = Some(option_value(&arg, args.next())?),
+            "--linker-script" => {
+                script_path = Some(PathBuf::from(option_value(&arg, args.next())?))
+            }
+            "--depth" => {
+                let value = option_value(&arg, args.next())?;
+                depth = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid depth `{value}`"))?,
+                );
+            }
+            "--no-pad" => pad = false,
+            "--no-stack-check" => stack_check = false,
+            "--out-dir" => out_dir = PathBuf::from(option_value(&arg, args.next())?),
+            _ => return Err(format!("unexpected argument `{arg}`").into()),
+        }
+    }
+    if formats.is_empty() {
+        formats = vec![Format::Bin, Format::ReadmemhWords];
+    }
+
+    let elf_path = match elf_path {
+        Some(path) => path,
+        None => {
+            cargo_build_release()?;
+            let program = out_dir.join("program");
+            std::fs::copy(release_dir(&root).join(FIRMWARE), &program)?;
+            program
+        }
+    };
+
+    let data = std::fs::read(&elf_path)
+        .map_err(|e| format!("couldn't read {}: {e}", elf_path.display()))?;
+    let elf = Elf::parse(&data).map_err(|e| format!("{}: {e}", elf_path.display()))?;
+    // An ELF linked with femto-rt's script says where its ROM is, which is
+    // more reliable than guessing which build the script came from.
+    let elf_rom = rom_region(&elf).filter(|_| script_path.is_none() && region_name.is_none());
+    let (region, source) = match elf_rom {
+        Some(region) => (region, None),
+        None => {
+            let script_path = match script_path {
+                Some(path) => path,
+                None => generated_linker_script(&root)?,
+            };
+            let region = script_region(&script_path, region_name)?;
+            (region, Some(script_path))
+        }
+    };
+    // Only firmware linked with femto-rt has a root and a stack size to check
+    // against; any other ELF is imaged as it is.
+    let limit = elf.symbol("_stack_size").map(|sym| sym.value);
+    if let (true, Some(limit)) = (stack_check, limit) {
+        let analysis = Analysis::new(&elf);
+        if let Some(root) = analysis.find(STACK_ROOT) {
+            let report = analysis.worst_case(root.addr);
+            if report.depth > limit {
+                stack::print_report(&analysis, &report, STACK_ROOT, limit);
+                return Err(stack_overflow(report.depth, limit).into());
+            }
+        }
+    }
+    let mut image = Image::from_elf(&elf, &region).map_err(|e| match &source {
+        Some(script_path) => format!(
+            "{}: {e}, which is declared in {}",
+            elf_path.display(),
+            script_path.display()
+        ),
+        None => format!("{}: {e}", elf_path.display()),
+    })?;
+    let used = image.bytes.len();
+    if pad {
+        let depth = depth.unwrap_or(region.length as usize / layout.bytes());
+        image.pad_to_depth(depth, layout.bytes())?;
+    } else {
+        image.pad_to_word(layout.bytes());
+    }
+
+    let mut written = Vec::new();
+    for format in formats {
+        std::fs::write(
+            out_dir.join(format.file_name()),
+            format.render(&image, &layout),
+        )?;
+        written.push(format.file_name());
+    }
+    eprintln!(
+        "wrote {}: {used} of {} bytes used",
+        written.join(", "),
+        image.bytes.len()
+    );
+    Ok(())
+}
+
+fn size(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut elf_path = None;
+    let mut top = 10;
+    let mut budgets = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--elf" => elf_path = Some(PathBuf::from(option_value(&arg, args.next())?)),
+            "--top" => {
+                let value = option_value(&arg, args.next())?;
+                top = value
+                    .parse()
+                    .map_err(|_| format!("invalid symbol count `{value}`"))?;
+            }
+            "--budget" => {
+                let value = option_value(&arg, args.next())?;
+                let budget = value
+                    .split_once('=')
+                    .and_then(|(name, bytes)| Some((name.to_string(), parse_number(bytes)?)))
+                    .ok_or_else(|| format!("invalid budget `{value}`, expected NAME=BYTES"))?;
+                budgets.push(budget);
+            }
+            _ => return Err(format!("unexpected argument `{arg}`").into()),
+        }
+    }
+    let elf_path = match elf_path {
+        Some(path) => path,
+        None => {
+            cargo_build_release()?;
+            release_dir(&root()).join(FIRMWARE)
+        }
+    };
+
+    let data = std::fs::read(&elf_path)
+        .map_err(|e| format!("couldn't read {}: {e}", elf_path.display()))?;
+    let elf = Elf::parse(&data).map_err(|e| format!("{}: {e}", elf_path.display()))?;
+    let sizes = Sizes::new(&elf);
+    sizes.print(top);
+
+    if budgets.is_empty() {
+        budgets = sizes.default_budgets();
+    }
+    let mut over = 0;
+    for (name, budget) in &budgets {
+        let size = sizes
+            .get(name)
+            .ok_or_else(|| format!("unknown budget `{name}`"))?;
+        if size > *budget {
+            eprintln!("error: {name} is {size} bytes, over its budget of {budget}");
+            over += 1;
+        }
+    }
+    match over {
+        0 => Ok(()),
+        1 => Err("1 size budget exceeded".into()),
+        n => Err(format!("{n} size budgets exceeded").into()),
+    }
+}
+
+fn size_diff(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut paths = Vec::new();
+    for arg in args {
+        if arg.starts_with('-') {
+            return Err(format!("unexpected argument `{arg}`").into());
+        }
+        paths.push(PathBuf::from(arg));
+    }
+    let (old, new) = match paths.as_slice() {
+        [old, new] => (old.clone(), new.clone()),
+        [old] => {
+            cargo_build_release()?;
+            (old.clone(), release_dir(&root()).join(FIRMWARE))
+        }
+        _ => return Err("`size-diff` takes one or two ELF files".into()),
+    };
+    print!("{}", SizeDiff::between_files(old, new)?);
+    Ok(())
+}
+
+fn stack(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut elf_path = None;
+    let mut root_name = STACK_ROOT.to_string();
+    let mut limit = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--elf" => elf_path = Some(PathBuf::from(option_value(&arg, args.next())?)),
+            "--root" => root_name = option_value(&arg, args.next())?,
+            "--limit" => {
+                let value = option_value(&arg, args.next())?;
+                limit = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid stack limit `{value}`"))?,
+                );
+            }
+            _ => return Err(format!("unexpected argument `{arg}`").into()),
+        }
+    }
+    let elf_path = match elf_path {
+        Some(path) => path,
+        None => {
+            cargo_build_release()?;
+            release_dir(&root()).join(FIRMWARE)
+        }
+    };
+
+    let data = std::fs::read(&elf_path)
+        .map_err(|e| format!("couldn't read {}: {e}", elf_path.display()))?;
+    let elf = Elf::parse(&data).map_err(|e| format!("{}: {e}", elf_path.display()))?;
+    let analysis = Analysis::new(&elf);
+    let report = stack_report(&analysis, &root_name)?;
+    let limit = stack_limit(&elf, limit)?;
+    stack::print_report(&analysis, &report, &root_name, limit);
+    if report.depth > limit {
+        return Err(stack_overflow(report.depth, limit).into());
+    }
+    Ok(())
+}
+
+fn stack_report(analysis: &Analysis, root: &str) -> Result<stack::Report> {
+    let root = analysis
+        .find(root)
+        .ok_or_else(|| format!("there's no function called `{root}` in the firmware"))?;
+    Ok(analysis.worst_case(root.addr))
+}
+
+/// `limit`, or else the stack size the ELF was linked with.
+fn stack_limit(elf: &Elf, limit: Option<u32>) -> Result<u32> {
+    match limit {
+        Some(limit) => Ok(limit),
+        None => Ok(elf
+            .symbol("_stack_size")
+            .ok_or("the firmware has no _stack_size symbol; give a --limit")?
+            .value),
+    }
+}
+
+fn stack_overflow(depth: u32, limit: u32) -> String {
+    format!("the firmware can need {depth} bytes of stack, but only has {limit}")
+}
+
+fn step(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut number = None;
+    let mut build_only = false;
+    let mut sim_args = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--build-only" => build_only = true,
+            "--" => sim_args.extend(args.by_ref()),
+            _ if number.is_none() => {
+                number = Some(
+                    arg.parse()
+                        .map_err(|_| format!("invalid step number `{arg}`"))?,
+                )
+            }
+            _ => return Err(format!("unexpected argument `{arg}`").into()),
+        }
+    }
+    let number = number.ok_or("which step? `cargo xtask steps` lists them")?;
+    let step = steps::find(number)
+        .ok_or_else(|| format!("there's no step {number}; `cargo xtask steps` lists them"))?;
+    let Some((package, bin)) = step.program else {
+        println!("step {number} doesn't have a program yet");
+        return Ok(());
+    };
+
+    // `cargo run` goes through femto-sim, the runner in .cargo/config.toml.
+    let run = !build_only && step.runs;
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let mut command = Command::new(cargo);
+    command
+        .current_dir(root())
+        .args([if run { "run" } else { "build" }, "--release"])
+        .args(["--package", package, "--bin", bin]);
+    if run {
+        command.arg("--").args(&sim_args);
+    }
+    let status = command.status()?;
+    // Every step's program ends in a `loop {}`, so for them getting stuck is
+    // the right ending.
+    let parked = run && status.code() == Some(femto_sim::STUCK_EXIT_CODE.into());
+    if !status.success() && !parked {
+        let what = if run { "running" } else { "building" };
+        return Err(format!("{what} step {number} ({bin}) failed").into());
+    }
+    if !run {
+        let elf = release_dir(&root()).join(bin);
+        if !step.runs && !build_only {
+            println!("step {number} has no code to run");
+        }
+        println!("built {}", elf.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,547 @@
+//! Worst-case stack depth, worked out from the machine code.
+//!
+//! Every function is scanned for the `addi sp, sp, -N` that sets up its
+//! frame and for the calls it makes, `jal` to a fixed target and `jalr`
+//! through an address built with `auipc` or `lui` right before it. The
+//! deepest path through that call graph is the most stack the program can
+//! need, as long as nothing got in the way:
+//!
+//! - A function that can end up calling itself makes the depth unbounded.
+//!   The path only goes around the loop once.
+//! - A call through a function pointer or vtable can't be followed.
+//! - A function that moves `sp` by an amount in a register (`alloca`, or a
+//!   frame too big for one `addi`) has a frame of unknown size.
+//!
+//! Any of these is reported, and the depth is then only a lower bound.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use femto_disasm::{Instr, Op};
+use femto_elf::{demangle, Elf, SymbolMap};
+
+const RA: u8 = 1;
+const SP: u8 = 2;
+const T0: u8 = 5;
+const FP: u8 = 8;
+
+/// A call from one function to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Call {
+    /// Address of the calling instruction.
+    pub site: u32,
+    /// Start of the function called.
+    pub target: u32,
+    /// A tail call, made after the caller has popped its frame.
+    pub tail: bool,
+}
+
+/// What one function does to the stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub addr: u32,
+    /// Bytes the function takes off `sp` for its own frame.
+    pub frame: u32,
+    pub calls: Vec<Call>,
+    /// Calls and jumps through registers that couldn't be resolved, by
+    /// address of the `jalr`.
+    pub indirect: Vec<u32>,
+    /// Address of an instruction that moves `sp` by an unknown amount.
+    pub dynamic: Option<u32>,
+}
+
+/// The result of [`Analysis::worst_case`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The most stack the root can use, in bytes.
+    pub depth: u32,
+    /// The functions along the deepest path, with the stack each one adds.
+    pub path: Vec<(u32, u32)>,
+    /// Calls that close a loop in the call graph, as (caller, callee).
+    pub recursion: Vec<(u32, u32)>,
+    /// Reachable functions with indirect calls, and where they make them.
+    pub indirect: Vec<(u32, u32)>,
+    /// Reachable functions with a frame of unknown size.
+    pub dynamic: Vec<u32>,
+    /// Calls to addresses that aren't in any function, as (site, target).
+    pub unknown_targets: Vec<(u32, u32)>,
+}
+
+impl Report {
+    /// True if nothing got in the way, so [`depth`](Self::depth) is exact
+    /// rather than a lower bound.
+    pub fn is_complete(&self) -> bool {
+        self.recursion.is_empty()
+            && self.indirect.is_empty()
+            && self.dynamic.is_empty()
+            && self.unknown_targets.is_empty()
+    }
+}
+
+/// The frames and calls of every function in an ELF.
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    functions: BTreeMap<u32, Function>,
+    /// Calls whose target isn't the start of a known function.
+    unknown_targets: HashMap<u32, Vec<(u32, u32)>>,
+}
+
+impl Analysis {
+    pub fn new(elf: &Elf) -> Self {
+        let symbols = SymbolMap::new(elf);
+        let syms = symbols.symbols();
+        let mut functions = Vec::new();
+
+        for (i, sym) in syms.iter().enumerate() {
+            let Some(section) = elf
+                .sections()
+                .iter()
+                .find(|s| s.is_code() && sym.value >= s.addr && sym.value < s.addr + s.size)
+            else {
+                continue;
+            };
+            let section_end = section.addr + section.size;
+            // Symbols without a size run up to the next one.
+            let end = if sym.size != 0 {
+                sym.value + sym.size
+            } else {
+                syms.get(i + 1).map_or(section_end, |next| next.value)
+            }
+            .min(section_end);
+            let data = elf.section_data(section);
+            let code = &data[(sym.value - section.addr) as usize..(end - section.addr) as usize];
+            functions.push(scan(&demangle(sym.name), sym.value, end, code));
+        }
+        Self::from_functions(functions)
+    }
+
+    /// Put scanned functions together into a call graph.
+    fn from_functions(functions: Vec<Function>) -> Self {
+        let mut analysis = Self {
+            functions: functions.into_iter().map(|f| (f.addr, f)).collect(),
+            unknown_targets: HashMap::new(),
+        };
+        // Point every call at the function it lands in, or note it as unknown.
+        let starts: Vec<u32> = analysis.functions.keys().copied().collect();
+        for function in analysis.functions.values_mut() {
+            let mut unknown = Vec::new();
+            function.calls.retain(|call| {
+                if starts.binary_search(&call.target).is_ok() {
+                    true
+                } else {
+                    unknown.push((call.site, call.target));
+                    false
+                }
+            });
+            if !unknown.is_empty() {
+                analysis.unknown_targets.insert(function.addr, unknown);
+            }
+        }
+        analysis
+    }
+
+    /// The function starting at `addr`.
+    pub fn function(&self, addr: u32) -> Option<&Function> {
+        self.functions.get(&addr)
+    }
+
+    /// The first function called `name`, demangled.
+    pub fn find(&self, name: &str) -> Option<&Function> {
+        self.functions.values().find(|f| f.name == name)
+    }
+
+    /// Work out the deepest the stack can get below where it was when
+    /// `root` was called.
+    pub fn worst_case(&self, root: u32) -> Report {
+        let mut walk = Walk {
+            analysis: self,
+            done: HashMap::new(),
+            active: HashSet::new(),
+            recursion: Vec::new(),
+        };
+        let depth = walk.depth(root);
+
+        // Follow the deepest callee down from the root.
+        let mut path = Vec::new();
+        let mut at = Some(root);
+        while let Some(addr) = at {
+            let Some(&(total, next)) = walk.done.get(&addr) else {
+                break;
+            };
+            let below = next.and_then(|n| walk.done.get(&n)).map_or(0, |&(d, _)| d);
+            path.push((addr, total - below));
+            // Stop if the path loops back on itself.
+            at = next.filter(|n| !path.iter().any(|&(a, _)| a == *n));
+        }
+
+        let mut reached: Vec<u32> = walk.done.keys().copied().collect();
+        reached.sort();
+        let mut report = Report {
+            depth,
+            path,
+            recursion: walk.recursion,
+            indirect: Vec::new(),
+            dynamic: Vec::new(),
+            unknown_targets: Vec::new(),
+        };
+        for addr in reached {
+            let Some(f) = self.functions.get(&addr) else {
+                continue;
+            };
+            report
+                .indirect
+                .extend(f.indirect.iter().map(|&site| (addr, site)));
+            report.dynamic.extend(f.dynamic.map(|_| addr));
+            if let Some(unknown) = self.unknown_targets.get(&addr) {
+                report.unknown_targets.extend(unknown);
+            }
+        }
+        report
+    }
+}
+
+/// A depth-first walk of the call graph, remembering each function's worst
+/// case and which callee it comes from.
+struct Walk<'a> {
+    analysis: &'a Analysis,
+    done: HashMap<u32, (u32, Option<u32>)>,
+    active: HashSet<u32>,
+    recursion: Vec<(u32, u32)>,
+}
+
+impl Walk<'_> {
+    fn depth(&mut self, addr: u32) -> u32 {
+        if let Some(&(depth, _)) = self.done.get(&addr) {
+            return depth;
+        }
+        let Some(function) = self.analysis.functions.get(&addr) else {
+            return 0;
+        };
+        self.active.insert(addr);
+        let mut worst = (function.frame, None);
+        for call in &function.calls {
+            if self.active.contains(&call.target) {
+                self.recursion.push((addr, call.target));
+                continue;
+            }
+            let below = self.depth(call.target);
+            let depth = if call.tail {
+                below
+            } else {
+                function.frame + below
+            };
+            if depth > worst.0 {
+                worst = (depth, Some(call.target));
+            }
+        }
+        self.active.remove(&addr);
+        self.done.insert(addr, worst);
+        worst.0
+    }
+}
+
+/// Find the frame size and calls of the function at `start..end`.
+fn scan(name: &str, start: u32, end: u32, code: &[u8]) -> Function {
+    let mut function = Function {
+        name: name.to_string(),
+        addr: start,
+        frame: 0,
+        calls: Vec::new(),
+        indirect: Vec::new(),
+        dynamic: None,
+    };
+    let mut prev: Option<Instr> = None;
+    for (i, word) in code.chunks_exact(4).enumerate() {
+        let pc = start + 4 * i as u32;
+        let Some(instr) = Instr::decode(u32::from_le_bytes(word.try_into().unwrap())) else {
+            prev = None;
+            continue;
+        };
+        let Instr { rd, rs1, imm, .. } = instr;
+
+        match instr.op {
+            Op::Jal => {
+                let target = pc.wrapping_add(imm as u32);
+                let outside = target < start || target >= end;
+                if rd == RA || rd == T0 {
+                    function.calls.push(Call {
+                        site: pc,
+                        target,
+                        tail: false,
+                    });
+                } else if rd == 0 && outside {
+                    function.calls.push(Call {
+                        site: pc,
+                        target,
+                        tail: true,
+                    });
+                }
+            }
+            Op::Jalr => {
+                // `call` and `tail` put the upper bits in rs1 with `auipc`
+                // right before the `jalr`.
+                let base = prev.and_then(|p| match p.op {
+                    Op::Auipc if p.rd == rs1 => Some((pc - 4).wrapping_add(p.imm as u32)),
+                    Op::Lui if p.rd == rs1 => Some(p.imm as u32),
+                    _ => None,
+                });
+                match base {
+                    Some(base) => {
+                        let target = base.wrapping_add(imm as u32);
+                        if rd != 0 {
+                            function.calls.push(Call {
+                                site: pc,
+                                target,
+                                tail: false,
+                            });
+                        } else if target < start || target >= end {
+                            function.calls.push(Call {
+                                site: pc,
+                                target,
+                                tail: true,
+                            });
+                        }
+                    }
+                    // `ret`
+                    None if rd == 0 && rs1 == RA && imm == 0 => {}
+                    None => function.indirect.push(pc),
+                }
+            }
+            Op::Addi if rd == SP && rs1 == SP && imm < 0 => {
+                function.frame += imm.unsigned_abs();
+            }
+            // Popping the frame, or restoring `sp` from the frame pointer,
+            // which puts it back where it started.
+            Op::Addi if rd == SP && (rs1 == SP || rs1 == FP) => {}
+            op if rd == SP && op.writes_rd() => {
+                function.dynamic.get_or_insert(pc);
+            }
+            _ => {}
+        }
+        prev = Some(instr);
+    }
+    function
+}
+
+/// Print what [`Analysis::worst_case`] found, for a stack of `limit` bytes.
+pub fn print_report(analysis: &Analysis, report: &Report, root: &str, limit: u32) {
+    let bound = if report.is_complete() {
+        ""
+    } else {
+        "at least "
+    };
+    println!(
+        "worst-case stack depth from {root}: {bound}{} bytes ({limit} reserved)",
+        report.depth
+    );
+    let name = |addr: u32| {
+        analysis
+            .function(addr)
+            .map_or_else(|| format!("{addr:#010x}"), |f| f.name.clone())
+    };
+    for &(addr, bytes) in &report.path {
+        println!("{bytes:>8}  {}", name(addr));
+    }
+    for &(caller, callee) in &report.recursion {
+        println!(
+            "warning: recursion: {} calls {}, which is already on the stack",
+            name(caller),
+            name(callee)
+        );
+    }
+    for &(addr, site) in &report.indirect {
+        println!(
+            "warning: can't follow the indirect call at {site:#010x} in {}",
+            name(addr)
+        );
+    }
+    for &addr in &report.dynamic {
+        let site = analysis
+            .function(addr)
+            .and_then(|f| f.dynamic)
+            .unwrap_or(addr);
+        println!(
+            "warning: {} moves sp by an unknown amount at {site:#010x}",
+            name(addr)
+        );
+    }
+    for &(site, target) in &report.unknown_targets {
+        println!(
+            "warning: the call at {site:#010x} goes to {target:#010x}, which isn't a function"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T1: u8 = 6;
+    const S0: u8 = 8;
+    const A0: u8 = 10;
+    const A5: u8 = 15;
+    const RET: u32 = 0x0000_8067;
+
+    fn i_type(opcode: u32, rd: u8, rs1: u8, imm: i32) -> u32 {
+        (imm as u32 & 0xfff) << 20 | (rs1 as u32) << 15 | (rd as u32) << 7 | opcode
+    }
+
+    fn addi(rd: u8, rs1: u8, imm: i32) -> u32 {
+        i_type(0x13, rd, rs1, imm)
+    }
+
+    fn jalr(rd: u8, rs1: u8, imm: i32) -> u32 {
+        i_type(0x67, rd, rs1, imm)
+    }
+
+    fn auipc(rd: u8, imm: i32) -> u32 {
+        imm as u32 & 0xffff_f000 | (rd as u32) << 7 | 0x17
+    }
+
+    fn jal(rd: u8, offset: i32) -> u32 {
+        let o = offset as u32;
+        (o >> 20 & 1) << 31
+            | (o >> 1 & 0x3ff) << 21
+            | (o >> 11 & 1) << 20
+            | (o >> 12 & 0xff) << 12
+            | (rd as u32) << 7
+            | 0x6f
+    }
+
+    fn sub(rd: u8, rs1: u8, rs2: u8) -> u32 {
+        0x4000_0000 | (rs2 as u32) << 20 | (rs1 as u32) << 15 | (rd as u32) << 7 | 0x33
+    }
+
+    /// Scan `words` as if they were a function at `addr`.
+    fn function(name: &str, addr: u32, words: &[u32]) -> Function {
+        let code: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        scan(name, addr, addr + code.len() as u32, &code)
+    }
+
+    fn call(site: u32, target: u32, tail: bool) -> Call {
+        Call { site, target, tail }
+    }
+
+    #[test]
+    fn frame_is_what_the_function_takes_off_sp() {
+        let f = function(
+            "f",
+            0x100,
+            &[
+                addi(SP, SP, -16),
+                addi(SP, SP, -32),
+                addi(S0, SP, 48),
+                addi(SP, S0, -48),
+                addi(SP, SP, 48),
+                RET,
+            ],
+        );
+        assert_eq!(f.frame, 48);
+        assert_eq!(f.dynamic, None);
+        assert!(f.calls.is_empty());
+        assert!(f.indirect.is_empty());
+
+        let f = function("f", 0x100, &[addi(SP, SP, -16), sub(SP, SP, A0), RET]);
+        assert_eq!(f.frame, 16);
+        assert_eq!(f.dynamic, Some(0x104));
+    }
+
+    #[test]
+    fn calls_through_jal_and_auipc_jalr() {
+        let f = function(
+            "f",
+            0x100,
+            &[
+                addi(SP, SP, -16),
+                jal(RA, 0x100),
+                auipc(RA, 0x1000),
+                jalr(RA, RA, -8),
+                jal(0, -4),
+                addi(SP, SP, 16),
+                auipc(T1, 0),
+                jalr(0, T1, 0x100),
+            ],
+        );
+        assert_eq!(
+            f.calls,
+            [
+                call(0x104, 0x204, false),
+                call(0x10c, 0x1100, false),
+                call(0x11c, 0x218, true),
+            ]
+        );
+        assert!(f.indirect.is_empty());
+
+        let f = function("f", 0x100, &[jal(T0, 0x40), jal(0, 0x80)]);
+        assert_eq!(
+            f.calls,
+            [call(0x100, 0x140, false), call(0x104, 0x184, true)]
+        );
+    }
+
+    #[test]
+    fn calls_through_a_register_are_indirect() {
+        let f = function("f", 0x100, &[jalr(RA, A5, 0), RET, jalr(0, A5, 0)]);
+        assert!(f.calls.is_empty());
+        assert_eq!(f.indirect, [0x100, 0x108]);
+    }
+
+    #[test]
+    fn worst_case_follows_the_deepest_call() {
+        let analysis = Analysis::from_functions(vec![
+            function(
+                "main",
+                0x100,
+                &[addi(SP, SP, -16), jal(RA, 0xfc), jal(RA, 0x1f8), RET],
+            ),
+            function("a", 0x200, &[addi(SP, SP, -32), addi(SP, SP, 32), RET]),
+            function(
+                "b",
+                0x300,
+                &[addi(SP, SP, -8), addi(SP, SP, 8), jal(0, 0xf8)],
+            ),
+            function("c", 0x400, &[addi(SP, SP, -48), addi(SP, SP, 48), RET]),
+        ]);
+        assert_eq!(analysis.find("b").map(|f| f.addr), Some(0x300));
+
+        // `b` tail-calls `c`, so its frame is gone by the time `c` runs.
+        let report = analysis.worst_case(0x100);
+        assert_eq!(report.depth, 64);
+        assert_eq!(report.path, [(0x100, 16), (0x300, 0), (0x400, 48)]);
+        assert!(report.is_complete());
+
+        assert_eq!(analysis.worst_case(0x200).depth, 32);
+    }
+
+    #[test]
+    fn recursion_is_reported() {
+        let analysis = Analysis::from_functions(vec![
+            function("a", 0x200, &[addi(SP, SP, -16), jal(RA, 0xfc), RET]),
+            function("b", 0x300, &[addi(SP, SP, -16), jal(RA, -0x104), RET]),
+        ]);
+        let report = analysis.worst_case(0x200);
+        assert_eq!(report.depth, 32);
+        assert_eq!(report.recursion, [(0x300, 0x200)]);
+        assert!(!report.is_complete());
+    }
+
+    #[test]
+    fn what_it_cant_see_through_is_reported() {
+        let analysis = Analysis::from_functions(vec![
+            function(
+                "main",
+                0x100,
+                &[addi(SP, SP, -16), jal(RA, 0xfc), jal(RA, 0x17c), RET],
+            ),
+            function("a", 0x200, &[jalr(RA, A5, 0), sub(SP, SP, A0), RET]),
+            // Never called, so it doesn't count.
+            function("unused", 0x300, &[jalr(RA, A5, 0)]),
+        ]);
+        let report = analysis.worst_case(0x100);
+        assert_eq!(report.depth, 16);
+        assert_eq!(report.indirect, [(0x200, 0x200)]);
+        assert_eq!(report.dynamic, [0x200]);
+        assert_eq!(report.unknown_targets, [(0x108, 0x284)]);
+        assert!(!report.is_complete());
+    }
+}
@@ -0,0 +1,94 @@
+//! The README's steps, and the programs in `steps/` that go with them.
+
+/// A step of the tutorial.
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    pub number: u32,
+    pub title: &'static str,
+    /// The package and binary with the program as it stands at the end of
+    /// the step, or `None` before there is one.
+    pub program: Option<(&'static str, &'static str)>,
+    /// False for a program that builds but has nothing in it to run.
+    pub runs: bool,
+}
+
+const STEPS_PACKAGE: &str = "femto-steps";
+
+/// Every step, in order. Steps that don't change the program share the one
+/// from the step before.
+pub const STEPS: &[Step] = &[
+    Step {
+        number: 1,
+        title: "Setup for cross-compilation",
+        program: None,
+        runs: false,
+    },
+    Step {
+        number: 2,
+        title: "Make Compilation Succeed",
+        program: Some((STEPS_PACKAGE, "step2")),
+        runs: false,
+    },
+    Step {
+        number: 3,
+        title: "Adding a Linker Script",
+        program: Some((STEPS_PACKAGE, "step3")),
+        runs: true,
+    },
+    Step {
+        number: 4,
+        title: "Loading the program",
+        program: Some((STEPS_PACKAGE, "step3")),
+        runs: true,
+    },
+    Step {
+        number: 5,
+        title: "Shrinking Code Size",
+        program: Some((STEPS_PACKAGE, "step3")),
+        runs: true,
+    },
+    Step {
+        number: 6,
+        title: "Adding a Stack",
+        program: Some((STEPS_PACKAGE, "step6")),
+        runs: true,
+    },
+    Step {
+        number: 7,
+        title: "Initializing the other registers",
+        program: Some((STEPS_PACKAGE, "step7")),
+        runs: true,
+    },
+    Step {
+        number: 8,
+        title: "More linker script sections - `.rodata`, `.data`, `.bss`",
+        program: Some((STEPS_PACKAGE, "step8")),
+        runs: true,
+    },
+    Step {
+        number: 9,
+        title: "Global Variable Setup",
+        program: Some((STEPS_PACKAGE, "step9")),
+        runs: true,
+    },
+    Step {
+        number: 10,
+        title: "Packaging the Runtime",
+        program: Some(("femto-step10", "step10")),
+        runs: true,
+    },
+];
+
+/// The step numbered `number`.
+pub fn find(number: u32) -> Option<&'static Step> {
+    STEPS.iter().find(|step| step.number == number)
+}
+
+/// Print the steps, and which program each one builds.
+pub fn print_list() {
+    println!("{:>4}  {:<8}  title", "step", "program");
+    for step in STEPS {
+        let program = step.program.map_or("-", |(_, bin)| bin);
+        println!("{:>4}  {program:<8}  {}", step.number, step.title);
+    }
+}
@@ -58,6 +58,16 @@ always @(posedge clk) begin
     end
 end
 
+// The core stops advancing pc on a SYSTEM instruction, which is how
+// femto_rt::exit ends the program. Finish there instead of running out the
+// clock, and report the exit code it left in a0.
+always @(posedge clk) begin
+    if (uut.state == uut.ST_EXECUTE && uut.isSystem) begin
+        $display("\nRiscvFemto halted at pc %h, exit code %0d", uut.pc, uut.regFile[10]);
+        $finish;
+    end
+end
+
 wire [31:0] regFile0  = uut.regFile[ 0];
 wire [31:0] regFile1  = uut.regFile[ 1];
 wire [31:0] regFile2  = uut.regFile[ 2];